@@ -0,0 +1,14 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generate the gRPC messages and services of `proto/attestation.proto`
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path().expect("vendored protoc"));
+    tonic_build::configure()
+        .compile_protos_with_config(config, &["proto/attestation.proto"], &["proto"])
+        .expect("compile proto/attestation.proto");
+}
@@ -24,7 +24,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             0x09, 0x57, 0xD8, 0x2B
         ]
     );
-    assert_eq!(measurement1.eq(&measurement2), false);
+    assert_ne!(measurement1, measurement2);
 
     Ok(())
 }
@@ -0,0 +1,38 @@
+//! Bodies of the verification service API, shared by the HTTP endpoints and the gRPC client
+use serde::{Deserialize, Serialize};
+
+/// Response of `GET /nonce`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NonceResponse {
+    #[serde(with = "crate::serialize::base64_bytes")]
+    pub nonce: Vec<u8>,
+    /// Seconds until the nonce expires
+    pub expires_in: u64,
+}
+
+/// Body of `POST /verify`
+#[cfg(feature = "service")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyRequest {
+    #[serde(with = "crate::serialize::base64_bytes")]
+    pub quote: Vec<u8>,
+    /// Nonce from `GET /nonce`, if the quote must be fresh
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "crate::serialize::base64_bytes"
+    )]
+    pub nonce: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+}
+
+/// Response of `POST /verify`
+#[cfg(any(feature = "service", feature = "grpc"))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyResponse {
+    /// Attestation result as an EAT JWT
+    pub token: String,
+    /// Same as the `attester_tcb_status` claim
+    pub tcb_status: String,
+}
@@ -0,0 +1,404 @@
+//! Tamper-evident audit log of attestation decisions
+//!
+//! Every decision is an [`AuditEntry`] passed to an [`AuditSink`]. [`FileAuditSink`] appends the
+//! entries to a JSON lines file, each line chained to the previous one by its hash:
+//!
+//! ```json
+//! {"sequence":0,"prev_hash":"0000…","hash":"9f2c…","entry":{"timestamp":1700000000,…}}
+//! ```
+//!
+//! `hash` is the SHA-256 of `prev_hash`, the big-endian `sequence` and the `entry` JSON as written,
+//! so modifying, removing or reordering entries breaks the chain, which [`verify_audit_log`]
+//! detects by replaying it.
+//!
+//! The hash is not keyed: whoever can write the file can also recompute every hash after the
+//! entries they changed, or drop entries from the end, and the log still verifies. Such rewrites
+//! are only detected by comparing the [`AuditChainHead`] with one anchored outside of the file,
+//! e.g. periodically exported to a separate system, which the verification service doesn't do.
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::audit::{verify_audit_file, FileAuditSink};
+//!
+//! let service = VerificationService::new(verifier, issuer)
+//!     .with_audit_sink(FileAuditSink::open("/var/log/occlum-sgx/audit.jsonl")?);
+//!
+//! let head = verify_audit_file("/var/log/occlum-sgx/audit.jsonl")?;
+//! println!("{} entries, head {}", head.entries, hex::encode(head.hash));
+//! ```
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+
+use crate::telemetry::warn;
+use crate::{
+    CollateralVersions, SGXError, SGXErrorKind, SGXMeasurement, SGXQuote, SGXQuoteVerifyResult,
+};
+
+/// Destination of the audit entries
+pub trait AuditSink: Send + Sync {
+    /// Persist the entry, the decision must not be acted on if this fails
+    fn record(&self, entry: &AuditEntry) -> Result<(), SGXError>;
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for Arc<S> {
+    fn record(&self, entry: &AuditEntry) -> Result<(), SGXError> {
+        S::record(self, entry)
+    }
+}
+
+/// Enclave identity decoded from the quote
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditIdentity {
+    pub mrenclave: SGXMeasurement,
+    pub mrsigner: SGXMeasurement,
+    pub product_id: u16,
+    pub version: u16,
+    pub debug: bool,
+}
+
+impl From<&SGXQuote> for AuditIdentity {
+    fn from(quote: &SGXQuote) -> Self {
+        Self {
+            mrenclave: quote.mrenclave(),
+            mrsigner: quote.mrsigner(),
+            product_id: quote.product_id(),
+            version: quote.version(),
+            debug: quote.is_debug(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditVerdict {
+    Accepted,
+    /// Rejected by the verification or the appraisal
+    Rejected,
+    /// No decision could be made, e.g. the quote is malformed or PCCS is unreachable
+    Failed,
+}
+
+impl AuditVerdict {
+    /// Verdict of the outcome of a verification
+    pub fn of<T>(result: &Result<T, SGXError>) -> Self {
+        match result {
+            Ok(_) => AuditVerdict::Accepted,
+            Err(e) if e.kind() == SGXErrorKind::Rejected => AuditVerdict::Rejected,
+            Err(_) => AuditVerdict::Failed,
+        }
+    }
+}
+
+/// Attestation decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// SHA-256 of the raw quote
+    #[serde(with = "crate::serialize::hex_bytes")]
+    pub quote_hash: [u8; 32],
+    /// `None` if the quote is malformed
+    pub identity: Option<AuditIdentity>,
+    /// TCB status name, `None` if the quote was not verified
+    pub tcb_status: Option<String>,
+    pub collateral_versions: CollateralVersions,
+    pub policy_id: String,
+    pub verdict: AuditVerdict,
+    /// Error of a rejected or failed decision
+    pub reason: Option<String>,
+}
+
+impl AuditEntry {
+    /// Decision on the raw `quote` at the current time
+    pub fn new<S: Into<String>>(quote: &[u8], policy_id: S, verdict: AuditVerdict) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            quote_hash: Sha256::digest(quote).into(),
+            identity: SGXQuote::from_slice(quote)
+                .ok()
+                .map(|quote| AuditIdentity::from(&quote)),
+            tcb_status: None,
+            collateral_versions: CollateralVersions::default(),
+            policy_id: policy_id.into(),
+            verdict,
+            reason: None,
+        }
+    }
+
+    /// Decision with the `result` of the request, `verification` is the verification result and the
+    /// versions of the collateral, if the quote was verified
+    pub fn of<T, S: Into<String>>(
+        quote: &[u8],
+        policy_id: S,
+        verification: Option<(SGXQuoteVerifyResult, CollateralVersions)>,
+        result: &Result<T, SGXError>,
+    ) -> Self {
+        let mut entry = Self::new(quote, policy_id, AuditVerdict::of(result));
+        if let Some((tcb_status, collateral_versions)) = verification {
+            entry = entry
+                .with_tcb_status(tcb_status)
+                .with_collateral_versions(collateral_versions);
+        }
+        if let Err(e) = result {
+            entry = entry.with_reason(e.to_string());
+        }
+        entry
+    }
+
+    pub fn with_tcb_status(mut self, result: SGXQuoteVerifyResult) -> Self {
+        self.tcb_status = Some(result.tcb_status().to_string());
+        self
+    }
+
+    pub fn with_collateral_versions(mut self, collateral_versions: CollateralVersions) -> Self {
+        self.collateral_versions = collateral_versions;
+        self
+    }
+
+    pub fn with_reason<S: Into<String>>(mut self, reason: S) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+/// Line of the audit log
+#[derive(Serialize, Deserialize)]
+struct ChainedEntry {
+    sequence: u64,
+    #[serde(with = "crate::serialize::hex_bytes")]
+    prev_hash: [u8; 32],
+    #[serde(with = "crate::serialize::hex_bytes")]
+    hash: [u8; 32],
+    entry: Box<RawValue>,
+}
+
+fn chain_hash(prev_hash: &[u8; 32], sequence: u64, entry: &str) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(prev_hash)
+        .chain_update(sequence.to_be_bytes())
+        .chain_update(entry)
+        .finalize()
+        .into()
+}
+
+/// End of a verified audit log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AuditChainHead {
+    /// Number of entries
+    pub entries: u64,
+    /// Hash of the last entry, zeros for an empty log
+    pub hash: [u8; 32],
+}
+
+/// Replay the hash chain of the audit log, fails with [`SGXError::AuditChainBroken`] at the first
+/// entry which does not verify
+pub fn verify_audit_log<R: BufRead>(reader: R) -> Result<AuditChainHead, SGXError> {
+    let mut head = AuditChainHead::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let broken = |reason: &str| SGXError::AuditChainBroken {
+            line: index as u64 + 1,
+            reason: reason.to_string(),
+        };
+        let chained: ChainedEntry =
+            serde_json::from_str(&line).map_err(|e| broken(&e.to_string()))?;
+        serde_json::from_str::<AuditEntry>(chained.entry.get())
+            .map_err(|e| broken(&e.to_string()))?;
+
+        if chained.sequence != head.entries {
+            return Err(broken(&format!("expected sequence {}", head.entries)));
+        }
+        if chained.prev_hash != head.hash {
+            return Err(broken("previous hash does not match"));
+        }
+        if chain_hash(&chained.prev_hash, chained.sequence, chained.entry.get()) != chained.hash {
+            return Err(broken("entry hash does not match"));
+        }
+        head = AuditChainHead {
+            entries: head.entries + 1,
+            hash: chained.hash,
+        };
+    }
+    Ok(head)
+}
+
+/// [`verify_audit_log`] of the file at `path`
+pub fn verify_audit_file<P: AsRef<Path>>(path: P) -> Result<AuditChainHead, SGXError> {
+    verify_audit_log(BufReader::new(File::open(path)?))
+}
+
+struct Chain {
+    file: File,
+    head: AuditChainHead,
+}
+
+/// Append-only audit log file, see the [module documentation](self)
+pub struct FileAuditSink {
+    chain: Mutex<Chain>,
+}
+
+impl FileAuditSink {
+    /// Open the log at `path` for appending, creating it if it doesn't exist. The existing entries
+    /// are replayed to continue the chain, fails if it is broken.
+    ///
+    /// A last line without a newline is an entry torn by a crash during [`AuditSink::record`],
+    /// which never returned it as recorded, so it is truncated.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SGXError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut log = Vec::new();
+        (&file).read_to_end(&mut log)?;
+        let complete = log.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if complete < log.len() {
+            warn!(
+                "Truncating a torn audit log entry of {} bytes",
+                log.len() - complete
+            );
+            file.set_len(complete as u64)?;
+            file.sync_data()?;
+        }
+        let head = verify_audit_log(&log[..complete])?;
+        Ok(Self {
+            chain: Mutex::new(Chain { file, head }),
+        })
+    }
+
+    /// End of the chain written so far
+    pub fn head(&self) -> AuditChainHead {
+        self.chain.lock().unwrap().head
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    /// Append the entry and sync the file
+    fn record(&self, entry: &AuditEntry) -> Result<(), SGXError> {
+        let entry =
+            serde_json::value::to_raw_value(entry).map_err(|e| SGXError::IoError(e.into()))?;
+
+        let mut chain = self.chain.lock().unwrap();
+        let head = chain.head;
+        let hash = chain_hash(&head.hash, head.entries, entry.get());
+        let mut line = serde_json::to_vec(&ChainedEntry {
+            sequence: head.entries,
+            prev_hash: head.hash,
+            hash,
+            entry,
+        })
+        .map_err(|e| SGXError::IoError(e.into()))?;
+        line.push(b'\n');
+        // A partial line would break the chain for every later entry, truncate it on failure
+        let len = chain.file.metadata()?.len();
+        if let Err(e) = chain
+            .file
+            .write_all(&line)
+            .and_then(|()| chain.file.sync_data())
+        {
+            let _ = chain.file.set_len(len);
+            return Err(e.into());
+        }
+
+        chain.head = AuditChainHead {
+            entries: head.entries + 1,
+            hash,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn chain_and_replay() {
+        let raw = include_bytes!("../tests/fixtures/quote.raw");
+        let quote = SGXQuote::from_slice(raw).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+
+        let sink = FileAuditSink::open(&path).unwrap();
+        let accepted = AuditEntry::new(raw, "default", AuditVerdict::Accepted)
+            .with_tcb_status(SGXQuoteVerifyResult::Ok);
+        sink.record(&accepted).unwrap();
+        let rejected = AuditEntry::new(raw, "strict", AuditVerdict::Rejected)
+            .with_tcb_status(SGXQuoteVerifyResult::OutOfDate)
+            .with_reason("Quote verification failed: OutOfDate");
+        sink.record(&rejected).unwrap();
+        assert_eq!(accepted.quote_hash, <[u8; 32]>::from(Sha256::digest(raw)));
+        assert_eq!(accepted.identity.unwrap().mrenclave, quote.mrenclave());
+
+        // The chain continues after reopening
+        let sink = FileAuditSink::open(&path).unwrap();
+        let failed = AuditEntry::new(&[0; 16], "default", AuditVerdict::Failed);
+        assert_eq!(failed.identity, None);
+        sink.record(&failed).unwrap();
+        let head = verify_audit_file(&path).unwrap();
+        assert_eq!(head.entries, 3);
+        assert_eq!(head, sink.head());
+
+        let log = fs::read_to_string(&path).unwrap();
+        let lines = log.lines().collect::<Vec<_>>();
+        let entry = serde_json::from_str::<ChainedEntry>(lines[1])
+            .unwrap()
+            .entry;
+        assert_eq!(
+            serde_json::from_str::<AuditEntry>(entry.get()).unwrap(),
+            rejected
+        );
+
+        let broken_at = |log: String| match verify_audit_log(log.as_bytes()) {
+            Err(SGXError::AuditChainBroken { line, .. }) => line,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(broken_at(log.replacen("rejected", "accepted", 1)), 2);
+        assert_eq!(broken_at(format!("{}\n{}\n", lines[0], lines[2])), 2);
+        assert_eq!(broken_at(format!("{}\n{}\n", lines[1], lines[2])), 1);
+        assert_eq!(
+            verify_audit_log(&b""[..]).unwrap(),
+            AuditChainHead::default()
+        );
+    }
+
+    #[test]
+    fn torn_entry_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let entry = AuditEntry::new(&[0; 16], "default", AuditVerdict::Failed);
+        let sink = FileAuditSink::open(&path).unwrap();
+        sink.record(&entry).unwrap();
+        sink.record(&entry).unwrap();
+        let log = fs::read_to_string(&path).unwrap();
+
+        // Crash in the middle of the third entry
+        let torn = &log[log.find('\n').unwrap() + 1..][..40];
+        fs::write(&path, format!("{}{}", log, torn)).unwrap();
+        verify_audit_file(&path).unwrap_err();
+
+        let sink = FileAuditSink::open(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), log);
+        assert_eq!(sink.head().entries, 2);
+        sink.record(&entry).unwrap();
+        assert_eq!(verify_audit_file(&path).unwrap().entries, 3);
+
+        // A torn first entry leaves an empty log
+        fs::write(&path, torn).unwrap();
+        assert_eq!(FileAuditSink::open(&path).unwrap().head().entries, 0);
+        assert!(fs::read(&path).unwrap().is_empty());
+    }
+}
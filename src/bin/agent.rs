@@ -0,0 +1,47 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use clap::Parser;
+use occlum_sgx::grpc::AttestationAgent;
+use occlum_sgx::pccs::PccsClient;
+use occlum_sgx::RetryPolicy;
+
+/// Local attestation agent: serves the gRPC `GenerateQuote` to the enclave workloads, must be run
+/// in an Occlum instance
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    listen: SocketAddr,
+    /// Quote generation attempts, including the first one
+    #[arg(long, default_value_t = 3)]
+    max_attempts: u32,
+    /// Also serve `GetCollateral` from the PCCS at this URL
+    #[arg(long)]
+    pccs_url: Option<String>,
+    /// Seconds to cache the collateral for
+    #[arg(long, default_value_t = 3600)]
+    collateral_ttl: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    let mut agent = AttestationAgent::new().with_quote_generation(RetryPolicy {
+        max_attempts: cli.max_attempts,
+        ..Default::default()
+    });
+    if let Some(pccs_url) = cli.pccs_url {
+        agent = agent.with_collateral(
+            PccsClient::new(pccs_url).with_cache_ttl(Duration::from_secs(cli.collateral_ttl)),
+        );
+    }
+
+    eprintln!("Serving gRPC on {}", cli.listen);
+    tonic::transport::Server::builder()
+        .add_service(agent.into_server())
+        .serve(cli.listen)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use occlum_sgx::dcap::DcapVerifier;
+use occlum_sgx::eat::EatIssuer;
+use occlum_sgx::pccs::PccsClient;
+use occlum_sgx::service::{self, PrometheusBuilder, VerificationService};
+use occlum_sgx::{AppraisalPolicy, RetryPolicy};
+
+/// Attestation verification service: verifies SGX DCAP quotes with the collateral from PCCS and
+/// returns the results as signed EAT tokens
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[arg(long, default_value = "0.0.0.0:8080")]
+    listen: SocketAddr,
+    /// Also serve the gRPC `Attestation` service (`VerifyQuote`, `GetNonce`, `GetCollateral`)
+    #[cfg(feature = "grpc-verifier")]
+    #[arg(long)]
+    grpc_listen: Option<SocketAddr>,
+    /// PCCS base URL
+    #[arg(long, default_value = "https://localhost:8081")]
+    pccs_url: String,
+    /// Seconds to cache the collateral for
+    #[arg(long, default_value_t = 3600)]
+    collateral_ttl: u64,
+    /// PKCS#8 PEM P-256 key which signs the tokens
+    #[arg(long)]
+    signing_key: PathBuf,
+    /// `iss` claim of the tokens
+    #[arg(long)]
+    issuer: Option<String>,
+    /// JSON object of the appraisal policies by ID, e.g. `{"default": {"mrsigner": [...]}}`. Required,
+    /// as the default policy accepts any enclave with a valid quote
+    #[arg(long)]
+    policies: PathBuf,
+    /// PEM root CA to trust instead of the Intel SGX Root CA
+    #[arg(long)]
+    trusted_root: Option<PathBuf>,
+    /// Append every decision to this hash-chained audit log
+    #[cfg(feature = "audit")]
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+}
+
+type Error = Box<dyn std::error::Error>;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+
+    let pccs = Arc::new(
+        PccsClient::new(cli.pccs_url)
+            .with_cache_ttl(Duration::from_secs(cli.collateral_ttl))
+            .with_retry_policy(RetryPolicy::default()),
+    );
+    let mut verifier = DcapVerifier::new(pccs.clone());
+    if let Some(path) = cli.trusted_root {
+        verifier = verifier.with_trusted_root_pem(&fs::read_to_string(path)?)?;
+    }
+
+    let mut issuer = EatIssuer::from_pkcs8_pem(&fs::read_to_string(cli.signing_key)?)?;
+    if let Some(iss) = cli.issuer {
+        issuer = issuer.with_issuer(iss);
+    }
+
+    let policies: HashMap<String, AppraisalPolicy> =
+        serde_json::from_slice(&fs::read(cli.policies)?)?;
+    let metrics = PrometheusBuilder::new().install_recorder()?;
+    occlum_sgx::describe_metrics();
+    let service = VerificationService::new(verifier, issuer)
+        .with_policies(policies)
+        .with_metrics(metrics);
+    #[cfg(feature = "audit")]
+    let service = match cli.audit_log {
+        Some(path) => service.with_audit_sink(occlum_sgx::audit::FileAuditSink::open(path)?),
+        None => service,
+    };
+    let service = Arc::new(service);
+
+    // Bind both listeners before serving, so that either fails at startup
+    let listener = tokio::net::TcpListener::bind(cli.listen).await?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+    let http = async {
+        axum::serve(listener, service::router(service.clone())).await?;
+        Ok::<_, Error>(())
+    };
+
+    #[cfg(feature = "grpc-verifier")]
+    if let Some(addr) = cli.grpc_listen {
+        let agent = occlum_sgx::grpc::AttestationAgent::new()
+            .with_verification(service.clone())
+            .with_collateral(pccs);
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        eprintln!("Serving gRPC on {}", listener.local_addr()?);
+        let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+            .map_err(|e| e as Error)?;
+        let grpc = async {
+            tonic::transport::Server::builder()
+                .add_service(agent.into_server())
+                .serve_with_incoming(incoming)
+                .await?;
+            Ok::<_, Error>(())
+        };
+        tokio::try_join!(http, grpc)?;
+        return Ok(());
+    }
+
+    http.await
+}
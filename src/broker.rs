@@ -0,0 +1,461 @@
+//! Secret provisioning after attestation
+//!
+//! The enclave generates an ephemeral X25519 key and binds it with a nonce of the broker into the
+//! report data of a quote (see [`report_data_for_key`]). [`KeyBroker`] verifies the quote, appraises
+//! it with its [`AppraisalPolicy`] and returns the requested secrets of its [`SecretStore`] sealed
+//! to the enclave key with [HPKE](crate::hpke). [`KeyBrokerClient`] is the enclave side.
+//!
+//! | Endpoint         | Description                                                          |
+//! |------------------|----------------------------------------------------------------------|
+//! | `GET /nonce`     | Single-use nonce                                                     |
+//! | `POST /secrets`  | `{"quote", "nonce", "public_key", "names": [...]}` → `{"enc", "ciphertext"}` |
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::broker::{KeyBroker, KeyBrokerClient};
+//!
+//! // Broker
+//! let secrets = HashMap::from([("db-password".to_string(), b"hunter2".to_vec())]);
+//! let broker = KeyBroker::new(DcapVerifier::new(pccs), secrets, policy);
+//! axum::serve(listener, broker.router()).await?;
+//!
+//! // Enclave
+//! let secrets = KeyBrokerClient::new("https://broker.example.com").request_secrets(&["db-password"])?;
+//! ```
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::api::NonceResponse;
+#[cfg(feature = "audit")]
+use crate::audit::{AuditEntry, AuditSink};
+use crate::constants::SGX_REPORT_DATA_SIZE;
+use crate::generator::GlobalGenerator;
+use crate::hpke::{self, PublicKey, StaticSecret, KEY_SIZE};
+use crate::http::{blocking, ErrorResponse, Nonces, NONCE_SIZE};
+use crate::telemetry::{self, debug};
+use crate::{
+    AppraisalPolicy, CollateralVersions, QuoteGenerator, QuoteVerifier, ReportData, SGXError,
+    SGXQuote, SGXQuoteVerifyResult,
+};
+
+/// HPKE `info` of the sealed secrets
+const HPKE_INFO: &[u8] = b"occlum-sgx key broker v1";
+
+/// Report data binding the enclave key to the broker nonce: the nonce (up to 32 bytes) followed by
+/// the SHA-256 hash of the key
+pub fn report_data_for_key(nonce: &[u8], public_key: &PublicKey) -> ReportData {
+    let mut report_data = [0u8; SGX_REPORT_DATA_SIZE];
+    let len = nonce.len().min(NONCE_SIZE);
+    report_data[..len].copy_from_slice(&nonce[..len]);
+    report_data[NONCE_SIZE..].copy_from_slice(&Sha256::digest(public_key.as_bytes()));
+    report_data
+}
+
+/// Source of the secrets released by [`KeyBroker`]
+pub trait SecretStore: Send + Sync {
+    /// Secret `name` for the enclave of the appraised `quote`, `None` if there is no such secret or
+    /// the enclave may not have it
+    fn secret(&self, name: &str, quote: &SGXQuote) -> Result<Option<Vec<u8>>, SGXError>;
+}
+
+/// Same secrets for every enclave which passes the appraisal
+impl SecretStore for HashMap<String, Vec<u8>> {
+    fn secret(&self, name: &str, _: &SGXQuote) -> Result<Option<Vec<u8>>, SGXError> {
+        Ok(self.get(name).cloned())
+    }
+}
+
+impl<F> SecretStore for F
+where
+    F: Fn(&str, &SGXQuote) -> Result<Option<Vec<u8>>, SGXError> + Send + Sync,
+{
+    fn secret(&self, name: &str, quote: &SGXQuote) -> Result<Option<Vec<u8>>, SGXError> {
+        self(name, quote)
+    }
+}
+
+/// Body of `POST /secrets`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretRequest {
+    #[serde(with = "crate::serialize::base64_bytes")]
+    pub quote: Vec<u8>,
+    /// Nonce from `GET /nonce`
+    #[serde(with = "crate::serialize::base64_bytes")]
+    pub nonce: Vec<u8>,
+    /// X25519 public key of the enclave
+    #[serde(with = "crate::serialize::base64_bytes")]
+    pub public_key: Vec<u8>,
+    pub names: Vec<String>,
+}
+
+/// Response of `POST /secrets`: the secrets sealed with HPKE, with the nonce as AAD
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedSecrets {
+    /// HPKE encapsulated key
+    #[serde(with = "crate::serialize::base64_bytes")]
+    pub enc: Vec<u8>,
+    #[serde(with = "crate::serialize::base64_bytes")]
+    pub ciphertext: Vec<u8>,
+}
+
+/// Plaintext of [`SealedSecrets`]
+#[derive(Serialize, Deserialize)]
+struct Secret {
+    name: String,
+    #[serde(with = "crate::serialize::base64_bytes")]
+    value: Vec<u8>,
+}
+
+/// Releases secrets to the appraised enclaves, see the [module documentation](self)
+pub struct KeyBroker {
+    verifier: Box<dyn QuoteVerifier>,
+    store: Box<dyn SecretStore>,
+    policy: AppraisalPolicy,
+    nonces: Nonces,
+    #[cfg(feature = "audit")]
+    audit_sink: Option<Box<dyn AuditSink>>,
+}
+
+impl KeyBroker {
+    pub fn new<V, S>(verifier: V, store: S, policy: AppraisalPolicy) -> Self
+    where
+        V: QuoteVerifier + 'static,
+        S: SecretStore + 'static,
+    {
+        Self {
+            verifier: Box::new(verifier),
+            store: Box::new(store),
+            policy,
+            nonces: Nonces::new(Duration::from_secs(300)),
+            #[cfg(feature = "audit")]
+            audit_sink: None,
+        }
+    }
+
+    /// How long an issued nonce may be used, 5 minutes by default
+    pub fn with_nonce_ttl(mut self, nonce_ttl: Duration) -> Self {
+        self.nonces = Nonces::new(nonce_ttl);
+        self
+    }
+
+    /// Record every decision to `sink` under [`DEFAULT_POLICY_ID`](crate::DEFAULT_POLICY_ID),
+    /// requests fail if it can't be recorded
+    #[cfg(feature = "audit")]
+    pub fn with_audit_sink<S: AuditSink + 'static>(mut self, sink: S) -> Self {
+        self.audit_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Issue a single-use nonce
+    pub fn issue_nonce(&self) -> Result<Vec<u8>, SGXError> {
+        self.nonces.issue()
+    }
+
+    /// Verify and appraise the quote of the request and seal the requested secrets to its key
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "release_secrets",
+            skip_all,
+            fields(names = ?request.names, quote_hash, mrenclave, result, error)
+        )
+    )]
+    pub fn release(&self, request: &SecretRequest) -> Result<SealedSecrets, SGXError> {
+        telemetry::record_quote(&request.quote);
+        let mut verification = None;
+        let sealed = self.seal_secrets(request, &mut verification);
+        #[cfg(feature = "audit")]
+        let sealed = match &self.audit_sink {
+            Some(sink) => sink
+                .record(&AuditEntry::of(
+                    &request.quote,
+                    crate::DEFAULT_POLICY_ID,
+                    verification,
+                    &sealed,
+                ))
+                .and(sealed),
+            None => sealed,
+        };
+        if let Err(e) = &sealed {
+            telemetry::record("result", "error");
+            telemetry::record_error(e);
+        }
+        sealed
+    }
+
+    /// Verification of the quote is stored in `verification` for the audit log
+    fn seal_secrets(
+        &self,
+        request: &SecretRequest,
+        verification: &mut Option<(SGXQuoteVerifyResult, CollateralVersions)>,
+    ) -> Result<SealedSecrets, SGXError> {
+        let public_key: [u8; KEY_SIZE] =
+            request.public_key.as_slice().try_into().map_err(|_| {
+                SGXError::KeyExchangeFailed("bad X25519 public key length".to_string())
+            })?;
+        let public_key = PublicKey::from(public_key);
+
+        let quote = SGXQuote::try_from(request.quote.as_slice())?;
+        self.nonces.consume(&request.nonce)?;
+        if *quote.report_data() != report_data_for_key(&request.nonce, &public_key) {
+            return Err(SGXError::ReportDataMismatch);
+        }
+        let (result, _) = *verification.insert(self.verifier.verify_quote_with_versions(&quote)?);
+        telemetry::record("result", result.tcb_status());
+        self.policy.appraise(&quote, result)?;
+
+        let secrets = request
+            .names
+            .iter()
+            .map(|name| {
+                let value = self
+                    .store
+                    .secret(name, &quote)?
+                    .ok_or_else(|| SGXError::SecretNotFound(name.clone()))?;
+                Ok(Secret {
+                    name: name.clone(),
+                    value,
+                })
+            })
+            .collect::<Result<Vec<_>, SGXError>>()?;
+        debug!(
+            "Releasing {:?} to {} ({:?})",
+            request.names,
+            quote.mrenclave(),
+            result
+        );
+
+        let plaintext = serde_json::to_vec(&secrets).map_err(|e| SGXError::IoError(e.into()))?;
+        let (enc, ciphertext) = hpke::seal(&public_key, HPKE_INFO, &request.nonce, &plaintext)?;
+        Ok(SealedSecrets {
+            enc: enc.to_vec(),
+            ciphertext,
+        })
+    }
+
+    /// HTTP routes of the broker
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/nonce", get(nonce))
+            .route("/secrets", post(secrets))
+            .route("/health", get(|| async { "OK" }))
+            .with_state(Arc::new(self))
+    }
+}
+
+type Broker = State<Arc<KeyBroker>>;
+
+async fn nonce(State(broker): Broker) -> Result<Json<NonceResponse>, ErrorResponse> {
+    let nonce = broker.issue_nonce().map_err(ErrorResponse)?;
+    Ok(Json(NonceResponse {
+        nonce,
+        expires_in: broker.nonces.ttl().as_secs(),
+    }))
+}
+
+async fn secrets(
+    State(broker): Broker,
+    Json(request): Json<SecretRequest>,
+) -> Result<Json<SealedSecrets>, ErrorResponse> {
+    blocking(move || broker.release(&request)).await
+}
+
+/// Client of [`KeyBroker`], run inside the enclave
+pub struct KeyBrokerClient {
+    base_url: String,
+    agent: ureq::Agent,
+    generator: Box<dyn QuoteGenerator>,
+}
+
+impl KeyBrokerClient {
+    /// Client of the broker at `base_url`, quotes are generated as [`SGXQuote::from_report_data`]
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+            generator: Box::new(GlobalGenerator),
+        }
+    }
+
+    /// HTTP agent, e.g. with a custom timeout or TLS configuration
+    pub fn with_agent(mut self, agent: ureq::Agent) -> Self {
+        self.agent = agent;
+        self
+    }
+
+    /// Generate the quotes with `generator`
+    pub fn with_quote_generator<G: QuoteGenerator + 'static>(mut self, generator: G) -> Self {
+        self.generator = Box::new(generator);
+        self
+    }
+
+    fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        request: ureq::Request,
+        body: Option<String>,
+    ) -> Result<T, SGXError> {
+        let url = request.url().to_string();
+        let response = match body {
+            Some(body) => request
+                .set("Content-Type", "application/json")
+                .send_string(&body),
+            None => request.call(),
+        }
+        .map_err(|e| match e {
+            ureq::Error::Status(status, response) => SGXError::BrokerRequestFailed {
+                status: Some(status),
+                message: response.into_string().unwrap_or(url),
+            },
+            ureq::Error::Transport(e) => SGXError::BrokerRequestFailed {
+                status: None,
+                message: e.to_string(),
+            },
+        })?;
+        serde_json::from_reader(response.into_reader()).map_err(|e| SGXError::BrokerRequestFailed {
+            status: None,
+            message: e.to_string(),
+        })
+    }
+
+    /// Attest to the broker and fetch the secrets, fails if any of them is not released
+    pub fn request_secrets(&self, names: &[&str]) -> Result<HashMap<String, Vec<u8>>, SGXError> {
+        let nonce: NonceResponse =
+            self.call(self.agent.get(&format!("{}/nonce", self.base_url)), None)?;
+
+        let secret = hpke::generate_key()?;
+        let public_key = PublicKey::from(&secret);
+        let report_data = report_data_for_key(&nonce.nonce, &public_key);
+        let quote = self.generator.generate_quote(&report_data)?;
+
+        let request = SecretRequest {
+            quote,
+            nonce: nonce.nonce,
+            public_key: public_key.as_bytes().to_vec(),
+            names: names.iter().map(|name| name.to_string()).collect(),
+        };
+        let body = serde_json::to_string(&request).map_err(|e| SGXError::IoError(e.into()))?;
+        let sealed: SealedSecrets = self.call(
+            self.agent.post(&format!("{}/secrets", self.base_url)),
+            Some(body),
+        )?;
+        open_secrets(&secret, &request.nonce, &sealed)
+    }
+}
+
+/// Decrypt the secrets sealed by [`KeyBroker::release`] to the key of `secret`
+pub fn open_secrets(
+    secret: &StaticSecret,
+    nonce: &[u8],
+    sealed: &SealedSecrets,
+) -> Result<HashMap<String, Vec<u8>>, SGXError> {
+    let plaintext = hpke::open(secret, &sealed.enc, HPKE_INFO, nonce, &sealed.ciphertext)?;
+    let secrets: Vec<Secret> =
+        serde_json::from_slice(&plaintext).map_err(|e| SGXError::DecryptionFailed(e.into()))?;
+    Ok(secrets
+        .into_iter()
+        .map(|secret| (secret.name, secret.value))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{accept_all, fixture_quote};
+    use crate::SGXMeasurement;
+
+    #[test]
+    fn release_secrets() {
+        let quote = SGXQuote::try_from(fixture_quote(&[0; 64]).unwrap()).unwrap();
+        let mrenclave = quote.mrenclave();
+        // `tls-key` is released to the fixture enclave only
+        let store = move |name: &str, quote: &SGXQuote| -> Result<_, SGXError> {
+            Ok(match name {
+                "db-password" => Some(b"hunter2".to_vec()),
+                "tls-key" if quote.mrenclave() == mrenclave => Some(b"key".to_vec()),
+                _ => None,
+            })
+        };
+        let broker = KeyBroker::new(accept_all, store, AppraisalPolicy::default());
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            runtime.block_on(async { axum::serve(listener, broker.router()).await })
+        });
+
+        let client = KeyBrokerClient::new(&url).with_quote_generator(fixture_quote);
+        let secrets = client.request_secrets(&["db-password", "tls-key"]).unwrap();
+        assert_eq!(secrets["db-password"], b"hunter2");
+        assert_eq!(secrets["tls-key"], b"key");
+
+        let e = client.request_secrets(&["other"]).err().unwrap();
+        assert!(matches!(
+            e,
+            SGXError::BrokerRequestFailed {
+                status: Some(403),
+                ..
+            }
+        ));
+
+        // Quote of another key or enclave
+        let client = KeyBrokerClient::new(&url)
+            .with_quote_generator(|_: &ReportData| fixture_quote(&[0; SGX_REPORT_DATA_SIZE]));
+        assert!(client.request_secrets(&["db-password"]).is_err());
+        let broker = KeyBroker::new(
+            accept_all,
+            HashMap::new(),
+            AppraisalPolicy {
+                mrenclave: vec![SGXMeasurement::new([0x55; 32])],
+                ..Default::default()
+            },
+        );
+        #[cfg(feature = "audit")]
+        let audit_dir = tempfile::tempdir().unwrap();
+        #[cfg(feature = "audit")]
+        let broker = broker.with_audit_sink(
+            crate::audit::FileAuditSink::open(audit_dir.path().join("audit.jsonl")).unwrap(),
+        );
+        let nonce = broker.issue_nonce().unwrap();
+        let public_key = PublicKey::from(&hpke::generate_key().unwrap());
+        let request = SecretRequest {
+            quote: fixture_quote(&report_data_for_key(&nonce, &public_key)).unwrap(),
+            nonce,
+            public_key: public_key.as_bytes().to_vec(),
+            names: Vec::new(),
+        };
+        assert!(matches!(
+            broker.release(&request).err().unwrap(),
+            SGXError::AppraisalRejected(_)
+        ));
+
+        #[cfg(feature = "audit")]
+        {
+            let log = std::fs::read_to_string(audit_dir.path().join("audit.jsonl")).unwrap();
+            assert_eq!(
+                crate::audit::verify_audit_log(log.as_bytes())
+                    .unwrap()
+                    .entries,
+                1
+            );
+            let line: serde_json::Value = serde_json::from_str(&log).unwrap();
+            assert_eq!(line["entry"]["policy_id"], "default");
+            assert_eq!(line["entry"]["verdict"], "rejected");
+            assert_eq!(line["entry"]["tcb_status"], "UpToDate");
+        }
+    }
+}
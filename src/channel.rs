@@ -0,0 +1,581 @@
+//! Mutually attested encrypted channel between two enclaves
+//!
+//! [`MutualAttestation::connect`] (initiator) and [`MutualAttestation::accept`] (responder) run a
+//! handshake over any `Read + Write` transport:
+//!
+//! 1. initiator → responder: ephemeral X25519 share `e_i`
+//! 2. responder → initiator: share `e_r` and a quote with the report data
+//!    `e_r || SHA-256("occlum-sgx channel v1" || "responder" || e_i || e_r)`
+//! 3. initiator → responder: a quote with the report data
+//!    `e_i || SHA-256("occlum-sgx channel v1" || "initiator" || e_i || e_r || quote_r)`
+//!
+//! Each side verifies the quote of the other and appraises it with its policy. The session keys are
+//! derived with HKDF-SHA256 from the DH output and the hash of the whole transcript, one
+//! ChaCha20Poly1305 key per direction. [`AttestedStream`] then sends length-prefixed records, the
+//! first byte of the plaintext is the record type: data, or the close record of
+//! [`AttestedStream::close`]. A connection closed without it is reported as truncated.
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::channel::MutualAttestation;
+//!
+//! let attestation = MutualAttestation::new(DcapVerifier::new(pccs), peer_policy);
+//! let mut stream = attestation.connect(TcpStream::connect("10.0.0.2:7000")?)?;
+//! stream.write_all(b"hello")?;
+//! stream.close()?;
+//! ```
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "audit")]
+use crate::audit::{AuditEntry, AuditSink};
+use crate::constants::SGX_REPORT_DATA_SIZE;
+use crate::generator::GlobalGenerator;
+use crate::hpke::{generate_key, PublicKey, KEY_SIZE};
+use crate::telemetry::{self, debug};
+use crate::{
+    AppraisalPolicy, CollateralVersions, QuoteGenerator, QuoteVerifier, ReportData, SGXError,
+    SGXQuote, SGXQuoteVerifyResult,
+};
+
+const PROTOCOL: &[u8] = b"occlum-sgx channel v1";
+/// Largest handshake message, enough for a quote with the PCK certificate chain
+const MAX_HANDSHAKE_MESSAGE: usize = 64 * 1024;
+/// Largest plaintext of a record
+const MAX_RECORD: usize = 16 * 1024;
+const TAG_SIZE: usize = 16;
+const RECORD_DATA: u8 = 0;
+const RECORD_CLOSE: u8 = 1;
+
+/// Handshake configuration, see the [module documentation](self)
+#[derive(Clone)]
+pub struct MutualAttestation {
+    verifier: Arc<dyn QuoteVerifier>,
+    policy: AppraisalPolicy,
+    generator: Arc<dyn QuoteGenerator>,
+    #[cfg(feature = "audit")]
+    audit_sink: Option<Arc<dyn AuditSink>>,
+}
+
+fn failed(msg: &str) -> SGXError {
+    SGXError::KeyExchangeFailed(msg.to_string())
+}
+
+fn report_data(share: &PublicKey, transcript: Sha256) -> ReportData {
+    let mut report_data = [0u8; SGX_REPORT_DATA_SIZE];
+    report_data[..KEY_SIZE].copy_from_slice(share.as_bytes());
+    report_data[KEY_SIZE..].copy_from_slice(&transcript.finalize());
+    report_data
+}
+
+fn read_share(message: &[u8]) -> Result<PublicKey, SGXError> {
+    let share: [u8; KEY_SIZE] = message
+        .get(..KEY_SIZE)
+        .and_then(|share| share.try_into().ok())
+        .ok_or_else(|| failed("truncated key share"))?;
+    Ok(PublicKey::from(share))
+}
+
+/// Length-prefixed message, `None` on EOF before the length
+fn read_message<T: Read>(transport: &mut T, max: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match transport.read(&mut len[..1])? {
+        0 => return Ok(None),
+        _ => transport.read_exact(&mut len[1..])?,
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes exceeds {} bytes", len, max),
+        ));
+    }
+    let mut message = vec![0u8; len];
+    transport.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+fn write_message<T: Write>(transport: &mut T, message: &[u8]) -> io::Result<()> {
+    transport.write_all(&(message.len() as u32).to_be_bytes())?;
+    transport.write_all(message)?;
+    transport.flush()
+}
+
+fn expect_message<T: Read>(transport: &mut T) -> Result<Vec<u8>, SGXError> {
+    read_message(transport, MAX_HANDSHAKE_MESSAGE)?
+        .ok_or_else(|| failed("peer closed the connection during the handshake"))
+}
+
+impl MutualAttestation {
+    /// Verify the peer quotes with `verifier` and appraise them with `policy`
+    pub fn new<V: QuoteVerifier + 'static>(verifier: V, policy: AppraisalPolicy) -> Self {
+        Self {
+            verifier: Arc::new(verifier),
+            policy,
+            generator: Arc::new(GlobalGenerator),
+            #[cfg(feature = "audit")]
+            audit_sink: None,
+        }
+    }
+
+    /// Generate the own quotes with `generator` instead of [`SGXQuote::from_report_data`]
+    pub fn with_quote_generator<G: QuoteGenerator + 'static>(mut self, generator: G) -> Self {
+        self.generator = Arc::new(generator);
+        self
+    }
+
+    /// Record every peer decision to `sink` under [`DEFAULT_POLICY_ID`](crate::DEFAULT_POLICY_ID),
+    /// handshakes fail if it can't be recorded
+    #[cfg(feature = "audit")]
+    pub fn with_audit_sink<S: AuditSink + 'static>(mut self, sink: S) -> Self {
+        self.audit_sink = Some(Arc::new(sink));
+        self
+    }
+
+    fn appraise_peer(&self, quote: &[u8], expected: &ReportData) -> Result<SGXQuote, SGXError> {
+        telemetry::record_quote(quote);
+        let mut verification = None;
+        let peer = self.verify_peer(quote, expected, &mut verification);
+        #[cfg(feature = "audit")]
+        if let Some(sink) = &self.audit_sink {
+            let entry = AuditEntry::of(quote, crate::DEFAULT_POLICY_ID, verification, &peer);
+            sink.record(&entry)?;
+        }
+        peer
+    }
+
+    /// Verification of the quote is stored in `verification` for the audit log
+    fn verify_peer(
+        &self,
+        quote: &[u8],
+        expected: &ReportData,
+        verification: &mut Option<(SGXQuoteVerifyResult, CollateralVersions)>,
+    ) -> Result<SGXQuote, SGXError> {
+        let quote = SGXQuote::try_from(quote)?;
+        if *quote.report_data() != *expected {
+            return Err(SGXError::ReportDataMismatch);
+        }
+        let (result, _) = *verification.insert(self.verifier.verify_quote_with_versions(&quote)?);
+        telemetry::record("result", result.tcb_status());
+        self.policy.appraise(&quote, result)?;
+        debug!("Peer {} attested: {:?}", quote.mrenclave(), result);
+        Ok(quote)
+    }
+
+    /// Run the handshake as the initiator
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "handshake",
+            skip_all,
+            fields(role = "initiator", quote_hash, mrenclave, result)
+        )
+    )]
+    pub fn connect<T: Read + Write>(
+        &self,
+        mut transport: T,
+    ) -> Result<AttestedStream<T>, SGXError> {
+        let secret = generate_key()?;
+        let share = PublicKey::from(&secret);
+        write_message(&mut transport, share.as_bytes())?;
+
+        let message = expect_message(&mut transport)?;
+        let peer_share = read_share(&message)?;
+        let peer_quote = &message[KEY_SIZE..];
+        let expected = report_data(
+            &peer_share,
+            Sha256::new()
+                .chain_update(PROTOCOL)
+                .chain_update(b"responder")
+                .chain_update(share)
+                .chain_update(peer_share),
+        );
+        let peer = self.appraise_peer(peer_quote, &expected)?;
+
+        let quote = self.generator.generate_quote(&report_data(
+            &share,
+            Sha256::new()
+                .chain_update(PROTOCOL)
+                .chain_update(b"initiator")
+                .chain_update(share)
+                .chain_update(peer_share)
+                .chain_update(peer_quote),
+        ))?;
+        write_message(&mut transport, &quote)?;
+
+        let transcript: [&[u8]; 4] = [share.as_bytes(), peer_share.as_bytes(), peer_quote, &quote];
+        let dh = secret.diffie_hellman(&peer_share);
+        if !dh.was_contributory() {
+            return Err(failed("peer share is a low order point"));
+        }
+        let (send, receive) = session_keys(dh.as_bytes(), &transcript);
+        Ok(AttestedStream::new(transport, send, receive, peer))
+    }
+
+    /// Run the handshake as the responder
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "handshake",
+            skip_all,
+            fields(role = "responder", quote_hash, mrenclave, result)
+        )
+    )]
+    pub fn accept<T: Read + Write>(&self, mut transport: T) -> Result<AttestedStream<T>, SGXError> {
+        let message = expect_message(&mut transport)?;
+        let peer_share = read_share(&message)?;
+
+        let secret = generate_key()?;
+        let share = PublicKey::from(&secret);
+        let quote = self.generator.generate_quote(&report_data(
+            &share,
+            Sha256::new()
+                .chain_update(PROTOCOL)
+                .chain_update(b"responder")
+                .chain_update(peer_share)
+                .chain_update(share),
+        ))?;
+        write_message(&mut transport, &[share.as_bytes(), &quote[..]].concat())?;
+
+        let peer_quote = expect_message(&mut transport)?;
+        let expected = report_data(
+            &peer_share,
+            Sha256::new()
+                .chain_update(PROTOCOL)
+                .chain_update(b"initiator")
+                .chain_update(peer_share)
+                .chain_update(share)
+                .chain_update(&quote),
+        );
+        let peer = self.appraise_peer(&peer_quote, &expected)?;
+
+        let transcript: [&[u8]; 4] = [peer_share.as_bytes(), share.as_bytes(), &quote, &peer_quote];
+        let dh = secret.diffie_hellman(&peer_share);
+        if !dh.was_contributory() {
+            return Err(failed("peer share is a low order point"));
+        }
+        let (receive, send) = session_keys(dh.as_bytes(), &transcript);
+        Ok(AttestedStream::new(transport, send, receive, peer))
+    }
+}
+
+/// Initiator → responder and responder → initiator keys of the session
+fn session_keys(dh: &[u8], transcript: &[&[u8]]) -> (Cipher, Cipher) {
+    let mut hash = Sha256::new().chain_update(PROTOCOL);
+    for message in transcript {
+        hash.update(message);
+    }
+    let hkdf = Hkdf::<Sha256>::new(Some(&hash.finalize()), dh);
+    let key = |label: &[u8]| {
+        let mut key = [0u8; 32];
+        hkdf.expand(&[PROTOCOL, label].concat(), &mut key)
+            .expect("32 bytes of HKDF-SHA256 output");
+        Cipher {
+            aead: ChaCha20Poly1305::new(&key.into()),
+            sequence: 0,
+        }
+    };
+    (key(b"initiator"), key(b"responder"))
+}
+
+struct Cipher {
+    aead: ChaCha20Poly1305,
+    sequence: u64,
+}
+
+impl Cipher {
+    fn nonce(&mut self) -> io::Result<[u8; 12]> {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.sequence.to_be_bytes());
+        self.sequence = self.sequence.checked_add(1).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "record sequence exhausted")
+        })?;
+        Ok(nonce)
+    }
+}
+
+/// Encrypted duplex stream of a completed handshake
+pub struct AttestedStream<T> {
+    transport: T,
+    send: Cipher,
+    receive: Cipher,
+    peer: SGXQuote,
+    /// Decrypted bytes of the last record which were not read yet
+    buffer: Vec<u8>,
+    position: usize,
+    /// The peer sent the close record
+    peer_closed: bool,
+    closed: bool,
+}
+
+impl<T> AttestedStream<T> {
+    fn new(transport: T, send: Cipher, receive: Cipher, peer: SGXQuote) -> Self {
+        Self {
+            transport,
+            send,
+            receive,
+            peer,
+            buffer: Vec::new(),
+            position: 0,
+            peer_closed: false,
+            closed: false,
+        }
+    }
+
+    /// Verified and appraised quote of the peer
+    pub fn peer_quote(&self) -> &SGXQuote {
+        &self.peer
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Decrypt the next record, returns its type and plaintext
+    fn read_record(&mut self) -> io::Result<(u8, Vec<u8>)>
+    where
+        T: Read,
+    {
+        let Some(record) = read_message(&mut self.transport, 1 + MAX_RECORD + TAG_SIZE)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "peer closed the connection without a close record",
+            ));
+        };
+        let nonce = self.receive.nonce()?;
+        let mut plaintext = self
+            .receive
+            .aead
+            .decrypt(&nonce.into(), record.as_slice())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad record"))?;
+        if plaintext.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad record"));
+        }
+        let record_type = plaintext.remove(0);
+        Ok((record_type, plaintext))
+    }
+
+    fn write_record(&mut self, record_type: u8, data: &[u8]) -> io::Result<()>
+    where
+        T: Write,
+    {
+        if self.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "stream is closed",
+            ));
+        }
+        let nonce = self.send.nonce()?;
+        let record = self
+            .send
+            .aead
+            .encrypt(&nonce.into(), [&[record_type], data].concat().as_slice())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "encryption failed"))?;
+        self.transport
+            .write_all(&(record.len() as u32).to_be_bytes())?;
+        self.transport.write_all(&record)
+    }
+
+    /// Send the close record, after which the peer reads EOF. Data sent by the peer can still be
+    /// read.
+    pub fn close(&mut self) -> io::Result<()>
+    where
+        T: Write,
+    {
+        self.write_record(RECORD_CLOSE, &[])?;
+        self.closed = true;
+        self.transport.flush()
+    }
+}
+
+impl<T: Read> Read for AttestedStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Empty data records are skipped, `Ok(0)` is only returned after the close record
+        while self.position == self.buffer.len() && !buf.is_empty() {
+            if self.peer_closed {
+                return Ok(0);
+            }
+            match self.read_record()? {
+                (RECORD_DATA, data) => {
+                    self.buffer = data;
+                    self.position = 0;
+                }
+                (RECORD_CLOSE, _) => self.peer_closed = true,
+                (record_type, _) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown record type {}", record_type),
+                    ))
+                }
+            }
+        }
+        let len = buf.len().min(self.buffer.len() - self.position);
+        buf[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl<T: Write> Write for AttestedStream<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(MAX_RECORD);
+        self.write_record(RECORD_DATA, &buf[..len])?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.transport.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use super::*;
+    use crate::tests::{accept_all, fixture_quote};
+    use crate::SGXMeasurement;
+
+    #[test]
+    fn handshake_and_stream() {
+        let attestation = MutualAttestation::new(accept_all, AppraisalPolicy::default())
+            .with_quote_generator(fixture_quote);
+        let (initiator, responder) = UnixStream::pair().unwrap();
+
+        let message: Vec<u8> = (0..MAX_RECORD * 2 + 100).map(|i| i as u8).collect();
+        let responder = std::thread::spawn({
+            let attestation = attestation.clone();
+            let len = message.len();
+            move || {
+                let mut stream = attestation.accept(responder).unwrap();
+                let mut message = vec![0u8; len];
+                stream.read_exact(&mut message).unwrap();
+                stream.write_all(&message).unwrap();
+                stream.read_to_end(&mut message).unwrap();
+                message
+            }
+        });
+        let mut stream = attestation.connect(initiator).unwrap();
+        let fixture = SGXQuote::try_from(fixture_quote(&[0; 64]).unwrap()).unwrap();
+        assert_eq!(stream.peer_quote().mrenclave(), fixture.mrenclave());
+        stream.write_all(&message).unwrap();
+        stream.write_all(b"bye").unwrap();
+        let mut echo = vec![0u8; message.len()];
+        stream.read_exact(&mut echo).unwrap();
+        assert_eq!(echo, message);
+        stream.close().unwrap();
+        assert!(stream.write_all(b"late").is_err());
+        assert_eq!(responder.join().unwrap()[message.len()..], *b"bye");
+
+        // Peer enclave rejected by the policy, the other side sees the connection closed
+        let strict = MutualAttestation::new(
+            accept_all,
+            AppraisalPolicy {
+                mrenclave: vec![SGXMeasurement::new([0x55; 32])],
+                ..Default::default()
+            },
+        )
+        .with_quote_generator(fixture_quote);
+        let (initiator, responder) = UnixStream::pair().unwrap();
+        let responder = std::thread::spawn(move || attestation.accept(responder).err());
+        assert!(matches!(
+            strict.connect(initiator).err(),
+            Some(SGXError::AppraisalRejected(_))
+        ));
+        assert!(matches!(
+            responder.join().unwrap(),
+            Some(SGXError::KeyExchangeFailed(_))
+        ));
+    }
+
+    #[test]
+    fn empty_records_and_truncation() {
+        let attestation = MutualAttestation::new(accept_all, AppraisalPolicy::default())
+            .with_quote_generator(fixture_quote);
+        let pair = || {
+            let (initiator, responder) = UnixStream::pair().unwrap();
+            let responder = std::thread::spawn({
+                let attestation = attestation.clone();
+                move || attestation.accept(responder).unwrap()
+            });
+            let initiator = attestation.connect(initiator).unwrap();
+            (initiator, responder.join().unwrap())
+        };
+
+        // Empty writes send nothing and empty records are skipped, neither is read as EOF
+        let (mut initiator, mut responder) = pair();
+        assert_eq!(initiator.write(b"").unwrap(), 0);
+        initiator.write_record(RECORD_DATA, b"").unwrap();
+        initiator.write_all(b"data").unwrap();
+        initiator.close().unwrap();
+        let mut received = [0u8; 4];
+        assert_eq!(responder.read(&mut received).unwrap(), 4);
+        assert_eq!(received, *b"data");
+        assert_eq!(responder.read(&mut received).unwrap(), 0);
+        assert_eq!(responder.read(&mut received).unwrap(), 0);
+        // Half-closed, the other direction is still open
+        responder.write_all(b"reply").unwrap();
+        responder.close().unwrap();
+        let mut reply = Vec::new();
+        initiator.read_to_end(&mut reply).unwrap();
+        assert_eq!(reply, b"reply");
+
+        // Transport closed without the close record
+        let (mut initiator, mut responder) = pair();
+        initiator.write_all(b"data").unwrap();
+        drop(initiator);
+        let mut received = Vec::new();
+        assert_eq!(
+            responder.read_to_end(&mut received).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(received, b"data");
+    }
+
+    #[cfg(all(feature = "audit", feature = "dcap"))]
+    #[test]
+    fn audit_handshakes() {
+        use crate::audit::{verify_audit_file, FileAuditSink};
+        use crate::dcap::tests::Synthetic;
+
+        let synthetic = Arc::new(Synthetic::new(5, false));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let attestation = MutualAttestation::new(synthetic.verifier(), AppraisalPolicy::default())
+            .with_quote_generator({
+                let synthetic = synthetic.clone();
+                move |report_data: &ReportData| Ok(synthetic.quote(*report_data).to_vec())
+            })
+            .with_audit_sink(FileAuditSink::open(&path).unwrap());
+
+        let (initiator, responder) = UnixStream::pair().unwrap();
+        let responder = std::thread::spawn({
+            let attestation = attestation.clone();
+            move || attestation.accept(responder).map(|_| ())
+        });
+        attestation.connect(initiator).unwrap();
+        responder.join().unwrap().unwrap();
+
+        // Each side records the quote of its peer
+        assert_eq!(verify_audit_file(&path).unwrap().entries, 2);
+        for line in std::fs::read_to_string(&path).unwrap().lines() {
+            let line: serde_json::Value = serde_json::from_str(line).unwrap();
+            let entry = &line["entry"];
+            assert_eq!(entry["policy_id"], "default");
+            assert_eq!(entry["verdict"], "accepted");
+            assert_eq!(entry["collateral_versions"]["tcb_info"]["version"], 3);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::generator::generate_with_retry;
+use crate::ioctl::{IoctlClient, IoctlClientConfig};
+use crate::retry::RetryPolicy;
+use crate::telemetry::trace;
+use crate::{QuoteVerifier, ReportData, SGXError, SGXQuote, SGXQuoteVerifyResult};
+
+/// Handle to a pool of `/dev/sgx` clients for high-throughput services.
+///
+/// Each client of the pool owns its own device descriptor, requests are spread over the clients
+/// round-robin and issued concurrently, without locks. The handle is cheap to clone and may be
+/// shared between threads.
+///
+/// [`SGXQuote::from_report_data`] and [`SGXQuote::verify_result`] use the global client, which is
+/// also lock-free.
+///
+/// # Example
+/// ```rust ignore
+/// let client = AttestationClient::with_pool_size(4);
+/// let quote = client.generate_quote(&[0u8; 64])?;
+/// let result = client.verify_quote(&quote)?;
+/// ```
+#[derive(Clone)]
+pub struct AttestationClient {
+    clients: Arc<[IoctlClient]>,
+    next: Arc<AtomicUsize>,
+    retry_policy: RetryPolicy,
+}
+
+impl AttestationClient {
+    /// Client with a single device descriptor
+    pub fn new() -> Self {
+        Self::with_pool_size(1)
+    }
+
+    /// Client with `size` device descriptors, which are opened lazily
+    pub fn with_pool_size(size: usize) -> Self {
+        Self::with_config(IoctlClientConfig::default(), size)
+    }
+
+    /// Client of a custom LibOS device with `size` device descriptors
+    pub fn with_config(config: IoctlClientConfig, size: usize) -> Self {
+        Self {
+            clients: (0..size.max(1))
+                .map(|_| IoctlClient::new(config.clone()))
+                .collect(),
+            next: Arc::new(AtomicUsize::new(0)),
+            retry_policy: RetryPolicy::none(),
+        }
+    }
+
+    /// Retry quote generation on transient failures, no retries by default
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn client(&self) -> &IoctlClient {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+        &self.clients[index]
+    }
+
+    /// Same as [`SGXQuote::from_report_data`]
+    pub fn generate_quote(&self, data: &ReportData) -> Result<SGXQuote, SGXError> {
+        let start = Instant::now();
+        let result = generate_with_retry(self.client(), data, &self.retry_policy)?;
+        trace!("Generated quote in {:?}ms", start.elapsed().as_millis());
+        result.try_into()
+    }
+
+    /// Same as [`SGXQuote::verify_result`]
+    pub fn verify_quote(&self, quote: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> {
+        let start = Instant::now();
+        let result = self.client().verify_quote(quote);
+        trace!("Verify quote in {:?}ms", start.elapsed().as_millis());
+        result
+    }
+}
+
+impl Default for AttestationClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QuoteVerifier for AttestationClient {
+    fn verify_quote(&self, quote: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> {
+        AttestationClient::verify_quote(self, quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::thread;
+
+    use super::*;
+    use crate::tests::FakeLibOs;
+
+    #[test]
+    fn concurrent_requests() {
+        const POOL_SIZE: usize = 3;
+        let libos = FakeLibOs::new();
+        let client = AttestationClient {
+            clients: (0..POOL_SIZE).map(|_| libos.client()).collect(),
+            ..AttestationClient::with_pool_size(POOL_SIZE)
+        };
+        assert_eq!(client.pool_size(), POOL_SIZE);
+
+        let threads: Vec<_> = (0..8u8)
+            .map(|thread| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    for request in 0..16u8 {
+                        let report_data: ReportData =
+                            [thread, request].repeat(32).try_into().unwrap();
+                        let quote = client.generate_quote(&report_data).unwrap();
+                        assert_eq!(*quote.report_data(), report_data);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let generated = &libos.state().generated;
+        assert_eq!(generated.len(), 8 * 16);
+        let fds: HashSet<_> = generated.iter().map(|(fd, _)| fd).collect();
+        assert_eq!(fds.len(), POOL_SIZE);
+    }
+}
@@ -0,0 +1,220 @@
+//! IETF RATS [conceptual message wrappers] (CMW)
+//!
+//! A [`Cmw`] record carries an attestation message together with its media type, so that
+//! RATS-based verifiers can dispatch it without guessing the format. Records are encoded as a JSON
+//! array `[type, base64url(value), ind]` or a CBOR array `[type, bstr value, ind]`.
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::cmw::Cmw;
+//!
+//! let json = Cmw::from_quote(&quote).to_json();
+//! // on the verifier side
+//! let quote = Cmw::from_json(&json)?.into_quote()?;
+//! ```
+//!
+//! [conceptual message wrappers]: https://datatracker.ietf.org/doc/draft-ietf-rats-msg-wrap/
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as base64url;
+use base64::Engine;
+use ciborium::Value;
+
+use crate::error::message;
+use crate::{SGXError, SGXQuote};
+
+/// Media type of a raw SGX DCAP quote
+pub const SGX_QUOTE_MEDIA_TYPE: &str = "application/vnd.intel.sgx.quote";
+
+/// Conceptual message indicator: reference values
+pub const CMW_IND_REFERENCE_VALUES: u8 = 1 << 0;
+/// Conceptual message indicator: endorsements
+pub const CMW_IND_ENDORSEMENTS: u8 = 1 << 1;
+/// Conceptual message indicator: evidence
+pub const CMW_IND_EVIDENCE: u8 = 1 << 2;
+/// Conceptual message indicator: attestation results
+pub const CMW_IND_ATTESTATION_RESULTS: u8 = 1 << 3;
+
+/// CMW record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmw {
+    pub media_type: String,
+    pub value: Vec<u8>,
+    /// Conceptual message indicator, a combination of `CMW_IND_*`
+    pub indicator: Option<u8>,
+}
+
+fn parse_error(msg: &str) -> SGXError {
+    SGXError::CmwParseError(message(msg))
+}
+
+impl Cmw {
+    pub fn new<S: Into<String>>(media_type: S, value: Vec<u8>) -> Self {
+        Self {
+            media_type: media_type.into(),
+            value,
+            indicator: None,
+        }
+    }
+
+    /// Record of the quote as evidence
+    pub fn from_quote(quote: &SGXQuote) -> Self {
+        Self::new(SGX_QUOTE_MEDIA_TYPE, quote.to_vec()).with_indicator(CMW_IND_EVIDENCE)
+    }
+
+    pub fn with_indicator(mut self, indicator: u8) -> Self {
+        self.indicator = Some(indicator);
+        self
+    }
+
+    /// Media type without parameters, in lower case
+    pub fn essence(&self) -> String {
+        self.media_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+    }
+
+    /// The wrapped quote, fails with [`SGXError::UnexpectedMediaType`] if the record is not an SGX
+    /// quote
+    pub fn into_quote(self) -> Result<SGXQuote, SGXError> {
+        if self.essence() != SGX_QUOTE_MEDIA_TYPE {
+            return Err(SGXError::UnexpectedMediaType(self.media_type));
+        }
+        SGXQuote::try_from(self.value)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut record = vec![
+            serde_json::Value::from(self.media_type.as_str()),
+            serde_json::Value::from(base64url.encode(&self.value)),
+        ];
+        if let Some(indicator) = self.indicator {
+            record.push(indicator.into());
+        }
+        serde_json::Value::Array(record).to_string()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SGXError> {
+        let record: Vec<serde_json::Value> =
+            serde_json::from_str(json).map_err(|e| SGXError::CmwParseError(e.into()))?;
+        let (media_type, value, indicator) = match record.as_slice() {
+            [media_type, value] => (media_type, value, None),
+            [media_type, value, indicator] => (media_type, value, Some(indicator)),
+            _ => return Err(parse_error("not a CMW record")),
+        };
+
+        let media_type = media_type
+            .as_str()
+            .ok_or_else(|| parse_error("type is not a string"))?;
+        let value = value
+            .as_str()
+            .ok_or_else(|| parse_error("value is not a string"))?;
+        let value = base64url
+            .decode(value.trim_end_matches('='))
+            .map_err(|e| SGXError::CmwParseError(e.into()))?;
+        let indicator = indicator
+            .map(|indicator| {
+                indicator
+                    .as_u64()
+                    .and_then(|indicator| u8::try_from(indicator).ok())
+                    .ok_or_else(|| parse_error("bad indicator"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            media_type: media_type.to_string(),
+            value,
+            indicator,
+        })
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut record = vec![
+            Value::Text(self.media_type.clone()),
+            Value::Bytes(self.value.clone()),
+        ];
+        if let Some(indicator) = self.indicator {
+            record.push(indicator.into());
+        }
+        let mut buf = Vec::new();
+        ciborium::into_writer(&Value::Array(record), &mut buf)
+            .expect("CBOR encoding to Vec does not fail");
+        buf
+    }
+
+    /// CBOR record, the type must be a media type string, CoAP content-format numbers are not
+    /// supported
+    pub fn from_cbor(cbor: &[u8]) -> Result<Self, SGXError> {
+        let record: Value =
+            ciborium::from_reader(cbor).map_err(|e| SGXError::CmwParseError(e.into()))?;
+        let record = record
+            .into_array()
+            .map_err(|_| parse_error("not a CMW record"))?;
+        let mut record = record.into_iter();
+        let (media_type, value, indicator) = match (record.next(), record.next(), record.next()) {
+            (Some(media_type), Some(value), indicator) if record.next().is_none() => {
+                (media_type, value, indicator)
+            }
+            _ => return Err(parse_error("not a CMW record")),
+        };
+
+        let media_type = media_type
+            .into_text()
+            .map_err(|_| parse_error("type is not a media type string"))?;
+        let value = value
+            .into_bytes()
+            .map_err(|_| parse_error("value is not a byte string"))?;
+        let indicator = indicator
+            .map(|indicator| {
+                indicator
+                    .as_integer()
+                    .and_then(|indicator| u8::try_from(indicator).ok())
+                    .ok_or_else(|| parse_error("bad indicator"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            media_type,
+            value,
+            indicator,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_and_unwrap_quote() {
+        let quote = SGXQuote::from_slice(include_bytes!("../tests/fixtures/quote.raw")).unwrap();
+        let cmw = Cmw::from_quote(&quote);
+
+        let json = cmw.to_json();
+        assert!(json.starts_with(r#"["application/vnd.intel.sgx.quote",""#));
+        assert!(json.ends_with(r#"",4]"#));
+        assert_eq!(Cmw::from_json(&json).unwrap(), cmw);
+        assert_eq!(Cmw::from_cbor(&cmw.to_cbor()).unwrap(), cmw);
+        assert_eq!(
+            Cmw::from_cbor(&cmw.to_cbor())
+                .unwrap()
+                .into_quote()
+                .unwrap()
+                .as_slice(),
+            quote.as_slice()
+        );
+
+        let other = Cmw::new("application/eat+jwt", quote.to_vec());
+        assert!(matches!(
+            Cmw::from_json(&other.to_json()).unwrap().into_quote(),
+            Err(SGXError::UnexpectedMediaType(_))
+        ));
+        let with_parameters = Cmw::new("Application/Vnd.Intel.SGX.Quote; v=3", quote.to_vec());
+        assert!(with_parameters.into_quote().is_ok());
+        assert!(matches!(
+            Cmw::from_json(r#"["application/vnd.intel.sgx.quote"]"#),
+            Err(SGXError::CmwParseError(_))
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+/// TEE type of the SGX collateral
+pub const SGX_TEE_TYPE: u32 = 0x0000_0000;
+
+/// Quote verification collateral: CRLs, TCB info and QE identity with their issuer chains, as
+/// returned by PCCS.
+///
+/// RFC: "Intel SGX ECDSA QuoteLibReference DCAP API" - "sgx_ql_qve_collateral_t"
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SGXQuoteCollateral {
+    pub major_version: u16,
+    pub minor_version: u16,
+    /// See [`SGX_TEE_TYPE`]
+    pub tee_type: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub pck_crl_issuer_chain: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub root_ca_crl: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub pck_crl: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub tcb_info_issuer_chain: Vec<u8>,
+    /// Signed TCB info JSON
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub tcb_info: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub qe_identity_issuer_chain: Vec<u8>,
+    /// Signed QE identity JSON
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub qe_identity: Vec<u8>,
+}
+
+impl Default for SGXQuoteCollateral {
+    fn default() -> Self {
+        Self {
+            major_version: 3,
+            minor_version: 0,
+            tee_type: SGX_TEE_TYPE,
+            pck_crl_issuer_chain: Vec::new(),
+            root_ca_crl: Vec::new(),
+            pck_crl: Vec::new(),
+            tcb_info_issuer_chain: Vec::new(),
+            tcb_info: Vec::new(),
+            qe_identity_issuer_chain: Vec::new(),
+            qe_identity: Vec::new(),
+        }
+    }
+}
+
+/// Version of the signed TCB info or QE identity of the collateral, also deserialized from the
+/// Intel JSON field names
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CollateralVersion {
+    pub version: u32,
+    #[cfg_attr(feature = "serde", serde(alias = "issueDate"))]
+    pub issue_date: String,
+    #[cfg_attr(feature = "serde", serde(default, alias = "tcbEvaluationDataNumber"))]
+    pub tcb_evaluation_data_number: Option<u32>,
+}
+
+/// Versions of the collateral which a quote was verified with, `None` if not available
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CollateralVersions {
+    pub tcb_info: Option<CollateralVersion>,
+    pub qe_identity: Option<CollateralVersion>,
+}
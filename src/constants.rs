@@ -1,7 +1,9 @@
-const SGXIOC_GET_DCAP_QUOTE_SIZE: u64 = 0x80047307;
-const SGXIOC_GEN_DCAP_QUOTE: u64 = 0xc0187308;
-const SGXIOC_GET_DCAP_SUPPLEMENTAL_SIZE: u64 = 0x80047309;
-const SGXIOC_VER_DCAP_QUOTE: u64 = 0xc030730a;
+pub const SGXIOC_GET_DCAP_QUOTE_SIZE: u64 = 0x80047307;
+pub const SGXIOC_GEN_DCAP_QUOTE: u64 = 0xc0187308;
+pub const SGXIOC_GET_DCAP_SUPPLEMENTAL_SIZE: u64 = 0x80047309;
+pub const SGXIOC_VER_DCAP_QUOTE: u64 = 0xc030730a;
+
+pub const OCCLUM_SGX_DEVICE_PATH: &str = "/dev/sgx";
 
 pub const SGX_REPORT_DATA_SIZE: usize = 64;
 pub const SGX_CPUSVN_SIZE: usize = 16;
@@ -14,16 +16,34 @@ pub const SGX_REPORT_BODY_RESERVED4_BYTES: usize = 42;
 
 pub const SGX_HASH_SIZE: usize = 32;
 
+pub const SGX_FLAGS_INITTED: u64 = 0x0000_0000_0000_0001;
+pub const SGX_FLAGS_DEBUG: u64 = 0x0000_0000_0000_0002;
+pub const SGX_FLAGS_MODE64BIT: u64 = 0x0000_0000_0000_0004;
+pub const SGX_FLAGS_PROVISION_KEY: u64 = 0x0000_0000_0000_0010;
+pub const SGX_FLAGS_EINITTOKEN_KEY: u64 = 0x0000_0000_0000_0020;
+pub const SGX_FLAGS_KSS: u64 = 0x0000_0000_0000_0080;
+pub const SGX_FLAGS_AEXNOTIFY: u64 = 0x0000_0000_0000_0400;
+
+pub const SGX_RSA3072_KEY_SIZE: usize = 384;
+pub const SGX_SIGSTRUCT_SIZE: usize = 1808;
+
+pub const SGX_PAGE_SIZE: usize = 4096;
+/// Size of the chunk measured by a single EEXTEND
+pub const SGX_EEXTEND_SIZE: usize = 256;
+
+pub const SGX_SECINFO_R: u64 = 0x001;
+pub const SGX_SECINFO_W: u64 = 0x002;
+pub const SGX_SECINFO_X: u64 = 0x004;
+pub const SGX_SECINFO_TCS: u64 = 0x100;
+pub const SGX_SECINFO_REG: u64 = 0x200;
+
+pub const SGX_ECDSA_SIGNATURE_SIZE: usize = 64;
+pub const SGX_ECDSA_PUBLIC_KEY_SIZE: usize = 64;
+
 cfg_if::cfg_if! {
   if #[cfg(target_env = "musl")] {
-      pub const IOCTL_GET_DCAP_QUOTE_SIZE: i32 = SGXIOC_GET_DCAP_QUOTE_SIZE as i32;
-      pub const IOCTL_GEN_DCAP_QUOTE: i32 = SGXIOC_GEN_DCAP_QUOTE as i32;
-      pub const IOCTL_GET_DCAP_SUPPLEMENTAL_SIZE: i32 = SGXIOC_GET_DCAP_SUPPLEMENTAL_SIZE as i32;
-      pub const IOCTL_VER_DCAP_QUOTE: i32 = SGXIOC_VER_DCAP_QUOTE as i32;
+      pub type IoctlRequest = i32;
   } else {
-      pub const IOCTL_GET_DCAP_QUOTE_SIZE: u64 = SGXIOC_GET_DCAP_QUOTE_SIZE;
-      pub const IOCTL_GEN_DCAP_QUOTE: u64 = SGXIOC_GEN_DCAP_QUOTE;
-      pub const IOCTL_GET_DCAP_SUPPLEMENTAL_SIZE: u64 = SGXIOC_GET_DCAP_SUPPLEMENTAL_SIZE;
-      pub const IOCTL_VER_DCAP_QUOTE: u64 = SGXIOC_VER_DCAP_QUOTE;
+      pub type IoctlRequest = u64;
   }
 }
@@ -0,0 +1,1257 @@
+//! Pure-Rust quote verification with Intel DCAP collateral
+//!
+//! [`DcapVerifier`] checks the quote the same way as the Intel Quote Verification Library, without
+//! the SGX SDK on the verifier host:
+//!
+//! - the PCK certificate chain up to the trusted root and the CRLs
+//! - the QE report signature, the QE report data binding and the quote signature
+//! - the QE identity and the TCB level of the platform, which gives the [`SGXQuoteVerifyResult`]
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::dcap::DcapVerifier;
+//! use occlum_sgx::pccs::PccsClient;
+//!
+//! let verifier = DcapVerifier::new(PccsClient::new("https://pccs.example.com:8081"));
+//! let result = verifier.verify_quote(&quote)?;
+//! ```
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use x509_parser::oid_registry::OID_SIG_ECDSA_WITH_SHA256;
+use x509_parser::prelude::{ASN1Time, FromDer, X509Certificate};
+use x509_parser::revocation_list::CertificateRevocationList;
+use x509_parser::x509::{AlgorithmIdentifier, SubjectPublicKeyInfo};
+
+use crate::der::pem_to_der;
+use crate::error::message;
+use crate::instrument;
+use crate::signature::SGXQuoteSignature;
+use crate::telemetry::{self, debug, warn};
+use crate::types::SGXQuoteHeader;
+use crate::{
+    CollateralVersions, QuoteVerifier, SGXError, SGXQuote, SGXQuoteCollateral,
+    SGXQuoteVerifyResult, SGXReportBody,
+};
+
+/// Intel SGX Root CA, the default trust anchor of the PCK certificates and the collateral
+pub const INTEL_SGX_ROOT_CA_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIICjzCCAjSgAwIBAgIUImUM1lqdNInzg7SVUr9QGzknBqwwCgYIKoZIzj0EAwIw
+aDEaMBgGA1UEAwwRSW50ZWwgU0dYIFJvb3QgQ0ExGjAYBgNVBAoMEUludGVsIENv
+cnBvcmF0aW9uMRQwEgYDVQQHDAtTYW50YSBDbGFyYTELMAkGA1UECAwCQ0ExCzAJ
+BgNVBAYTAlVTMB4XDTE4MDUyMTEwNDUxMFoXDTQ5MTIzMTIzNTk1OVowaDEaMBgG
+A1UEAwwRSW50ZWwgU0dYIFJvb3QgQ0ExGjAYBgNVBAoMEUludGVsIENvcnBvcmF0
+aW9uMRQwEgYDVQQHDAtTYW50YSBDbGFyYTELMAkGA1UECAwCQ0ExCzAJBgNVBAYT
+AlVTMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEC6nEwMDIYZOj/iPWsCzaEKi7
+1OiOSLRFhWGjbnBVJfVnkY4u3IjkDYYL0MxO4mqsyYjlBalTVYxFP2sJBK5zlKOB
+uzCBuDAfBgNVHSMEGDAWgBQiZQzWWp00ifODtJVSv1AbOScGrDBSBgNVHR8ESzBJ
+MEegRaBDhkFodHRwczovL2NlcnRpZmljYXRlcy50cnVzdGVkc2VydmljZXMuaW50
+ZWwuY29tL0ludGVsU0dYUm9vdENBLmRlcjAdBgNVHQ4EFgQUImUM1lqdNInzg7SV
+Ur9QGzknBqwwDgYDVR0PAQH/BAQDAgEGMBIGA1UdEwEB/wQIMAYBAf8CAQEwCgYI
+KoZIzj0EAwIDSQAwRgIhAOW/5QkR+S9CiSDcNoowLuPRLsWGf/Yi7GSX94BgwTwg
+AiEA4J0lrHoMs+Xo5o/sX6O9QWxHRAvZUGOdRQ7cvqRXaqI=
+-----END CERTIFICATE-----
+";
+
+const QUOTE_VERSION: u16 = 3;
+const ATT_KEY_TYPE_ECDSA_P256: u16 = 2;
+/// `qe_vendor_id` of the quotes generated by the Intel QE
+const INTEL_QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9A, 0x72, 0x33, 0xF7, 0x9C, 0x4C, 0xA9, 0x94, 0x0A, 0x0D, 0xB3, 0x95, 0x7F, 0x06, 0x07,
+];
+
+/// Intermediate CA which issued the PCK certificate, selects the PCK CRL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PckCa {
+    Processor,
+    Platform,
+}
+
+impl PckCa {
+    /// Value of the `ca` parameter of the PCCS `pckcrl` request
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PckCa::Processor => "processor",
+            PckCa::Platform => "platform",
+        }
+    }
+}
+
+/// Source of the verification collateral, e.g. PCCS
+pub trait CollateralProvider: Send + Sync {
+    fn collateral(&self, fmspc: &[u8], ca: PckCa) -> Result<SGXQuoteCollateral, SGXError>;
+}
+
+/// Shared provider, e.g. a [`PccsClient`](crate::pccs::PccsClient) with one cache for several
+/// consumers
+impl<C: CollateralProvider + ?Sized> CollateralProvider for Arc<C> {
+    fn collateral(&self, fmspc: &[u8], ca: PckCa) -> Result<SGXQuoteCollateral, SGXError> {
+        C::collateral(self, fmspc, ca)
+    }
+}
+
+/// Fixed collateral, e.g. fetched ahead of time for offline verification
+impl CollateralProvider for SGXQuoteCollateral {
+    fn collateral(&self, _fmspc: &[u8], _ca: PckCa) -> Result<SGXQuoteCollateral, SGXError> {
+        Ok(self.clone())
+    }
+}
+
+/// Outcome of [`DcapVerifier::verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DcapVerification {
+    pub result: SGXQuoteVerifyResult,
+    /// `true` if a certificate, CRL, TCB info or QE identity was not valid at the expiration check
+    /// time
+    pub collateral_expired: bool,
+    /// Intel Security Advisory IDs of the matching TCB level
+    pub advisory_ids: Vec<String>,
+    /// Versions of the TCB info and QE identity the quote was verified with
+    pub collateral_versions: CollateralVersions,
+}
+
+impl SGXQuoteCollateral {
+    /// Versions of the TCB info and QE identity, `None` if they are not signed JSON
+    pub fn versions(&self) -> CollateralVersions {
+        let version = |json: &[u8], body: &str| {
+            let json = json.strip_suffix(&[0]).unwrap_or(json);
+            let mut signed: HashMap<String, serde_json::Value> =
+                serde_json::from_slice(json).ok()?;
+            serde_json::from_value(signed.remove(body)?).ok()
+        };
+        CollateralVersions {
+            tcb_info: version(&self.tcb_info, "tcbInfo"),
+            qe_identity: version(&self.qe_identity, "enclaveIdentity"),
+        }
+    }
+}
+
+/// Verifies quotes with the collateral of the [`CollateralProvider`], see the [module
+/// documentation](self).
+pub struct DcapVerifier {
+    trusted_root: Vec<u8>,
+    collateral: Arc<dyn CollateralProvider>,
+    expiration_time: Option<SystemTime>,
+}
+
+fn collateral_error(msg: &str) -> SGXError {
+    SGXError::CollateralParseError(message(msg))
+}
+
+/// Certificates of a PEM chain, in order
+fn pem_chain(pem: &[u8]) -> Result<Vec<Vec<u8>>, SGXError> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = std::str::from_utf8(pem).map_err(|e| collateral_error(&e.to_string()))?;
+    pem.split_inclusive(END)
+        .filter(|cert| cert.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|cert| Ok(pem_to_der(cert)?.1))
+        .collect()
+}
+
+/// DER of a CRL given as PEM, hex-encoded DER (PCCS) or raw DER
+fn crl_der(crl: &[u8]) -> Result<Vec<u8>, SGXError> {
+    // Only the text forms are NUL-terminated, a DER CRL may end with a zero byte
+    let text = crl.strip_suffix(&[0]).unwrap_or(crl);
+    if text.starts_with(b"-----BEGIN") {
+        let pem = std::str::from_utf8(text).map_err(|e| collateral_error(&e.to_string()))?;
+        Ok(pem_to_der(pem)?.1)
+    } else if !text.is_empty() && text.iter().all(u8::is_ascii_hexdigit) {
+        hex::decode(text).map_err(|e| collateral_error(&e.to_string()))
+    } else {
+        Ok(crl.to_vec())
+    }
+}
+
+fn parse_certificate(der: &[u8]) -> Result<X509Certificate<'_>, SGXError> {
+    X509Certificate::from_der(der)
+        .map(|(_, cert)| cert)
+        .map_err(|e| SGXError::CertificateParseError(e.into()))
+}
+
+fn parse_crl(der: &[u8]) -> Result<CertificateRevocationList<'_>, SGXError> {
+    CertificateRevocationList::from_der(der)
+        .map(|(_, crl)| crl)
+        .map_err(|e| collateral_error(&e.to_string()))
+}
+
+/// Whether the certificate is a CA allowing `path_len` intermediate CAs below it
+fn is_ca(cert: &X509Certificate, path_len: u32) -> bool {
+    match cert.basic_constraints() {
+        Ok(Some(constraints)) => {
+            constraints.value.ca
+                && constraints
+                    .value
+                    .path_len_constraint
+                    .is_none_or(|max| path_len <= max)
+        }
+        _ => false,
+    }
+}
+
+fn public_key(spki: &SubjectPublicKeyInfo) -> Option<VerifyingKey> {
+    VerifyingKey::from_sec1_bytes(&spki.subject_public_key.data).ok()
+}
+
+/// ECDSA P-256 signature in the raw `r || s` form of the quote and the collateral
+fn verify_raw(key: &VerifyingKey, message: &[u8], signature: &[u8]) -> bool {
+    Signature::from_slice(signature).is_ok_and(|signature| key.verify(message, &signature).is_ok())
+}
+
+/// ECDSA-with-SHA256 signature in the DER form of the certificates and CRLs
+fn verify_der(
+    issuer: &X509Certificate,
+    message: &[u8],
+    algorithm: &AlgorithmIdentifier,
+    signature: &[u8],
+) -> bool {
+    algorithm.algorithm == OID_SIG_ECDSA_WITH_SHA256
+        && public_key(issuer.public_key()).is_some_and(|key| {
+            Signature::from_der(signature).is_ok_and(|sig| key.verify(message, &sig).is_ok())
+        })
+}
+
+/// Seconds since the UNIX epoch of a `YYYY-MM-DDTHH:MM:SSZ` timestamp
+fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let b = timestamp.as_bytes();
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || b[10] != b'T' || !timestamp.ends_with('Z') {
+        return None;
+    }
+    let n = |range: std::ops::Range<usize>| timestamp.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (n(0..4)?, n(5..7)?, n(8..10)?);
+    let (hour, minute, second) = (n(11..13)?, n(14..16)?, n(17..19)?);
+
+    // Days from civil, http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TcbInfo {
+    version: u32,
+    next_update: String,
+    fmspc: String,
+    pce_id: String,
+    tcb_levels: Vec<TcbLevel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TcbLevel {
+    tcb: Tcb,
+    tcb_status: String,
+    #[serde(default, rename = "advisoryIDs")]
+    advisory_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Tcb {
+    sgxtcbcomponents: Vec<TcbComponent>,
+    pcesvn: u16,
+}
+
+#[derive(Deserialize)]
+struct TcbComponent {
+    svn: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QeIdentity {
+    next_update: String,
+    miscselect: String,
+    miscselect_mask: String,
+    attributes: String,
+    attributes_mask: String,
+    mrsigner: String,
+    isvprodid: u16,
+    tcb_levels: Vec<QeTcbLevel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QeTcbLevel {
+    tcb: QeTcb,
+    tcb_status: String,
+}
+
+#[derive(Deserialize)]
+struct QeTcb {
+    isvsvn: u16,
+}
+
+/// State of a single verification
+struct Verification<'a> {
+    root: X509Certificate<'a>,
+    now: ASN1Time,
+    now_secs: i64,
+    expired: bool,
+}
+
+/// Early exit with a terminal result
+type Outcome<T> = Result<Result<T, SGXQuoteVerifyResult>, SGXError>;
+
+impl<'a> Verification<'a> {
+    fn check_validity(&mut self, cert: &X509Certificate) {
+        if !cert.validity().is_valid_at(self.now) {
+            debug!(
+                "Certificate {} is not valid at {}",
+                cert.subject(),
+                self.now
+            );
+            self.expired = true;
+        }
+    }
+
+    fn check_next_update(&mut self, what: &str, next_update: &str) -> Result<(), SGXError> {
+        let next_update =
+            parse_timestamp(next_update).ok_or_else(|| collateral_error("bad nextUpdate"))?;
+        if next_update < self.now_secs {
+            debug!("{} expired", what);
+            self.expired = true;
+        }
+        Ok(())
+    }
+
+    /// Verifies the chain (leaf first) up to the trusted root, the root may be included. The leaf
+    /// must be an end-entity certificate and every issuer a CA allowing the length of the path
+    /// below it.
+    fn verify_chain(&mut self, chain: &[X509Certificate]) -> bool {
+        let chain = match chain.last() {
+            Some(last) if last.subject().as_raw() == self.root.subject().as_raw() => {
+                if last.as_ref() != self.root.as_ref() {
+                    return false;
+                }
+                &chain[..chain.len() - 1]
+            }
+            _ => chain,
+        };
+        let Some(leaf) = chain.first() else {
+            return false;
+        };
+        if is_ca(leaf, 0) {
+            debug!("Certificate {} is not an end-entity", leaf.subject());
+            return false;
+        }
+
+        for (i, cert) in chain.iter().enumerate() {
+            let issuer = chain.get(i + 1).unwrap_or(&self.root);
+            // `i` intermediate CAs are between the issuer and the leaf
+            if !is_ca(issuer, i as u32) {
+                debug!(
+                    "Certificate {} is not a CA for a path of {}",
+                    issuer.subject(),
+                    i
+                );
+                return false;
+            }
+            if cert.issuer().as_raw() != issuer.subject().as_raw()
+                || !verify_der(
+                    issuer,
+                    cert.tbs_certificate.as_ref(),
+                    &cert.signature_algorithm,
+                    &cert.signature_value.data,
+                )
+            {
+                debug!(
+                    "Certificate {} is not signed by {}",
+                    cert.subject(),
+                    issuer.subject()
+                );
+                return false;
+            }
+            self.check_validity(cert);
+        }
+        let root = self.root.clone();
+        self.check_validity(&root);
+        true
+    }
+
+    /// Verifies the CRL signature by `issuer`, returns `true` if the certificate is revoked
+    fn is_revoked(
+        &mut self,
+        crl: &CertificateRevocationList,
+        issuer: &X509Certificate,
+        cert: &X509Certificate,
+    ) -> Result<bool, SGXError> {
+        if crl.issuer().as_raw() != issuer.subject().as_raw()
+            || !verify_der(
+                issuer,
+                crl.tbs_cert_list.as_ref(),
+                &crl.signature_algorithm,
+                &crl.signature_value.data,
+            )
+        {
+            return Err(collateral_error(&format!(
+                "CRL is not signed by {}",
+                issuer.subject()
+            )));
+        }
+        if crl.next_update().is_some_and(|next| next < self.now) {
+            debug!("CRL of {} expired", issuer.subject());
+            self.expired = true;
+        }
+        Ok(crl
+            .iter_revoked_certificates()
+            .any(|revoked| revoked.raw_serial() == cert.raw_serial()))
+    }
+
+    /// Verifies the signed collateral `{"<body>": {...}, "signature": "<hex r || s>"}`, returns the
+    /// body
+    fn signed_body<T: serde::de::DeserializeOwned>(
+        &mut self,
+        json: &[u8],
+        body: &str,
+        chain: &[X509Certificate],
+        root_crl: &CertificateRevocationList,
+    ) -> Outcome<T> {
+        let json = json.strip_suffix(&[0]).unwrap_or(json);
+        let signed: HashMap<String, &RawValue> =
+            serde_json::from_slice(json).map_err(|e| collateral_error(&e.to_string()))?;
+        let (Some(value), Some(signature)) = (signed.get(body), signed.get("signature")) else {
+            return Err(collateral_error(&format!("{} is not signed", body)));
+        };
+        let signature: String =
+            serde_json::from_str(signature.get()).map_err(|e| collateral_error(&e.to_string()))?;
+        let signature = hex::decode(signature).map_err(|e| collateral_error(&e.to_string()))?;
+
+        let signer = chain
+            .first()
+            .ok_or_else(|| collateral_error("empty issuer chain"))?;
+        if !self.verify_chain(chain) {
+            warn!("{} issuer chain is not trusted", body);
+            return Ok(Err(SGXQuoteVerifyResult::InvalidSignature));
+        }
+        let root = self.root.clone();
+        if self.is_revoked(root_crl, &root, signer)? {
+            return Ok(Err(SGXQuoteVerifyResult::Revoked));
+        }
+        let key =
+            public_key(signer.public_key()).ok_or_else(|| collateral_error("bad signing key"))?;
+        if !verify_raw(&key, value.get().as_bytes(), &signature) {
+            warn!("{} signature is invalid", body);
+            return Ok(Err(SGXQuoteVerifyResult::InvalidSignature));
+        }
+
+        serde_json::from_str(value.get())
+            .map(Ok)
+            .map_err(|e| collateral_error(&e.to_string()))
+    }
+}
+
+/// Only the ECDSA-256 quotes v3 of the Intel QE are supported
+fn check_header(header: &SGXQuoteHeader) -> Result<(), SGXError> {
+    let unsupported = |msg: String| Err(SGXError::UnsupportedQuoteFormat(msg));
+    if header.version != QUOTE_VERSION {
+        return unsupported(format!("version {}", header.version));
+    }
+    if header.att_key_type != ATT_KEY_TYPE_ECDSA_P256 {
+        return unsupported(format!("attestation key type {}", header.att_key_type));
+    }
+    if header.vendor_id != INTEL_QE_VENDOR_ID {
+        return unsupported(format!("QE vendor {}", hex::encode(header.vendor_id)));
+    }
+    Ok(())
+}
+
+fn hex_u32(s: &str) -> Result<u32, SGXError> {
+    u32::from_str_radix(s, 16).map_err(|e| collateral_error(&e.to_string()))
+}
+
+fn hex_bytes(s: &str) -> Result<Vec<u8>, SGXError> {
+    hex::decode(s).map_err(|e| collateral_error(&e.to_string()))
+}
+
+fn masked_eq(a: &[u8], b: &[u8], mask: &[u8]) -> bool {
+    a.len() == mask.len()
+        && b.len() == mask.len()
+        && a.iter().zip(b).zip(mask).all(|((a, b), m)| a & m == b & m)
+}
+
+/// QE identity check, returns the QE TCB status
+fn qe_identity_status(
+    identity: &QeIdentity,
+    qe_report: &SGXReportBody,
+) -> Result<SGXQuoteVerifyResult, SGXError> {
+    let mask = hex_u32(&identity.miscselect_mask)?;
+    let mut attributes = qe_report.attributes.flags.to_le_bytes().to_vec();
+    attributes.extend_from_slice(&qe_report.attributes.xfrm.to_le_bytes());
+
+    if qe_report.misc_select & mask != hex_u32(&identity.miscselect)? & mask
+        || !masked_eq(
+            &attributes,
+            &hex_bytes(&identity.attributes)?,
+            &hex_bytes(&identity.attributes_mask)?,
+        )
+        || qe_report.mr_signer[..] != hex_bytes(&identity.mrsigner)?
+        || qe_report.isv_prod_id != identity.isvprodid
+    {
+        warn!("QE report does not match the QE identity");
+        return Ok(SGXQuoteVerifyResult::InvalidSignature);
+    }
+
+    Ok(identity
+        .tcb_levels
+        .iter()
+        .find(|level| qe_report.isv_svn >= level.tcb.isvsvn)
+        .and_then(|level| SGXQuoteVerifyResult::from_tcb_status(&level.tcb_status))
+        .unwrap_or(SGXQuoteVerifyResult::OutOfDate))
+}
+
+/// Combined status of the platform TCB and the QE TCB
+fn converge(tcb: SGXQuoteVerifyResult, qe: SGXQuoteVerifyResult) -> SGXQuoteVerifyResult {
+    use SGXQuoteVerifyResult::*;
+    match (qe, tcb) {
+        (Revoked, _) => Revoked,
+        (OutOfDate, Ok | SwHardeningNeeded) => OutOfDate,
+        (OutOfDate, ConfigNeeded | ConfigAndSwHardeningNeeded) => OutOfDateConfigNeeded,
+        (_, tcb) => tcb,
+    }
+}
+
+impl DcapVerifier {
+    /// Verifier with the collateral of `collateral`, trusting the Intel SGX Root CA
+    pub fn new<C: CollateralProvider + 'static>(collateral: C) -> Self {
+        Self {
+            trusted_root: pem_to_der(INTEL_SGX_ROOT_CA_PEM)
+                .expect("valid Intel SGX Root CA")
+                .1,
+            collateral: Arc::new(collateral),
+            expiration_time: None,
+        }
+    }
+
+    /// Trust another root CA instead of the Intel SGX Root CA, e.g. in tests
+    pub fn with_trusted_root_pem(mut self, pem: &str) -> Result<Self, SGXError> {
+        let der = pem_to_der(pem)?.1;
+        parse_certificate(&der)?;
+        self.trusted_root = der;
+        Ok(self)
+    }
+
+    /// Time to check the certificates and the collateral against, the current time by default
+    pub fn with_expiration_time(mut self, expiration_time: SystemTime) -> Self {
+        self.expiration_time = Some(expiration_time);
+        self
+    }
+
+    /// Verify the quote with the collateral of the provider
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "verify_quote",
+            skip_all,
+            fields(backend = "dcap", quote_hash, mrenclave, fmspc, result, error)
+        )
+    )]
+    pub fn verify(&self, quote: &SGXQuote) -> Result<DcapVerification, SGXError> {
+        telemetry::record_quote(quote);
+        let start = Instant::now();
+        let verification = self.fetch_and_verify(quote);
+        instrument::quote_verified("dcap", start, verification.as_ref().map(|v| v.result));
+        telemetry::record_result(verification.as_ref().map(|v| v.result));
+        verification
+    }
+
+    fn fetch_and_verify(&self, quote: &SGXQuote) -> Result<DcapVerification, SGXError> {
+        check_header(&quote.header())?;
+        let signature = quote.signature()?;
+        let chain = self.pck_chain(&signature)?;
+        let intermediate = parse_certificate(chain.get(1).ok_or_else(|| {
+            SGXError::CertificateParseError(message("PCK certificate chain has no CA"))
+        })?)?;
+        let ca = if intermediate
+            .subject()
+            .iter_common_name()
+            .any(|cn| cn.as_str().is_ok_and(|cn| cn.contains("Processor")))
+        {
+            PckCa::Processor
+        } else {
+            PckCa::Platform
+        };
+
+        let fmspc = signature.pck_extensions()?.fmspc;
+        telemetry::record("fmspc", &hex::encode(&fmspc));
+        let collateral = self.collateral.collateral(&fmspc, ca)?;
+        self.verify_collateral(
+            quote,
+            &collateral,
+            self.expiration_time.unwrap_or_else(SystemTime::now),
+        )
+    }
+
+    fn pck_chain(&self, signature: &SGXQuoteSignature) -> Result<Vec<Vec<u8>>, SGXError> {
+        let chain = signature
+            .certification_data
+            .pck_cert_chain()
+            .ok_or_else(|| {
+                SGXError::CertificateParseError(message("quote has no PCK certificate chain"))
+            })?;
+        chain.iter().map(|cert| Ok(pem_to_der(cert)?.1)).collect()
+    }
+
+    /// Verify the quote with the caller-supplied collateral and expiration check time
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "verify_quote",
+            skip_all,
+            fields(backend = "dcap", quote_hash, mrenclave, result, error)
+        )
+    )]
+    pub fn verify_with_collateral(
+        &self,
+        quote: &SGXQuote,
+        collateral: &SGXQuoteCollateral,
+        expiration_time: SystemTime,
+    ) -> Result<DcapVerification, SGXError> {
+        telemetry::record_quote(quote);
+        let start = Instant::now();
+        let verification = check_header(&quote.header())
+            .and_then(|()| self.verify_collateral(quote, collateral, expiration_time));
+        instrument::quote_verified("dcap", start, verification.as_ref().map(|v| v.result));
+        telemetry::record_result(verification.as_ref().map(|v| v.result));
+        verification
+    }
+
+    fn verify_collateral(
+        &self,
+        quote: &SGXQuote,
+        collateral: &SGXQuoteCollateral,
+        expiration_time: SystemTime,
+    ) -> Result<DcapVerification, SGXError> {
+        let now_secs = expiration_time
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs() as i64)
+            .unwrap_or_default();
+        let mut verification = Verification {
+            root: parse_certificate(&self.trusted_root)?,
+            now: ASN1Time::from_timestamp(now_secs)
+                .map_err(|e| SGXError::CertificateParseError(e.into()))?,
+            now_secs,
+            expired: false,
+        };
+
+        let (result, advisory_ids) = match self.check(quote, collateral, &mut verification)? {
+            Ok(outcome) => outcome,
+            Err(result) => (result, Vec::new()),
+        };
+        Ok(DcapVerification {
+            result,
+            collateral_expired: verification.expired,
+            advisory_ids,
+            collateral_versions: collateral.versions(),
+        })
+    }
+
+    fn check(
+        &self,
+        quote: &SGXQuote,
+        collateral: &SGXQuoteCollateral,
+        verification: &mut Verification,
+    ) -> Outcome<(SGXQuoteVerifyResult, Vec<String>)> {
+        let signature = quote.signature()?;
+
+        // PCK certificate chain and CRLs
+        let pck_chain = self.pck_chain(&signature)?;
+        let pck_chain = pck_chain
+            .iter()
+            .map(|der| parse_certificate(der))
+            .collect::<Result<Vec<_>, _>>()?;
+        // PCK, PCK platform or processor CA and the root CA
+        if pck_chain.len() != 3
+            || pck_chain[2].as_ref() != verification.root.as_ref()
+            || !verification.verify_chain(&pck_chain)
+        {
+            warn!("PCK certificate chain is not trusted");
+            return Ok(Err(SGXQuoteVerifyResult::InvalidSignature));
+        }
+        let (pck, pck_ca) = (&pck_chain[0], &pck_chain[1]);
+
+        let root_crl = crl_der(&collateral.root_ca_crl)?;
+        let root_crl = parse_crl(&root_crl)?;
+        let pck_crl = crl_der(&collateral.pck_crl)?;
+        let pck_crl = parse_crl(&pck_crl)?;
+        let root = verification.root.clone();
+        if verification.is_revoked(&root_crl, &root, pck_ca)?
+            || verification.is_revoked(&pck_crl, pck_ca, pck)?
+        {
+            warn!("PCK certificate is revoked");
+            return Ok(Err(SGXQuoteVerifyResult::Revoked));
+        }
+
+        // QE report and quote signatures
+        let pck_key =
+            public_key(pck.public_key()).ok_or_else(|| collateral_error("bad PCK public key"))?;
+        if !verify_raw(
+            &pck_key,
+            signature.qe_report.as_bytes(),
+            &signature.qe_report_signature,
+        ) {
+            warn!("QE report signature is invalid");
+            return Ok(Err(SGXQuoteVerifyResult::InvalidSignature));
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(signature.attestation_key);
+        hasher.update(&signature.qe_auth_data);
+        let report_data = &signature.qe_report.report_data;
+        if report_data[..32] != hasher.finalize()[..] || report_data[32..] != [0u8; 32] {
+            warn!("QE report data does not match the attestation key");
+            return Ok(Err(SGXQuoteVerifyResult::InvalidSignature));
+        }
+
+        let mut attestation_key = vec![0x04];
+        attestation_key.extend_from_slice(&signature.attestation_key);
+        let attestation_key = VerifyingKey::from_sec1_bytes(&attestation_key)
+            .map_err(|e| SGXError::CertificateParseError(e.into()))?;
+        let signed_len =
+            std::mem::size_of::<SGXQuoteHeader>() + std::mem::size_of::<SGXReportBody>();
+        if !verify_raw(&attestation_key, &quote[..signed_len], &signature.signature) {
+            warn!("Quote signature is invalid");
+            return Ok(Err(SGXQuoteVerifyResult::InvalidSignature));
+        }
+
+        // QE identity
+        let chain = pem_chain(&collateral.qe_identity_issuer_chain)?;
+        let chain = chain
+            .iter()
+            .map(|der| parse_certificate(der))
+            .collect::<Result<Vec<_>, _>>()?;
+        let identity: QeIdentity = match verification.signed_body(
+            &collateral.qe_identity,
+            "enclaveIdentity",
+            &chain,
+            &root_crl,
+        )? {
+            Ok(identity) => identity,
+            Err(result) => return Ok(Err(result)),
+        };
+        verification.check_next_update("QE identity", &identity.next_update)?;
+        let qe_status = qe_identity_status(&identity, &signature.qe_report)?;
+        if qe_status == SGXQuoteVerifyResult::InvalidSignature {
+            return Ok(Err(qe_status));
+        }
+
+        // TCB info
+        let chain = pem_chain(&collateral.tcb_info_issuer_chain)?;
+        let chain = chain
+            .iter()
+            .map(|der| parse_certificate(der))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tcb_info: TcbInfo =
+            match verification.signed_body(&collateral.tcb_info, "tcbInfo", &chain, &root_crl)? {
+                Ok(tcb_info) => tcb_info,
+                Err(result) => return Ok(Err(result)),
+            };
+        if tcb_info.version != 3 {
+            return Err(collateral_error(&format!(
+                "unsupported TCB info version {}",
+                tcb_info.version
+            )));
+        }
+        verification.check_next_update("TCB info", &tcb_info.next_update)?;
+
+        let extensions = signature.pck_extensions()?;
+        if hex_bytes(&tcb_info.fmspc)? != extensions.fmspc
+            || hex_bytes(&tcb_info.pce_id)? != extensions.pce_id
+        {
+            return Err(collateral_error("TCB info does not match the PCK FMSPC"));
+        }
+
+        let tcb = extensions.tcb;
+        let level = tcb_info.tcb_levels.into_iter().find(|level| {
+            level.tcb.sgxtcbcomponents.len() == tcb.comp_svn.len()
+                && level
+                    .tcb
+                    .sgxtcbcomponents
+                    .iter()
+                    .zip(tcb.comp_svn)
+                    .all(|(component, svn)| svn >= component.svn)
+                && tcb.pce_svn >= level.tcb.pcesvn
+        });
+        let Some(level) = level else {
+            warn!("TCB level of the platform is not supported");
+            return Ok(Err(SGXQuoteVerifyResult::Unspecified));
+        };
+        let tcb_status = SGXQuoteVerifyResult::from_tcb_status(&level.tcb_status)
+            .ok_or_else(|| collateral_error(&format!("unknown TCB status {}", level.tcb_status)))?;
+
+        Ok(Ok((converge(tcb_status, qe_status), level.advisory_ids)))
+    }
+}
+
+impl QuoteVerifier for DcapVerifier {
+    /// Fails with [`SGXError::CollateralExpired`] if the collateral expired
+    fn verify_quote(&self, quote: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> {
+        self.verify_quote_with_versions(quote)
+            .map(|(result, _)| result)
+    }
+
+    fn verify_quote_with_versions(
+        &self,
+        quote: &SGXQuote,
+    ) -> Result<(SGXQuoteVerifyResult, CollateralVersions), SGXError> {
+        let verification = self.verify(quote)?;
+        if verification.collateral_expired {
+            return Err(SGXError::CollateralExpired);
+        }
+        Ok((verification.result, verification.collateral_versions))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::SigningKey;
+    use p256::pkcs8::DecodePrivateKey;
+    use rcgen::{
+        BasicConstraints, CertificateParams, CertificateRevocationListParams, CustomExtension,
+        DistinguishedName, DnType, IsCa, KeyIdMethod, KeyPair, RevokedCertParams, SerialNumber,
+        PKCS_ECDSA_P256_SHA256,
+    };
+
+    use super::*;
+    use crate::tests::REPORT_DATA_OFFSET;
+    use crate::{CollateralVersion, SGXMeasurement};
+
+    pub(crate) const FMSPC: [u8; 6] = [0x00, 0x90, 0x6E, 0xD5, 0x00, 0x00];
+    const QE_MRSIGNER: [u8; 32] = [0x8C; 32];
+
+    fn der(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if value.len() < 0x80 {
+            out.push(value.len() as u8);
+        } else {
+            out.push(0x82);
+            out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        }
+        out.extend_from_slice(value);
+        out
+    }
+
+    fn sgx_entry(id: &[u8], value: Vec<u8>) -> Vec<u8> {
+        let oid = [
+            &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01][..],
+            id,
+        ]
+        .concat();
+        der(0x30, &[der(0x06, &oid), value].concat())
+    }
+
+    /// Value of the SGX extension of the PCK certificate
+    fn sgx_extension(comp_svn: u8, pce_svn: u8) -> Vec<u8> {
+        let mut tcb: Vec<u8> = (1..=16)
+            .flat_map(|n| sgx_entry(&[2, n], der(0x02, &[comp_svn])))
+            .collect();
+        tcb.extend(sgx_entry(&[2, 17], der(0x02, &[pce_svn])));
+        tcb.extend(sgx_entry(&[2, 18], der(0x04, &[comp_svn; 16])));
+
+        der(
+            0x30,
+            &[
+                sgx_entry(&[1], der(0x04, &[0x11; 16])),
+                sgx_entry(&[2], der(0x30, &tcb)),
+                sgx_entry(&[3], der(0x04, &[0, 0])),
+                sgx_entry(&[4], der(0x04, &FMSPC)),
+                sgx_entry(&[5], der(0x0A, &[0])),
+            ]
+            .concat(),
+        )
+    }
+
+    fn ca_params(name: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, name);
+        params.distinguished_name = dn;
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.not_before = rcgen::date_time_ymd(2020, 1, 1);
+        params.not_after = rcgen::date_time_ymd(2049, 1, 1);
+        params
+    }
+
+    fn signed_json(key: &KeyPair, name: &str, body: serde_json::Value) -> Vec<u8> {
+        let body = body.to_string();
+        let key = SigningKey::from_pkcs8_der(&key.serialize_der()).unwrap();
+        let signature: Signature = key.sign(body.as_bytes());
+        format!(
+            r#"{{"{}":{},"signature":"{}"}}"#,
+            name,
+            body,
+            hex::encode(signature.to_bytes())
+        )
+        .into_bytes()
+    }
+
+    /// Test PKI and collateral, with an `UpToDate` TCB level at SVN 5 and an `OutOfDate`
+    /// level at SVN 3
+    pub(crate) struct Synthetic {
+        pub root_pem: String,
+        pub collateral: SGXQuoteCollateral,
+        pck_signing: SigningKey,
+        chain: String,
+        comp_svn: u8,
+    }
+
+    impl Synthetic {
+        pub(crate) fn new(comp_svn: u8, revoked: bool) -> Self {
+            let root_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let root = ca_params("Test SGX Root CA")
+                .self_signed(&root_key)
+                .unwrap();
+            let ca_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let ca = ca_params("Test SGX PCK Platform CA")
+                .signed_by(&ca_key, &root, &root_key)
+                .unwrap();
+
+            let pck_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let mut pck = ca_params("Test SGX PCK Certificate");
+            pck.is_ca = IsCa::NoCa;
+            pck.serial_number = Some(SerialNumber::from_slice(&[0x42]));
+            pck.custom_extensions = vec![CustomExtension::from_oid_content(
+                &[1, 2, 840, 113741, 1, 13, 1],
+                sgx_extension(comp_svn, 11),
+            )];
+            let pck = pck.signed_by(&pck_key, &ca, &ca_key).unwrap();
+
+            let tcb_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let mut tcb_signing = ca_params("Test SGX TCB Signing");
+            tcb_signing.is_ca = IsCa::NoCa;
+            let tcb_signing = tcb_signing.signed_by(&tcb_key, &root, &root_key).unwrap();
+
+            let crl = |revoked_certs: Vec<RevokedCertParams>| CertificateRevocationListParams {
+                this_update: rcgen::date_time_ymd(2020, 1, 1),
+                next_update: rcgen::date_time_ymd(2049, 1, 1),
+                crl_number: SerialNumber::from_slice(&[1]),
+                issuing_distribution_point: None,
+                revoked_certs,
+                key_identifier_method: KeyIdMethod::Sha256,
+            };
+            let root_crl = crl(vec![]).signed_by(&root, &root_key).unwrap();
+            let pck_crl = crl(if revoked {
+                vec![RevokedCertParams {
+                    serial_number: SerialNumber::from_slice(&[0x42]),
+                    revocation_time: rcgen::date_time_ymd(2021, 1, 1),
+                    reason_code: None,
+                    invalidity_date: None,
+                }]
+            } else {
+                vec![]
+            })
+            .signed_by(&ca, &ca_key)
+            .unwrap();
+
+            let pck_signing = SigningKey::from_pkcs8_der(&pck_key.serialize_der()).unwrap();
+            let chain = format!("{}{}{}", pck.pem(), ca.pem(), root.pem());
+
+            let level = |svn: u8, status: &str| {
+                serde_json::json!({
+                    "tcb": {
+                        "sgxtcbcomponents": vec![serde_json::json!({"svn": svn}); 16],
+                        "pcesvn": 11,
+                    },
+                    "tcbDate": "2024-01-01T00:00:00Z",
+                    "tcbStatus": status,
+                    "advisoryIDs": if status == "UpToDate" { vec![] } else { vec!["INTEL-SA-00615"] },
+                })
+            };
+            let tcb_info = serde_json::json!({
+                "id": "SGX",
+                "version": 3,
+                "issueDate": "2024-01-01T00:00:00Z",
+                "nextUpdate": "2049-01-01T00:00:00Z",
+                "fmspc": hex::encode(FMSPC),
+                "pceId": "0000",
+                "tcbType": 0,
+                "tcbEvaluationDataNumber": 16,
+                "tcbLevels": [level(5, "UpToDate"), level(3, "OutOfDate")],
+            });
+            let qe_identity = serde_json::json!({
+                "id": "QE",
+                "version": 2,
+                "issueDate": "2024-01-01T00:00:00Z",
+                "nextUpdate": "2049-01-01T00:00:00Z",
+                "tcbEvaluationDataNumber": 16,
+                "miscselect": "00000000",
+                "miscselectMask": "FFFFFFFF",
+                "attributes": "11000000000000000000000000000000",
+                "attributesMask": "FBFFFFFFFFFFFFFF0000000000000000",
+                "mrsigner": hex::encode_upper(QE_MRSIGNER),
+                "isvprodid": 1,
+                "tcbLevels": [
+                    {"tcb": {"isvsvn": 8}, "tcbDate": "2024-01-01T00:00:00Z", "tcbStatus": "UpToDate"},
+                ],
+            });
+
+            let tcb_chain = format!("{}{}", tcb_signing.pem(), root.pem()).into_bytes();
+            Self {
+                root_pem: root.pem(),
+                collateral: SGXQuoteCollateral {
+                    pck_crl_issuer_chain: format!("{}{}", ca.pem(), root.pem()).into_bytes(),
+                    root_ca_crl: hex::encode(root_crl.der()).into_bytes(),
+                    pck_crl: pck_crl.der().to_vec(),
+                    tcb_info_issuer_chain: tcb_chain.clone(),
+                    tcb_info: signed_json(&tcb_key, "tcbInfo", tcb_info),
+                    qe_identity_issuer_chain: tcb_chain,
+                    qe_identity: signed_json(&tcb_key, "enclaveIdentity", qe_identity),
+                    ..Default::default()
+                },
+                pck_signing,
+                chain,
+                comp_svn,
+            }
+        }
+
+        /// Quote with `report_data`: QE report signed by the PCK, quote signed by the attestation
+        /// key
+        pub(crate) fn quote(&self, report_data: [u8; 64]) -> SGXQuote {
+            let attestation_key = SigningKey::from_slice(&[9u8; 32]).unwrap();
+            let point = attestation_key.verifying_key().to_encoded_point(false);
+            let attestation_public = &point.as_bytes()[1..];
+            let qe_auth_data = [0xAAu8; 32];
+
+            let mut body = [0u8; 384];
+            body[0..16].copy_from_slice(&[self.comp_svn; 16]);
+            body[48..56].copy_from_slice(&0x5u64.to_le_bytes());
+            body[64..96].copy_from_slice(&[0x33; 32]);
+            body[128..160].copy_from_slice(&[0x44; 32]);
+            body[256..258].copy_from_slice(&1u16.to_le_bytes());
+            body[258..260].copy_from_slice(&2u16.to_le_bytes());
+            body[320..384].copy_from_slice(&report_data);
+
+            let mut qe_report = [0u8; 384];
+            qe_report[48..56].copy_from_slice(&0x11u64.to_le_bytes());
+            qe_report[128..160].copy_from_slice(&QE_MRSIGNER);
+            qe_report[256..258].copy_from_slice(&1u16.to_le_bytes());
+            qe_report[258..260].copy_from_slice(&8u16.to_le_bytes());
+            let mut hasher = Sha256::new();
+            hasher.update(attestation_public);
+            hasher.update(qe_auth_data);
+            qe_report[320..352].copy_from_slice(&hasher.finalize());
+            let qe_report_signature: Signature = self.pck_signing.sign(&qe_report);
+
+            let mut header = [0u8; 48];
+            header[0..2].copy_from_slice(&3u16.to_le_bytes());
+            header[2..4].copy_from_slice(&2u16.to_le_bytes());
+            header[12..28].copy_from_slice(&INTEL_QE_VENDOR_ID);
+            let mut quote = [&header[..], &body[..]].concat();
+            let quote_signature: Signature = attestation_key.sign(&quote);
+
+            let mut signature_data = quote_signature.to_bytes().to_vec();
+            signature_data.extend_from_slice(attestation_public);
+            signature_data.extend_from_slice(&qe_report);
+            signature_data.extend_from_slice(&qe_report_signature.to_bytes());
+            signature_data.extend_from_slice(&(qe_auth_data.len() as u16).to_le_bytes());
+            signature_data.extend_from_slice(&qe_auth_data);
+            signature_data.extend_from_slice(&5u16.to_le_bytes());
+            signature_data.extend_from_slice(&(self.chain.len() as u32).to_le_bytes());
+            signature_data.extend_from_slice(self.chain.as_bytes());
+            quote.extend_from_slice(&(signature_data.len() as u32).to_le_bytes());
+            quote.extend_from_slice(&signature_data);
+
+            SGXQuote::try_from(quote).unwrap()
+        }
+
+        pub(crate) fn verifier(&self) -> DcapVerifier {
+            DcapVerifier::new(self.collateral.clone())
+                .with_trusted_root_pem(&self.root_pem)
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn verify_quotes() {
+        let synthetic = Synthetic::new(5, false);
+        let quote = synthetic.quote([1u8; 64]);
+        let verification = synthetic.verifier().verify(&quote).unwrap();
+        assert_eq!(
+            verification,
+            DcapVerification {
+                result: SGXQuoteVerifyResult::Ok,
+                collateral_expired: false,
+                advisory_ids: vec![],
+                collateral_versions: CollateralVersions {
+                    tcb_info: Some(CollateralVersion {
+                        version: 3,
+                        issue_date: "2024-01-01T00:00:00Z".to_string(),
+                        tcb_evaluation_data_number: Some(16),
+                    }),
+                    qe_identity: Some(CollateralVersion {
+                        version: 2,
+                        issue_date: "2024-01-01T00:00:00Z".to_string(),
+                        tcb_evaluation_data_number: Some(16),
+                    }),
+                },
+            }
+        );
+        assert_eq!(quote.mrenclave(), SGXMeasurement::new([0x33; 32]));
+
+        let out_of_date = Synthetic::new(4, false);
+        let verification = out_of_date
+            .verifier()
+            .verify(&out_of_date.quote([1u8; 64]))
+            .unwrap();
+        assert_eq!(verification.result, SGXQuoteVerifyResult::OutOfDate);
+        assert_eq!(verification.advisory_ids, ["INTEL-SA-00615"]);
+        let unsupported = Synthetic::new(2, false);
+        assert_eq!(
+            unsupported
+                .verifier()
+                .verify_quote(&unsupported.quote([1u8; 64]))
+                .unwrap(),
+            SGXQuoteVerifyResult::Unspecified
+        );
+
+        let revoked = Synthetic::new(5, true);
+        assert_eq!(
+            revoked
+                .verifier()
+                .verify_quote(&revoked.quote([1u8; 64]))
+                .unwrap(),
+            SGXQuoteVerifyResult::Revoked
+        );
+
+        // Tampered report body
+        let mut tampered = quote.to_vec();
+        tampered[REPORT_DATA_OFFSET] ^= 1;
+        let tampered = SGXQuote::try_from(tampered).unwrap();
+        assert_eq!(
+            synthetic.verifier().verify_quote(&tampered).unwrap(),
+            SGXQuoteVerifyResult::InvalidSignature
+        );
+
+        // Not chained to the trusted Intel root
+        assert_eq!(
+            DcapVerifier::new(synthetic.collateral.clone())
+                .verify_quote(&quote)
+                .unwrap(),
+            SGXQuoteVerifyResult::InvalidSignature
+        );
+
+        // Genuine Intel PCK chain is trusted, but the collateral is not signed by Intel
+        let intel_quote =
+            SGXQuote::from_slice(include_bytes!("../tests/fixtures/quote.raw")).unwrap();
+        assert!(matches!(
+            DcapVerifier::new(synthetic.collateral.clone()).verify_quote(&intel_quote),
+            Err(SGXError::CollateralParseError(_))
+        ));
+
+        let expired = synthetic
+            .verifier()
+            .with_expiration_time(UNIX_EPOCH + std::time::Duration::from_secs(2_600_000_000));
+        assert!(expired.verify(&quote).unwrap().collateral_expired);
+        assert!(matches!(
+            expired.verify_quote(&quote),
+            Err(SGXError::CollateralExpired)
+        ));
+    }
+
+    #[test]
+    fn reject_quote_formats() {
+        let synthetic = Synthetic::new(5, false);
+        let quote = synthetic.quote([1u8; 64]).to_vec();
+        for (offset, value) in [(0, 4u8), (2, 3), (12, 0)] {
+            let mut unsupported = quote.clone();
+            unsupported[offset] = value;
+            let unsupported = SGXQuote::try_from(unsupported).unwrap();
+            assert!(matches!(
+                synthetic.verifier().verify_quote(&unsupported),
+                Err(SGXError::UnsupportedQuoteFormat(_))
+            ));
+        }
+
+        // PCK chain without the root CA
+        let mut rootless = Synthetic::new(5, false);
+        rootless.chain = rootless
+            .chain
+            .strip_suffix(&rootless.root_pem)
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            rootless
+                .verifier()
+                .verify_quote(&rootless.quote([1u8; 64]))
+                .unwrap(),
+            SGXQuoteVerifyResult::InvalidSignature
+        );
+    }
+
+    #[test]
+    fn chain_constraints() {
+        let root_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let root = ca_params("Test SGX Root CA")
+            .self_signed(&root_key)
+            .unwrap();
+        let issue = |name: &str, is_ca: IsCa, issuer: &rcgen::Certificate, key: &KeyPair| {
+            let subject_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let mut params = ca_params(name);
+            params.is_ca = is_ca;
+            let cert = params.signed_by(&subject_key, issuer, key).unwrap();
+            (cert, subject_key)
+        };
+        let (ca, ca_key) = issue(
+            "CA",
+            IsCa::Ca(BasicConstraints::Constrained(0)),
+            &root,
+            &root_key,
+        );
+        let (sub_ca, sub_ca_key) = issue(
+            "Sub CA",
+            IsCa::Ca(BasicConstraints::Unconstrained),
+            &ca,
+            &ca_key,
+        );
+        let (not_ca, not_ca_key) = issue("Not a CA", IsCa::ExplicitNoCa, &root, &root_key);
+        let (leaf, _) = issue("Leaf", IsCa::ExplicitNoCa, &ca, &ca_key);
+        let (sub_leaf, _) = issue("Leaf", IsCa::NoCa, &sub_ca, &sub_ca_key);
+        let (not_ca_leaf, _) = issue("Leaf", IsCa::NoCa, &not_ca, &not_ca_key);
+        let (ca_leaf, _) = issue(
+            "CA leaf",
+            IsCa::Ca(BasicConstraints::Unconstrained),
+            &ca,
+            &ca_key,
+        );
+
+        let verifies = |chain: &[&rcgen::Certificate]| {
+            let chain = chain
+                .iter()
+                .map(|cert| parse_certificate(cert.der()).unwrap())
+                .collect::<Vec<_>>();
+            Verification {
+                root: parse_certificate(root.der()).unwrap(),
+                now: ASN1Time::now(),
+                now_secs: 0,
+                expired: false,
+            }
+            .verify_chain(&chain)
+        };
+        assert!(verifies(&[&leaf, &ca, &root]));
+        assert!(verifies(&[&leaf, &ca]));
+        // The CA allows no intermediate CA below it
+        assert!(!verifies(&[&sub_leaf, &sub_ca, &ca, &root]));
+        assert!(!verifies(&[&not_ca_leaf, &not_ca, &root]));
+        // The signer of the collateral must be an end-entity
+        assert!(!verifies(&[&ca_leaf, &ca, &root]));
+        assert!(!verifies(&[&ca, &root]));
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2024-02-29T12:30:15Z"), Some(1_709_209_815));
+        assert_eq!(parse_timestamp("2024-02-29"), None);
+    }
+}
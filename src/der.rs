@@ -0,0 +1,88 @@
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+
+use crate::error::{message, SGXError};
+
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_BIT_STRING: u8 = 0x03;
+pub(crate) const TAG_OCTET_STRING: u8 = 0x04;
+pub(crate) const TAG_OID: u8 = 0x06;
+pub(crate) const TAG_ENUMERATED: u8 = 0x0A;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+
+pub(crate) fn parse_error(msg: &str) -> SGXError {
+    SGXError::DerParseError(message(msg))
+}
+
+/// Minimal DER reader, enough to walk certificates and RSA keys
+pub(crate) struct Der<'a>(pub &'a [u8]);
+
+impl<'a> Der<'a> {
+    /// Read the next TLV, returns its tag and value
+    pub fn next(&mut self) -> Result<Option<(u8, &'a [u8])>, SGXError> {
+        let buf = self.0;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        if buf.len() < 2 {
+            return Err(parse_error("truncated DER"));
+        }
+
+        let tag = buf[0];
+        let (len, header_len) = match buf[1] {
+            len @ 0..=0x7F => (len as usize, 2),
+            0x81..=0x84 => {
+                let len_bytes = (buf[1] & 0x7F) as usize;
+                let bytes = buf
+                    .get(2..2 + len_bytes)
+                    .ok_or_else(|| parse_error("truncated DER length"))?;
+                let len = bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+                (len, 2 + len_bytes)
+            }
+            _ => return Err(parse_error("unsupported DER length")),
+        };
+
+        let value = buf
+            .get(header_len..header_len + len)
+            .ok_or_else(|| parse_error("truncated DER value"))?;
+        self.0 = &buf[header_len + len..];
+        Ok(Some((tag, value)))
+    }
+
+    pub fn expect(&mut self, expected: u8) -> Result<&'a [u8], SGXError> {
+        match self.next()? {
+            Some((tag, value)) if tag == expected => Ok(value),
+            _ => Err(parse_error("unexpected DER tag")),
+        }
+    }
+}
+
+/// Small non-negative INTEGER
+pub(crate) fn integer(value: &[u8]) -> Result<u16, SGXError> {
+    if value.len() > 3 || (value.len() == 3 && value[0] != 0) {
+        return Err(parse_error("integer is too large"));
+    }
+    Ok(value.iter().fold(0u16, |n, b| (n << 8) | *b as u16))
+}
+
+/// Decode the first PEM block, returns its label (e.g. `CERTIFICATE`) and DER content
+pub(crate) fn pem_to_der(pem: &str) -> Result<(String, Vec<u8>), SGXError> {
+    let mut lines = pem
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| !line.starts_with("-----BEGIN "));
+
+    let label = lines
+        .next()
+        .and_then(|line| line.strip_prefix("-----BEGIN "))
+        .and_then(|line| line.strip_suffix("-----"))
+        .ok_or_else(|| parse_error("PEM block not found"))?
+        .to_string();
+    let body: String = lines
+        .take_while(|line| !line.starts_with("-----END"))
+        .collect();
+
+    let der = base64
+        .decode(body)
+        .map_err(|e| SGXError::DerParseError(e.into()))?;
+    Ok((label, der))
+}
@@ -0,0 +1,203 @@
+use std::fmt::Display;
+
+use crate::constants::{
+    SGX_FLAGS_AEXNOTIFY, SGX_FLAGS_DEBUG, SGX_FLAGS_EINITTOKEN_KEY, SGX_FLAGS_INITTED,
+    SGX_FLAGS_KSS, SGX_FLAGS_MODE64BIT, SGX_FLAGS_PROVISION_KEY,
+};
+use crate::{SGXQuote, SGXReportBody};
+
+/// Field which differs between two quotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SGXQuoteFieldDiff {
+    /// Dotted path of the field, e.g. `report_body.mr_enclave`
+    pub field: String,
+    pub left: String,
+    pub right: String,
+}
+
+/// Result of [`SGXQuote::diff`]. Its [`Display`] implementation renders a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SGXQuoteDiff {
+    pub fields: Vec<SGXQuoteFieldDiff>,
+}
+
+impl SGXQuoteDiff {
+    /// Returns `true` if all compared fields are equal
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl Display for SGXQuoteDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.fields.is_empty() {
+            return writeln!(f, "Quotes are identical");
+        }
+
+        let width = |header: &str, column: fn(&SGXQuoteFieldDiff) -> &str| {
+            self.fields
+                .iter()
+                .map(|diff| column(diff).len())
+                .max()
+                .unwrap_or_default()
+                .max(header.len())
+        };
+        let field_width = width("FIELD", |diff| &diff.field);
+        let left_width = width("LEFT", |diff| &diff.left);
+
+        writeln!(f, "{:field_width$}  {:left_width$}  RIGHT", "FIELD", "LEFT")?;
+        for diff in &self.fields {
+            writeln!(
+                f,
+                "{:field_width$}  {:left_width$}  {}",
+                diff.field, diff.left, diff.right
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn attribute_flags(flags: u64) -> String {
+    let names: Vec<&str> = [
+        (SGX_FLAGS_INITTED, "INITTED"),
+        (SGX_FLAGS_DEBUG, "DEBUG"),
+        (SGX_FLAGS_MODE64BIT, "MODE64BIT"),
+        (SGX_FLAGS_PROVISION_KEY, "PROVISION_KEY"),
+        (SGX_FLAGS_EINITTOKEN_KEY, "EINITTOKEN_KEY"),
+        (SGX_FLAGS_KSS, "KSS"),
+        (SGX_FLAGS_AEXNOTIFY, "AEXNOTIFY"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| flags & flag != 0)
+    .map(|(_, name)| name)
+    .collect();
+    format!("{:#x} ({})", flags, names.join(" | "))
+}
+
+fn report_body_fields(prefix: &str, body: &SGXReportBody, fields: &mut Vec<(String, String)>) {
+    let mut push = |name: &str, value: String| fields.push((format!("{}.{}", prefix, name), value));
+    push("cpu_svn", hex::encode(body.cpu_svn.svn));
+    push("misc_select", format!("{:#x}", body.misc_select));
+    push("attributes.flags", attribute_flags(body.attributes.flags));
+    push("attributes.xfrm", format!("{:#x}", body.attributes.xfrm));
+    push("mr_enclave", body.mr_enclave.to_string());
+    push("mr_signer", body.mr_signer.to_string());
+    push("config_id", hex::encode(body.config_id.0));
+    push("isv_prod_id", body.isv_prod_id.to_string());
+    push("isv_svn", body.isv_svn.to_string());
+    push("config_svn", body.config_svn.to_string());
+    push("isv_ext_prod_id", format!("{:?}", body.isv_ext_prod_id));
+    push("isv_family_id", format!("{:?}", body.isv_family_id));
+    push("report_data", hex::encode(&*body.report_data));
+}
+
+/// Flatten the quote into the list of `(field, value)`, which has the same fields for any quote
+fn fields(quote: &SGXQuote) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+
+    let header = quote.header();
+    fields.push(("header.version".into(), header.version.to_string()));
+    fields.push((
+        "header.att_key_type".into(),
+        header.att_key_type.to_string(),
+    ));
+    fields.push(("header.qe_svn".into(), header.qe_svn.to_string()));
+    fields.push(("header.pce_svn".into(), header.pce_svn.to_string()));
+    fields.push(("header.vendor_id".into(), hex::encode(header.vendor_id)));
+    fields.push(("header.user_data".into(), hex::encode(header.user_data)));
+
+    report_body_fields("report_body", &quote.report_body(), &mut fields);
+
+    let signature = match quote.signature() {
+        Ok(signature) => signature,
+        Err(e) => {
+            fields.push(("signature".into(), format!("<{}>", e)));
+            return fields;
+        }
+    };
+    fields.push((
+        "signature.attestation_key".into(),
+        hex::encode(signature.attestation_key),
+    ));
+    report_body_fields("qe_report", &signature.qe_report, &mut fields);
+    fields.push((
+        "signature.qe_auth_data".into(),
+        hex::encode(&signature.qe_auth_data),
+    ));
+
+    match signature.pck_extensions() {
+        Ok(pck) => {
+            fields.push(("pck.fmspc".into(), hex::encode(&pck.fmspc)));
+            fields.push(("pck.pce_id".into(), hex::encode(&pck.pce_id)));
+            fields.push(("pck.sgx_type".into(), pck.sgx_type.to_string()));
+            for (i, svn) in pck.tcb.comp_svn.iter().enumerate() {
+                fields.push((format!("pck.tcb.comp_svn[{}]", i + 1), svn.to_string()));
+            }
+            fields.push(("pck.tcb.pce_svn".into(), pck.tcb.pce_svn.to_string()));
+            fields.push(("pck.tcb.cpu_svn".into(), hex::encode(pck.tcb.cpu_svn)));
+        }
+        Err(e) => fields.push(("pck".into(), format!("<{}>", e))),
+    }
+
+    fields
+}
+
+impl SGXQuote {
+    /// Compare two quotes field by field.
+    ///
+    /// Compares the header, the report body, the QE report and the SGX extension of the PCK
+    /// certificate (FMSPC and TCB components). Signatures and certificates themselves are skipped.
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// let diff = expected_quote.diff(&quote);
+    /// if !diff.is_empty() {
+    ///     println!("{}", diff);
+    /// }
+    /// ```
+    pub fn diff(&self, other: &SGXQuote) -> SGXQuoteDiff {
+        let left = fields(self);
+        let mut right = fields(other).into_iter().peekable();
+
+        let mut diff = SGXQuoteDiff::default();
+        for (field, left) in left {
+            let right = match right.next_if(|(name, _)| *name == field) {
+                Some((_, right)) => right,
+                None => "<missing>".into(),
+            };
+            if left != right {
+                diff.fields.push(SGXQuoteFieldDiff { field, left, right });
+            }
+        }
+        for (field, right) in right {
+            diff.fields.push(SGXQuoteFieldDiff {
+                field,
+                left: "<missing>".into(),
+                right,
+            });
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SGXQuote;
+
+    #[test]
+    fn diff() {
+        let quote_buf = include_bytes!("../tests/fixtures/quote.raw");
+        let quote = SGXQuote::from_slice(quote_buf).unwrap();
+
+        let mut other_buf = quote_buf.to_vec();
+        // mrenclave and isv_svn
+        other_buf[48 + 64] ^= 0xff;
+        other_buf[48 + 258] = 0;
+        let other = SGXQuote::from_slice(&other_buf).unwrap();
+
+        insta::assert_snapshot!(quote.diff(&other).to_string());
+    }
+}
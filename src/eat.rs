@@ -0,0 +1,921 @@
+//! [Entity Attestation Token] (EAT) issuance from verified quotes
+//!
+//! Downstream services get a signed claims token instead of a raw [`SGXQuote`]: [`EatIssuer`]
+//! turns a verified quote and its [`SGXQuoteVerifyResult`] into an ES256-signed JWT or CWT.
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::eat::{AttestationClaims, EatIssuer};
+//!
+//! let issuer = EatIssuer::from_pkcs8_pem(&fs::read_to_string("verifier-key.pem")?)?
+//!     .with_issuer("https://verifier.example.com")
+//!     .with_validity(Duration::from_secs(300));
+//!
+//! let result = quote.verify_result()?;
+//! let claims = AttestationClaims::from_quote(&quote, result)?.with_nonce(nonce);
+//! let jwt = issuer.issue_jwt(&claims)?;
+//! ```
+//!
+//! Relying parties check the tokens with [`EatVerifier`] and the issuer keys published as a JWKS:
+//! ```rust ignore
+//! use occlum_sgx::eat::EatVerifier;
+//!
+//! let verifier = EatVerifier::from_jwks_file("jwks.json")?
+//!     .with_issuer("https://verifier.example.com")
+//!     .with_audience("relying-party");
+//!
+//! let token = verifier.verify_jwt(&jwt)?;
+//! assert_eq!(token.mrenclave(), expected_mrenclave);
+//! ```
+//!
+//! [Entity Attestation Token]: https://www.rfc-editor.org/rfc/rfc9711
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as base64url;
+use base64::Engine;
+use ciborium::Value;
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p256::pkcs8::DecodePrivateKey;
+use p256::EncodedPoint;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::message;
+use crate::{SGXError, SGXMeasurement, SGXQuote, SGXQuoteVerifyResult, SGXReportData};
+
+/// COSE algorithm identifier of ES256
+pub(crate) const COSE_ALG_ES256: i64 = -7;
+/// CBOR tag of `COSE_Sign1`
+pub(crate) const COSE_SIGN1_TAG: u64 = 18;
+const COSE_HEADER_ALG: i64 = 1;
+const COSE_HEADER_KID: i64 = 4;
+
+/// CWT claim keys of the registered claims, the others are text keys as in the JWT
+///
+/// RFC: <https://www.rfc-editor.org/rfc/rfc8392#section-3.1>, <https://www.rfc-editor.org/rfc/rfc9711#section-4.1>
+pub(crate) const CWT_CLAIM_KEYS: [(&str, i64); 5] = [
+    ("iss", 1),
+    ("aud", 3),
+    ("exp", 4),
+    ("iat", 6),
+    ("eat_nonce", 10),
+];
+
+/// Claims about the attested enclave, carried by the token next to `iss`, `aud`, `iat` and `exp`.
+///
+/// The claim names follow Intel Trust Authority: `sgx_mrenclave`, `sgx_mrsigner`, ...
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationClaims {
+    #[serde(rename = "sgx_mrenclave")]
+    pub mrenclave: SGXMeasurement,
+    #[serde(rename = "sgx_mrsigner")]
+    pub mrsigner: SGXMeasurement,
+    #[serde(rename = "sgx_isvprodid")]
+    pub isv_prod_id: u16,
+    #[serde(rename = "sgx_isvsvn")]
+    pub isv_svn: u16,
+    #[serde(rename = "sgx_is_debuggable")]
+    pub debug: bool,
+    /// Verification result as TCB status, e.g. `UpToDate` or `SWHardeningNeeded`
+    #[serde(rename = "attester_tcb_status", with = "tcb_status")]
+    pub tcb_status: SGXQuoteVerifyResult,
+    /// Intel Security Advisory IDs which apply to the platform, e.g. `INTEL-SA-00334`
+    #[serde(
+        rename = "attester_advisory_ids",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub advisory_ids: Vec<String>,
+    #[serde(rename = "sgx_report_data")]
+    pub report_data: SGXReportData,
+    /// Freshness nonce of the relying party
+    #[serde(
+        rename = "eat_nonce",
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "crate::serialize::base64_bytes"
+    )]
+    pub nonce: Vec<u8>,
+}
+
+impl AttestationClaims {
+    /// Claims of a verified quote, terminal results are rejected with
+    /// [`SGXError::VerifyQuoteFailed`]
+    pub fn from_quote(quote: &SGXQuote, result: SGXQuoteVerifyResult) -> Result<Self, SGXError> {
+        if result.is_terminal() {
+            return Err(SGXError::VerifyQuoteFailed(result));
+        }
+
+        Ok(Self {
+            mrenclave: quote.mrenclave(),
+            mrsigner: quote.mrsigner(),
+            isv_prod_id: quote.product_id(),
+            isv_svn: quote.version(),
+            debug: quote.is_debug(),
+            tcb_status: result,
+            advisory_ids: Vec::new(),
+            report_data: quote.report_data(),
+            nonce: Vec::new(),
+        })
+    }
+
+    pub fn with_advisory_ids<I, S>(mut self, advisory_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.advisory_ids = advisory_ids.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_nonce<T: Into<Vec<u8>>>(mut self, nonce: T) -> Self {
+        self.nonce = nonce.into();
+        self
+    }
+}
+
+/// Full claims set of the token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TokenClaims {
+    pub iss: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    pub iat: u64,
+    pub exp: u64,
+    #[serde(flatten)]
+    pub claims: AttestationClaims,
+}
+
+/// TCB status names, see [`SGXQuoteVerifyResult::tcb_status`]
+mod tcb_status {
+    use serde::de::{self, Deserializer};
+    use serde::{Deserialize, Serializer};
+
+    use crate::SGXQuoteVerifyResult;
+
+    pub fn serialize<S: Serializer>(v: &SGXQuoteVerifyResult, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(v.tcb_status())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<SGXQuoteVerifyResult, D::Error> {
+        let name = String::deserialize(d)?;
+        SGXQuoteVerifyResult::from_tcb_status(&name)
+            .ok_or_else(|| de::Error::custom(format!("unknown TCB status {}", name)))
+    }
+}
+
+/// Issues ES256-signed attestation tokens
+pub struct EatIssuer {
+    signing_key: SigningKey,
+    key_id: String,
+    issuer: String,
+    audience: Option<String>,
+    validity: Duration,
+}
+
+impl EatIssuer {
+    /// Issuer with the P-256 verifier key, the key ID defaults to the JWK thumbprint of the public
+    /// key (RFC 7638)
+    pub fn new(signing_key: SigningKey) -> Self {
+        let key_id = Jwk::from_verifying_key(signing_key.verifying_key()).thumbprint();
+
+        Self {
+            signing_key,
+            key_id,
+            issuer: "occlum-sgx".to_string(),
+            audience: None,
+            validity: Duration::from_secs(300),
+        }
+    }
+
+    /// Issuer with the PKCS#8 PEM-encoded P-256 verifier key
+    pub fn from_pkcs8_pem(pem: &str) -> Result<Self, SGXError> {
+        SigningKey::from_pkcs8_pem(pem)
+            .map(Self::new)
+            .map_err(|e| SGXError::TokenKeyParseError(e.into()))
+    }
+
+    /// Issuer with the PKCS#8 DER-encoded P-256 verifier key
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, SGXError> {
+        SigningKey::from_pkcs8_der(der)
+            .map(Self::new)
+            .map_err(|e| SGXError::TokenKeyParseError(e.into()))
+    }
+
+    pub fn with_key_id<S: Into<String>>(mut self, key_id: S) -> Self {
+        self.key_id = key_id.into();
+        self
+    }
+
+    /// `iss` claim, `occlum-sgx` by default
+    pub fn with_issuer<S: Into<String>>(mut self, issuer: S) -> Self {
+        self.issuer = issuer.into();
+        self
+    }
+
+    /// `aud` claim, omitted by default
+    pub fn with_audience<S: Into<String>>(mut self, audience: S) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Token lifetime, 5 minutes by default
+    pub fn with_validity(mut self, validity: Duration) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    /// Public key to publish in the JWKS of the relying parties
+    pub fn jwk(&self) -> Jwk {
+        Jwk {
+            kid: Some(self.key_id.clone()),
+            alg: Some("ES256".to_string()),
+            ..Jwk::from_verifying_key(self.signing_key.verifying_key())
+        }
+    }
+
+    fn token_claims(&self, claims: &AttestationClaims) -> TokenClaims {
+        let iat = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        TokenClaims {
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            iat,
+            exp: iat.saturating_add(self.validity.as_secs()),
+            claims: claims.clone(),
+        }
+    }
+
+    /// JWT in the JWS compact serialization
+    pub fn issue_jwt(&self, claims: &AttestationClaims) -> Result<String, SGXError> {
+        let header = serde_json::json!({
+            "alg": "ES256",
+            "typ": "JWT",
+            "kid": self.key_id,
+        });
+        let payload = serde_json::to_vec(&self.token_claims(claims))
+            .map_err(|e| SGXError::TokenEncodingError(e.into()))?;
+
+        let signing_input = format!(
+            "{}.{}",
+            base64url.encode(header.to_string()),
+            base64url.encode(payload)
+        );
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            base64url.encode(signature.to_bytes())
+        ))
+    }
+
+    /// CWT as a tagged `COSE_Sign1`
+    pub fn issue_cwt(&self, claims: &AttestationClaims) -> Result<Vec<u8>, SGXError> {
+        let claims = Value::serialized(&self.token_claims(claims))
+            .map_err(|e| SGXError::TokenEncodingError(e.into()))?;
+        let Value::Map(claims) = claims else {
+            unreachable!("claims are serialized as a map")
+        };
+        let claims = claims
+            .into_iter()
+            .map(|(key, value)| {
+                let key = CWT_CLAIM_KEYS
+                    .iter()
+                    .find(|(name, _)| key.as_text() == Some(name))
+                    .map_or(key, |(_, label)| (*label).into());
+                (key, value)
+            })
+            .collect();
+        let payload = to_cbor(&Value::Map(claims))?;
+
+        let protected = to_cbor(&Value::Map(vec![
+            (COSE_HEADER_ALG.into(), COSE_ALG_ES256.into()),
+            (
+                COSE_HEADER_KID.into(),
+                Value::Bytes(self.key_id.as_bytes().to_vec()),
+            ),
+        ]))?;
+        let signature: Signature = self.signing_key.sign(&sig_structure(&protected, &payload)?);
+
+        to_cbor(&Value::Tag(
+            COSE_SIGN1_TAG,
+            Box::new(Value::Array(vec![
+                Value::Bytes(protected),
+                Value::Map(Vec::new()),
+                Value::Bytes(payload),
+                Value::Bytes(signature.to_bytes().to_vec()),
+            ])),
+        ))
+    }
+}
+
+/// JSON Web Key, only EC P-256 keys are used for verification
+///
+/// RFC: <https://www.rfc-editor.org/rfc/rfc7517>
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+}
+
+impl Jwk {
+    pub fn from_verifying_key(key: &VerifyingKey) -> Self {
+        let point = key.to_encoded_point(false);
+        Self {
+            kty: "EC".to_string(),
+            crv: Some("P-256".to_string()),
+            x: point.x().map(|x| base64url.encode(x)),
+            y: point.y().map(|y| base64url.encode(y)),
+            kid: None,
+            alg: None,
+        }
+    }
+
+    /// JWK thumbprint (RFC 7638)
+    pub fn thumbprint(&self) -> String {
+        let jwk = format!(
+            r#"{{"crv":"{}","kty":"{}","x":"{}","y":"{}"}}"#,
+            self.crv.as_deref().unwrap_or_default(),
+            self.kty,
+            self.x.as_deref().unwrap_or_default(),
+            self.y.as_deref().unwrap_or_default()
+        );
+        base64url.encode(Sha256::digest(jwk))
+    }
+
+    /// P-256 public key, fails for the other key types
+    pub fn verifying_key(&self) -> Result<VerifyingKey, SGXError> {
+        if self.kty != "EC" || self.crv.as_deref() != Some("P-256") {
+            return Err(SGXError::TokenKeyParseError(message(format!(
+                "unsupported key type {} {}",
+                self.kty,
+                self.crv.as_deref().unwrap_or_default()
+            ))));
+        }
+        let coordinate = |c: &Option<String>| {
+            let c = base64url
+                .decode(c.as_deref().unwrap_or_default())
+                .map_err(|e| SGXError::TokenKeyParseError(e.into()))?;
+            <[u8; 32]>::try_from(c)
+                .map_err(|_| SGXError::TokenKeyParseError(message("bad coordinate length")))
+        };
+        let point = EncodedPoint::from_affine_coordinates(
+            &coordinate(&self.x)?.into(),
+            &coordinate(&self.y)?.into(),
+            false,
+        );
+        VerifyingKey::from_encoded_point(&point).map_err(|e| SGXError::TokenKeyParseError(e.into()))
+    }
+}
+
+/// JSON Web Key Set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// Verified attestation token, with accessors mirroring [`SGXQuote`]
+#[derive(Debug, Clone)]
+pub struct AttestationToken {
+    issuer: String,
+    audience: Option<String>,
+    issued_at: SystemTime,
+    expires_at: SystemTime,
+    claims: AttestationClaims,
+}
+
+impl AttestationToken {
+    pub fn mrenclave(&self) -> SGXMeasurement {
+        self.claims.mrenclave
+    }
+
+    pub fn mrsigner(&self) -> SGXMeasurement {
+        self.claims.mrsigner
+    }
+
+    pub fn product_id(&self) -> u16 {
+        self.claims.isv_prod_id
+    }
+
+    pub fn version(&self) -> u16 {
+        self.claims.isv_svn
+    }
+
+    pub fn report_data(&self) -> SGXReportData {
+        self.claims.report_data
+    }
+
+    /// Returns `true` if the enclave was launched in debug mode
+    pub fn is_debug(&self) -> bool {
+        self.claims.debug
+    }
+
+    /// Verification result of the quote the token was issued for
+    pub fn tcb_status(&self) -> SGXQuoteVerifyResult {
+        self.claims.tcb_status
+    }
+
+    pub fn advisory_ids(&self) -> &[String] {
+        &self.claims.advisory_ids
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        &self.claims.nonce
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn audience(&self) -> Option<&str> {
+        self.audience.as_deref()
+    }
+
+    pub fn issued_at(&self) -> SystemTime {
+        self.issued_at
+    }
+
+    pub fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
+
+    pub fn claims(&self) -> &AttestationClaims {
+        &self.claims
+    }
+}
+
+/// Verifies ES256-signed attestation tokens issued by [`EatIssuer`]: signature, expiry, issuer and
+/// audience.
+///
+/// A token with an `aud` claim is rejected unless the verifier expects this audience.
+pub struct EatVerifier {
+    keys: Vec<(Option<String>, VerifyingKey)>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: Duration,
+}
+
+impl EatVerifier {
+    /// Verifier with the P-256 keys of the set, the other keys are skipped
+    pub fn new(jwks: &Jwks) -> Result<Self, SGXError> {
+        let keys: Vec<_> = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| Some((jwk.kid.clone(), jwk.verifying_key().ok()?)))
+            .collect();
+        if keys.is_empty() {
+            return Err(SGXError::TokenKeyParseError(message(
+                "JWKS does not contain P-256 keys",
+            )));
+        }
+
+        Ok(Self {
+            keys,
+            issuer: None,
+            audience: None,
+            leeway: Duration::from_secs(60),
+        })
+    }
+
+    /// Verifier with the keys of a local JWKS file
+    pub fn from_jwks_file<P: AsRef<Path>>(path: P) -> Result<Self, SGXError> {
+        let jwks: Jwks = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| SGXError::TokenKeyParseError(e.into()))?;
+        Self::new(&jwks)
+    }
+
+    /// Expected `iss` claim, any issuer is accepted by default
+    pub fn with_issuer<S: Into<String>>(mut self, issuer: S) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Expected `aud` claim
+    pub fn with_audience<S: Into<String>>(mut self, audience: S) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Allowed clock skew for `exp` and `iat`, 1 minute by default
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    fn key(&self, kid: Option<&str>) -> Result<&VerifyingKey, SGXError> {
+        let mut keys = self
+            .keys
+            .iter()
+            .filter(|(key_id, _)| kid.is_none() || key_id.as_deref() == kid);
+        match (keys.next(), keys.next()) {
+            (Some((_, key)), None) => Ok(key),
+            (Some(_), Some(_)) => Err(SGXError::TokenVerificationFailed(
+                "token without kid, but several keys".to_string(),
+            )),
+            (None, _) => Err(SGXError::TokenVerificationFailed(format!(
+                "unknown key {}",
+                kid.unwrap_or_default()
+            ))),
+        }
+    }
+
+    fn check_claims(&self, token: TokenClaims) -> Result<AttestationToken, SGXError> {
+        let now = SystemTime::now();
+        let time = |secs: u64, claim: &str| {
+            UNIX_EPOCH
+                .checked_add(Duration::from_secs(secs))
+                .ok_or_else(|| {
+                    SGXError::TokenVerificationFailed(format!("{} is out of range", claim))
+                })
+        };
+        let issued_at = time(token.iat, "iat")?;
+        let expires_at = time(token.exp, "exp")?;
+        // Beyond the range of `SystemTime`, the leeway covers any time
+        if expires_at
+            .checked_add(self.leeway)
+            .is_some_and(|expires_at| expires_at <= now)
+        {
+            return Err(SGXError::TokenVerificationFailed(
+                "token expired".to_string(),
+            ));
+        }
+        if now
+            .checked_add(self.leeway)
+            .is_some_and(|now| issued_at > now)
+        {
+            return Err(SGXError::TokenVerificationFailed(
+                "token issued in the future".to_string(),
+            ));
+        }
+        if let Some(issuer) = &self.issuer {
+            if &token.iss != issuer {
+                return Err(SGXError::TokenVerificationFailed(format!(
+                    "unexpected issuer {}",
+                    token.iss
+                )));
+            }
+        }
+        if token.aud.is_some() && token.aud != self.audience
+            || self.audience.is_some() && token.aud.is_none()
+        {
+            return Err(SGXError::TokenVerificationFailed(format!(
+                "unexpected audience {}",
+                token.aud.unwrap_or_default()
+            )));
+        }
+
+        Ok(AttestationToken {
+            issuer: token.iss,
+            audience: token.aud,
+            issued_at,
+            expires_at,
+            claims: token.claims,
+        })
+    }
+
+    /// Verify a JWT in the JWS compact serialization
+    pub fn verify_jwt(&self, jwt: &str) -> Result<AttestationToken, SGXError> {
+        #[derive(Deserialize)]
+        struct Header {
+            alg: String,
+            kid: Option<String>,
+        }
+
+        let parse_error = |msg: &str| SGXError::TokenParseError(message(msg));
+        let (signing_input, signature) = jwt.rsplit_once('.').ok_or(parse_error("not a JWS"))?;
+        let (header, payload) = signing_input
+            .split_once('.')
+            .ok_or(parse_error("not a JWS"))?;
+        let decode = |part: &str| {
+            base64url
+                .decode(part)
+                .map_err(|e| SGXError::TokenParseError(e.into()))
+        };
+
+        let header: Header = serde_json::from_slice(&decode(header)?)
+            .map_err(|e| SGXError::TokenParseError(e.into()))?;
+        if header.alg != "ES256" {
+            return Err(SGXError::TokenVerificationFailed(format!(
+                "unsupported algorithm {}",
+                header.alg
+            )));
+        }
+        let signature = Signature::from_slice(&decode(signature)?)
+            .map_err(|e| SGXError::TokenParseError(e.into()))?;
+        self.key(header.kid.as_deref())?
+            .verify(signing_input.as_bytes(), &signature)
+            .map_err(|_| SGXError::TokenVerificationFailed("bad signature".to_string()))?;
+
+        let claims = serde_json::from_slice(&decode(payload)?)
+            .map_err(|e| SGXError::TokenParseError(e.into()))?;
+        self.check_claims(claims)
+    }
+
+    /// Verify a CWT, a tagged or untagged `COSE_Sign1`
+    pub fn verify_cwt(&self, cwt: &[u8]) -> Result<AttestationToken, SGXError> {
+        let parse_error = |msg: &str| SGXError::TokenParseError(message(msg));
+        let sign1: Value =
+            ciborium::from_reader(cwt).map_err(|e| SGXError::TokenParseError(e.into()))?;
+        let sign1 = match sign1 {
+            Value::Tag(COSE_SIGN1_TAG, sign1) => *sign1,
+            sign1 => sign1,
+        };
+        let [protected, unprotected, payload, signature] = <[Value; 4]>::try_from(
+            sign1
+                .into_array()
+                .map_err(|_| parse_error("not a COSE_Sign1"))?,
+        )
+        .map_err(|_| parse_error("not a COSE_Sign1"))?;
+        let (
+            Value::Bytes(protected),
+            Value::Map(unprotected),
+            Value::Bytes(payload),
+            Value::Bytes(signature),
+        ) = (protected, unprotected, payload, signature)
+        else {
+            return Err(parse_error("not a COSE_Sign1"));
+        };
+
+        let headers: Value = ciborium::from_reader(protected.as_slice())
+            .map_err(|e| SGXError::TokenParseError(e.into()))?;
+        let headers = headers
+            .into_map()
+            .map_err(|_| parse_error("bad protected header"))?;
+        let header = |headers: &[(Value, Value)], label: i64| {
+            headers
+                .iter()
+                .find(|(key, _)| key.as_integer() == Some(label.into()))
+                .map(|(_, value)| value.clone())
+        };
+        // The algorithm must be integrity protected, RFC 9052 section 3.1
+        if header(&headers, COSE_HEADER_ALG).and_then(|alg| alg.as_integer())
+            != Some(COSE_ALG_ES256.into())
+        {
+            return Err(SGXError::TokenVerificationFailed(
+                "unsupported algorithm".to_string(),
+            ));
+        }
+        let kid = header(&headers, COSE_HEADER_KID)
+            .or_else(|| header(&unprotected, COSE_HEADER_KID))
+            .and_then(|kid| kid.into_bytes().ok())
+            .map(|kid| String::from_utf8_lossy(&kid).into_owned());
+
+        let signature =
+            Signature::from_slice(&signature).map_err(|e| SGXError::TokenParseError(e.into()))?;
+        self.key(kid.as_deref())?
+            .verify(&sig_structure(&protected, &payload)?, &signature)
+            .map_err(|_| SGXError::TokenVerificationFailed("bad signature".to_string()))?;
+
+        let claims: Value = ciborium::from_reader(payload.as_slice())
+            .map_err(|e| SGXError::TokenParseError(e.into()))?;
+        let claims = claims
+            .into_map()
+            .map_err(|_| parse_error("claims are not a map"))?
+            .into_iter()
+            .map(|(key, value)| {
+                let key = CWT_CLAIM_KEYS
+                    .iter()
+                    .find(|(_, label)| key.as_integer() == Some((*label).into()))
+                    .map_or(key, |(name, _)| Value::Text(name.to_string()));
+                (key, value)
+            })
+            .collect();
+        let claims = Value::Map(claims)
+            .deserialized()
+            .map_err(|e| SGXError::TokenParseError(e.into()))?;
+        self.check_claims(claims)
+    }
+}
+
+pub(crate) fn to_cbor(value: &Value) -> Result<Vec<u8>, SGXError> {
+    let mut buf = Vec::new();
+    ciborium::into_writer(value, &mut buf).map_err(|e| SGXError::TokenEncodingError(e.into()))?;
+    Ok(buf)
+}
+
+/// `Sig_structure` of `COSE_Sign1` without external AAD
+///
+/// RFC: <https://www.rfc-editor.org/rfc/rfc9052#section-4.4>
+pub(crate) fn sig_structure(protected: &[u8], payload: &[u8]) -> Result<Vec<u8>, SGXError> {
+    to_cbor(&Value::Array(vec![
+        Value::Text("Signature1".to_string()),
+        Value::Bytes(protected.to_vec()),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(payload.to_vec()),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::signature::Verifier;
+    use p256::ecdsa::VerifyingKey;
+
+    use super::*;
+
+    #[test]
+    fn issue_jwt_and_cwt() {
+        let quote = SGXQuote::from_slice(include_bytes!("../tests/fixtures/quote.raw")).unwrap();
+        assert!(matches!(
+            AttestationClaims::from_quote(&quote, SGXQuoteVerifyResult::Revoked),
+            Err(SGXError::VerifyQuoteFailed(SGXQuoteVerifyResult::Revoked))
+        ));
+        let claims = AttestationClaims::from_quote(&quote, SGXQuoteVerifyResult::SwHardeningNeeded)
+            .unwrap()
+            .with_advisory_ids(["INTEL-SA-00334", "INTEL-SA-00615"])
+            .with_nonce(b"nonce-1234".to_vec());
+
+        let issuer = EatIssuer::new(SigningKey::from_slice(&[7u8; 32]).unwrap())
+            .with_issuer("https://verifier.example.com")
+            .with_audience("relying-party");
+        let verifying_key = VerifyingKey::from(issuer.signing_key());
+
+        let jwt = issuer.issue_jwt(&claims).unwrap();
+        let (signing_input, signature) = jwt.rsplit_once('.').unwrap();
+        let signature = Signature::from_slice(&base64url.decode(signature).unwrap()).unwrap();
+        verifying_key
+            .verify(signing_input.as_bytes(), &signature)
+            .unwrap();
+
+        let (header, payload) = signing_input.split_once('.').unwrap();
+        let header: serde_json::Value =
+            serde_json::from_slice(&base64url.decode(header).unwrap()).unwrap();
+        assert_eq!(header["kid"], issuer.key_id());
+        let mut payload: serde_json::Value =
+            serde_json::from_slice(&base64url.decode(payload).unwrap()).unwrap();
+        assert_eq!(
+            payload["exp"].as_u64().unwrap() - payload["iat"].as_u64().unwrap(),
+            300
+        );
+        payload["iat"] = 0.into();
+        payload["exp"] = 300.into();
+        insta::assert_yaml_snapshot!(payload);
+
+        let cwt = issuer.issue_cwt(&claims).unwrap();
+        let Value::Tag(COSE_SIGN1_TAG, sign1) = ciborium::from_reader(cwt.as_slice()).unwrap()
+        else {
+            panic!("not a COSE_Sign1")
+        };
+        let sign1 = sign1.into_array().unwrap();
+        let (protected, payload, signature) = (
+            sign1[0].as_bytes().unwrap(),
+            sign1[2].as_bytes().unwrap(),
+            sign1[3].as_bytes().unwrap(),
+        );
+        verifying_key
+            .verify(
+                &sig_structure(protected, payload).unwrap(),
+                &Signature::from_slice(signature).unwrap(),
+            )
+            .unwrap();
+        let payload: Value = ciborium::from_reader(payload.as_slice()).unwrap();
+        let payload = payload.into_map().unwrap();
+        let claim = |key: Value| payload.iter().find(|(k, _)| *k == key).unwrap().1.clone();
+        assert_eq!(
+            claim(1.into()),
+            Value::Text("https://verifier.example.com".into())
+        );
+        assert_eq!(claim(10.into()), Value::Bytes(b"nonce-1234".to_vec()));
+        assert_eq!(
+            claim("sgx_mrenclave".into()),
+            Value::Bytes(quote.mrenclave().to_vec())
+        );
+    }
+
+    #[test]
+    fn verify_tokens() {
+        let quote = SGXQuote::from_slice(include_bytes!("../tests/fixtures/quote.raw")).unwrap();
+        let claims = AttestationClaims::from_quote(&quote, SGXQuoteVerifyResult::Ok)
+            .unwrap()
+            .with_nonce(b"nonce-1234".to_vec());
+        let issuer = EatIssuer::new(SigningKey::from_slice(&[7u8; 32]).unwrap())
+            .with_issuer("https://verifier.example.com")
+            .with_audience("relying-party");
+        let other = EatIssuer::new(SigningKey::from_slice(&[8u8; 32]).unwrap());
+
+        let dir = tempfile::tempdir().unwrap();
+        let jwks = Jwks {
+            keys: vec![
+                Jwk {
+                    kty: "RSA".to_string(),
+                    crv: None,
+                    x: None,
+                    y: None,
+                    kid: Some("rsa".to_string()),
+                    alg: Some("RS256".to_string()),
+                },
+                issuer.jwk(),
+            ],
+        };
+        fs::write(
+            dir.path().join("jwks.json"),
+            serde_json::to_vec(&jwks).unwrap(),
+        )
+        .unwrap();
+        let verifier = EatVerifier::from_jwks_file(dir.path().join("jwks.json"))
+            .unwrap()
+            .with_issuer("https://verifier.example.com")
+            .with_audience("relying-party");
+
+        let jwt = issuer.issue_jwt(&claims).unwrap();
+        let cwt = issuer.issue_cwt(&claims).unwrap();
+        for token in [
+            verifier.verify_jwt(&jwt).unwrap(),
+            verifier.verify_cwt(&cwt).unwrap(),
+        ] {
+            assert_eq!(token.mrenclave(), quote.mrenclave());
+            assert_eq!(token.mrsigner(), quote.mrsigner());
+            assert_eq!(token.product_id(), quote.product_id());
+            assert_eq!(token.version(), quote.version());
+            assert_eq!(*token.report_data(), *quote.report_data());
+            assert_eq!(token.is_debug(), quote.is_debug());
+            assert_eq!(token.tcb_status(), SGXQuoteVerifyResult::Ok);
+            assert_eq!(token.nonce(), b"nonce-1234");
+            assert_eq!(token.audience(), Some("relying-party"));
+        }
+
+        let rejected = |result: Result<AttestationToken, SGXError>| {
+            matches!(result, Err(SGXError::TokenVerificationFailed(_)))
+        };
+        let (signing_input, _) = jwt.rsplit_once('.').unwrap();
+        let forged = other.issue_jwt(&claims).unwrap();
+        let (_, signature) = forged.rsplit_once('.').unwrap();
+        assert!(rejected(
+            verifier.verify_jwt(&format!("{}.{}", signing_input, signature))
+        ));
+        assert!(rejected(
+            verifier.verify_cwt(&other.issue_cwt(&claims).unwrap())
+        ));
+        // Algorithm in the unprotected header only
+        let Value::Tag(_, sign1) = ciborium::from_reader(cwt.as_slice()).unwrap() else {
+            panic!("not a COSE_Sign1")
+        };
+        let payload = sign1.into_array().unwrap().remove(2).into_bytes().unwrap();
+        let protected = to_cbor(&Value::Map(vec![(
+            COSE_HEADER_KID.into(),
+            Value::Bytes(issuer.key_id().as_bytes().to_vec()),
+        )]))
+        .unwrap();
+        let signature: Signature = SigningKey::from_slice(&[7u8; 32])
+            .unwrap()
+            .sign(&sig_structure(&protected, &payload).unwrap());
+        let unprotected_alg = to_cbor(&Value::Array(vec![
+            Value::Bytes(protected),
+            Value::Map(vec![(COSE_HEADER_ALG.into(), COSE_ALG_ES256.into())]),
+            Value::Bytes(payload),
+            Value::Bytes(signature.to_bytes().to_vec()),
+        ]))
+        .unwrap();
+        assert!(rejected(verifier.verify_cwt(&unprotected_alg)));
+        assert!(rejected(
+            EatVerifier::new(&jwks)
+                .unwrap()
+                .with_audience("other-party")
+                .verify_jwt(&jwt)
+        ));
+        assert!(rejected(EatVerifier::new(&jwks).unwrap().verify_jwt(&jwt)));
+        let expired = issuer
+            .with_validity(Duration::ZERO)
+            .issue_jwt(&claims)
+            .unwrap();
+        assert!(rejected(
+            verifier.with_leeway(Duration::ZERO).verify_jwt(&expired)
+        ));
+
+        // Times beyond the range of `SystemTime`
+        assert!(EatVerifier::new(&jwks)
+            .unwrap()
+            .with_audience("relying-party")
+            .with_leeway(Duration::MAX)
+            .verify_jwt(&jwt)
+            .is_ok());
+        let unbounded = EatIssuer::new(SigningKey::from_slice(&[7u8; 32]).unwrap())
+            .with_validity(Duration::MAX)
+            .issue_jwt(&claims)
+            .unwrap();
+        assert!(rejected(
+            EatVerifier::new(&jwks).unwrap().verify_jwt(&unbounded)
+        ));
+    }
+}
@@ -1,19 +1,132 @@
 use crate::types::SGXQuoteVerifyResult;
-use std::{error, fmt::Display};
+use std::{error, fmt::Display, io};
+
+/// Category of [`SGXError`], see [`SGXError::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SGXErrorKind {
+    /// The SGX device is missing or doesn't support DCAP ioctls, i.e. not running inside Occlum
+    NotInOcclum,
+    /// Quote generation or verification failed for a transient reason, e.g. PCCS is unreachable
+    PccsUnreachable,
+    /// Quote, certificate, key or enclave image is malformed
+    Malformed,
+    /// Quote is well-formed, but rejected by verification or appraisal
+    Rejected,
+    /// Other I/O error
+    Io,
+    Other,
+}
+
+/// Underlying error of an [`SGXError`], or its message if there is none
+pub type BoxError = Box<dyn error::Error + Send + Sync>;
+
+/// Payload of an [`SGXError`] without an underlying error, which is not its
+/// [`source`](error::Error::source)
+#[derive(Debug)]
+struct Message(String);
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for Message {}
+
+pub(crate) fn message(msg: impl Into<String>) -> BoxError {
+    Box::new(Message(msg.into()))
+}
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum SGXError {
-    DeviceOpenFailed(&'static str),
+    DeviceOpenFailed {
+        path: String,
+        source: io::Error,
+    },
     BadQuoteLength {
         min: usize,
         actual: usize,
     },
     IoctlClientError {
         request_type: &'static str,
-        ret: i32,
+        source: io::Error,
     },
-    SGXMeasurementParseError(String),
+    SGXMeasurementParseError(BoxError),
     VerifyQuoteFailed(SGXQuoteVerifyResult),
+    CertificateParseError(BoxError),
+    DerParseError(BoxError),
+    SigningKeyParseError(BoxError),
+    SigStructParseError(BoxError),
+    IoError(io::Error),
+    EnclaveImageParseError(BoxError),
+    #[cfg(feature = "ratls")]
+    QuoteExtensionNotFound,
+    ReportDataMismatch,
+    AppraisalRejected(String),
+    #[cfg(feature = "tokio")]
+    BlockingTaskFailed(BoxError),
+    UnsupportedAttestationType(String),
+    TsmGenerationMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    UnsupportedTsmProvider(String),
+    #[cfg(feature = "cmw")]
+    CmwParseError(BoxError),
+    #[cfg(feature = "cmw")]
+    UnexpectedMediaType(String),
+    #[cfg(feature = "qvl")]
+    LibraryLoadFailed(BoxError),
+    #[cfg(feature = "eat")]
+    TokenKeyParseError(BoxError),
+    #[cfg(feature = "eat")]
+    TokenEncodingError(BoxError),
+    #[cfg(feature = "eat")]
+    TokenParseError(BoxError),
+    #[cfg(feature = "eat")]
+    TokenVerificationFailed(String),
+    #[cfg(feature = "qvl")]
+    QvlError {
+        function: &'static str,
+        code: u32,
+    },
+    #[cfg(feature = "dcap")]
+    CollateralParseError(BoxError),
+    CollateralExpired,
+    #[cfg(feature = "dcap")]
+    UnsupportedQuoteFormat(String),
+    #[cfg(feature = "pccs")]
+    PccsRequestFailed {
+        /// HTTP status, `None` if PCCS was not reached
+        status: Option<u16>,
+        message: String,
+    },
+    #[cfg(feature = "grpc")]
+    RpcFailed {
+        /// gRPC status code
+        code: i32,
+        message: String,
+    },
+    #[cfg(any(feature = "broker", feature = "channel"))]
+    KeyExchangeFailed(String),
+    #[cfg(any(feature = "broker", feature = "channel"))]
+    DecryptionFailed(BoxError),
+    #[cfg(feature = "broker")]
+    SecretNotFound(String),
+    #[cfg(feature = "broker")]
+    BrokerRequestFailed {
+        /// HTTP status, `None` if the key broker was not reached
+        status: Option<u16>,
+        message: String,
+    },
+    #[cfg(feature = "audit")]
+    AuditChainBroken {
+        /// 1-based line of the first entry which does not verify
+        line: u64,
+        reason: String,
+    },
 }
 
 impl Display for SGXError {
@@ -22,20 +135,378 @@ impl Display for SGXError {
             SGXError::BadQuoteLength { min, actual } => {
                 write!(f, "Bad report length (actual: {}, min: {})", actual, min)
             }
-            SGXError::IoctlClientError { request_type, ret } => {
-                write!(f, "Failed {} with code {}", request_type, ret)
+            SGXError::IoctlClientError {
+                request_type,
+                source,
+            } => {
+                write!(f, "Failed {}", request_type)?;
+                write_errno(f, source)
             }
             SGXError::VerifyQuoteFailed(result) => {
                 write!(f, "Quote verification failed: {:?}", result)
             }
-            SGXError::DeviceOpenFailed(path) => {
-                write!(f, "Failed to open {}", path)
+            SGXError::DeviceOpenFailed { path, source } => {
+                write!(f, "Failed to open {}", path)?;
+                write_errno(f, source)
             }
             SGXError::SGXMeasurementParseError(msg) => {
                 write!(f, "Failed to parse SGX measurement: {}", msg)
             }
+            SGXError::CertificateParseError(msg) => {
+                write!(f, "Failed to parse certificate: {}", msg)
+            }
+            SGXError::DerParseError(msg) => {
+                write!(f, "Failed to parse DER: {}", msg)
+            }
+            SGXError::SigningKeyParseError(msg) => {
+                write!(f, "Failed to parse enclave signing key: {}", msg)
+            }
+            SGXError::SigStructParseError(msg) => {
+                write!(f, "Failed to parse SIGSTRUCT: {}", msg)
+            }
+            SGXError::EnclaveImageParseError(msg) => {
+                write!(f, "Failed to parse enclave image: {}", msg)
+            }
+            SGXError::UnsupportedAttestationType(attestation_type) => {
+                write!(f, "Unsupported attestation type: {}", attestation_type)
+            }
+            SGXError::TsmGenerationMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "TSM report was written concurrently (generation: {}, expected: {})",
+                    actual, expected
+                )
+            }
+            SGXError::UnsupportedTsmProvider(provider) => {
+                write!(f, "TSM provider {:?} does not produce SGX quotes", provider)
+            }
+            #[cfg(feature = "eat")]
+            SGXError::TokenKeyParseError(msg) => {
+                write!(f, "Failed to parse token signing key: {}", msg)
+            }
+            #[cfg(feature = "eat")]
+            SGXError::TokenEncodingError(msg) => {
+                write!(f, "Failed to encode token: {}", msg)
+            }
+            #[cfg(feature = "eat")]
+            SGXError::TokenParseError(msg) => {
+                write!(f, "Failed to parse token: {}", msg)
+            }
+            #[cfg(feature = "eat")]
+            SGXError::TokenVerificationFailed(msg) => {
+                write!(f, "Token verification failed: {}", msg)
+            }
+            #[cfg(feature = "cmw")]
+            SGXError::CmwParseError(msg) => {
+                write!(f, "Failed to parse conceptual message wrapper: {}", msg)
+            }
+            #[cfg(feature = "cmw")]
+            SGXError::UnexpectedMediaType(media_type) => {
+                write!(f, "Unexpected media type: {}", media_type)
+            }
+            #[cfg(feature = "qvl")]
+            SGXError::LibraryLoadFailed(msg) => {
+                write!(f, "Failed to load library: {}", msg)
+            }
+            #[cfg(feature = "qvl")]
+            SGXError::QvlError { function, code } => {
+                write!(f, "Failed {} (error {:#06x})", function, code)
+            }
+            #[cfg(feature = "dcap")]
+            SGXError::CollateralParseError(msg) => {
+                write!(f, "Failed to parse collateral: {}", msg)
+            }
+            SGXError::CollateralExpired => {
+                write!(f, "Quote verification collateral expired")
+            }
+            #[cfg(feature = "dcap")]
+            SGXError::UnsupportedQuoteFormat(msg) => {
+                write!(f, "Unsupported quote format: {}", msg)
+            }
+            #[cfg(feature = "pccs")]
+            SGXError::PccsRequestFailed { status, message } => {
+                write!(f, "PCCS request failed: {}", message)?;
+                match status {
+                    Some(status) => write!(f, " (status {})", status),
+                    None => Ok(()),
+                }
+            }
+            #[cfg(feature = "grpc")]
+            SGXError::RpcFailed { code, message } => {
+                write!(f, "RPC failed: {} (code {})", message, code)
+            }
+            #[cfg(any(feature = "broker", feature = "channel"))]
+            SGXError::KeyExchangeFailed(msg) => {
+                write!(f, "Key exchange failed: {}", msg)
+            }
+            #[cfg(any(feature = "broker", feature = "channel"))]
+            SGXError::DecryptionFailed(msg) => {
+                write!(f, "Decryption failed: {}", msg)
+            }
+            #[cfg(feature = "broker")]
+            SGXError::SecretNotFound(name) => {
+                write!(f, "Secret {} not found", name)
+            }
+            #[cfg(feature = "broker")]
+            SGXError::BrokerRequestFailed { status, message } => {
+                write!(f, "Key broker request failed: {}", message)?;
+                match status {
+                    Some(status) => write!(f, " (status {})", status),
+                    None => Ok(()),
+                }
+            }
+            #[cfg(feature = "audit")]
+            SGXError::AuditChainBroken { line, reason } => {
+                write!(f, "Audit log chain is broken at line {}: {}", line, reason)
+            }
+            #[cfg(feature = "tokio")]
+            SGXError::BlockingTaskFailed(msg) => {
+                write!(f, "Blocking task failed: {}", msg)
+            }
+            SGXError::IoError(e) => {
+                write!(f, "I/O error: {}", e)
+            }
+            #[cfg(feature = "ratls")]
+            SGXError::QuoteExtensionNotFound => {
+                write!(f, "Certificate does not contain an SGX quote extension")
+            }
+            SGXError::ReportDataMismatch => {
+                write!(f, "Quote report data does not match the expected value")
+            }
+            SGXError::AppraisalRejected(msg) => {
+                write!(f, "Quote rejected by appraisal: {}", msg)
+            }
+        }
+    }
+}
+
+fn write_errno(f: &mut std::fmt::Formatter<'_>, e: &io::Error) -> std::fmt::Result {
+    match e.raw_os_error() {
+        Some(errno) => write!(f, " (errno {})", errno),
+        None => Ok(()),
+    }
+}
+
+/// Errnos which the LibOS returns when the DCAP library fails to reach PCCS or the QE is busy
+const TRANSIENT_ERRNOS: &[i32] = &[
+    libc::EAGAIN,
+    libc::EBUSY,
+    libc::EINTR,
+    libc::EIO,
+    libc::ETIMEDOUT,
+    libc::ECONNREFUSED,
+    libc::ECONNRESET,
+    libc::ENETUNREACH,
+    libc::EHOSTUNREACH,
+];
+
+/// gRPC status codes of [`SGXError::RpcFailed`] with a specific [`SGXErrorKind`]
+#[cfg(feature = "grpc")]
+const RPC_INVALID_ARGUMENT: i32 = 3;
+#[cfg(feature = "grpc")]
+const RPC_PERMISSION_DENIED: i32 = 7;
+#[cfg(feature = "grpc")]
+const RPC_UNAVAILABLE: i32 = 14;
+
+impl SGXError {
+    pub fn kind(&self) -> SGXErrorKind {
+        match self {
+            SGXError::DeviceOpenFailed { source, .. } => match source.raw_os_error() {
+                Some(libc::ENOENT | libc::ENODEV | libc::ENXIO) => SGXErrorKind::NotInOcclum,
+                _ => SGXErrorKind::Io,
+            },
+            SGXError::IoctlClientError { source, .. } => match source.raw_os_error() {
+                Some(libc::ENOTTY) => SGXErrorKind::NotInOcclum,
+                Some(errno) if TRANSIENT_ERRNOS.contains(&errno) => SGXErrorKind::PccsUnreachable,
+                Some(libc::EINVAL | libc::EFAULT | libc::EMSGSIZE) => SGXErrorKind::Malformed,
+                _ => SGXErrorKind::Other,
+            },
+            SGXError::BadQuoteLength { .. }
+            | SGXError::SGXMeasurementParseError(_)
+            | SGXError::CertificateParseError(_)
+            | SGXError::DerParseError(_)
+            | SGXError::SigningKeyParseError(_)
+            | SGXError::SigStructParseError(_)
+            | SGXError::EnclaveImageParseError(_) => SGXErrorKind::Malformed,
+            #[cfg(feature = "eat")]
+            SGXError::TokenKeyParseError(_) | SGXError::TokenParseError(_) => {
+                SGXErrorKind::Malformed
+            }
+            #[cfg(feature = "cmw")]
+            SGXError::CmwParseError(_) | SGXError::UnexpectedMediaType(_) => {
+                SGXErrorKind::Malformed
+            }
+            #[cfg(feature = "dcap")]
+            SGXError::CollateralParseError(_) | SGXError::UnsupportedQuoteFormat(_) => {
+                SGXErrorKind::Malformed
+            }
+            #[cfg(any(feature = "broker", feature = "channel"))]
+            SGXError::KeyExchangeFailed(_) => SGXErrorKind::Malformed,
+            #[cfg(feature = "ratls")]
+            SGXError::QuoteExtensionNotFound => SGXErrorKind::Malformed,
+            SGXError::VerifyQuoteFailed(_)
+            | SGXError::ReportDataMismatch
+            | SGXError::AppraisalRejected(_)
+            | SGXError::CollateralExpired => SGXErrorKind::Rejected,
+            #[cfg(feature = "eat")]
+            SGXError::TokenVerificationFailed(_) => SGXErrorKind::Rejected,
+            #[cfg(any(feature = "broker", feature = "channel"))]
+            SGXError::DecryptionFailed(_) => SGXErrorKind::Rejected,
+            #[cfg(feature = "broker")]
+            SGXError::SecretNotFound(_) => SGXErrorKind::Rejected,
+            #[cfg(feature = "audit")]
+            SGXError::AuditChainBroken { .. } => SGXErrorKind::Rejected,
+            #[cfg(feature = "pccs")]
+            SGXError::PccsRequestFailed { status, .. } => match status {
+                Some(400..=499) => SGXErrorKind::Other,
+                _ => SGXErrorKind::PccsUnreachable,
+            },
+            #[cfg(feature = "broker")]
+            SGXError::BrokerRequestFailed { status, .. } => match status {
+                Some(400) => SGXErrorKind::Malformed,
+                Some(403) => SGXErrorKind::Rejected,
+                Some(503) | None => SGXErrorKind::PccsUnreachable,
+                Some(_) => SGXErrorKind::Other,
+            },
+            #[cfg(feature = "grpc")]
+            SGXError::RpcFailed { code, .. } => match *code {
+                RPC_INVALID_ARGUMENT => SGXErrorKind::Malformed,
+                RPC_PERMISSION_DENIED => SGXErrorKind::Rejected,
+                RPC_UNAVAILABLE => SGXErrorKind::PccsUnreachable,
+                _ => SGXErrorKind::Other,
+            },
+            SGXError::IoError(_) => SGXErrorKind::Io,
+            SGXError::UnsupportedAttestationType(_)
+            | SGXError::TsmGenerationMismatch { .. }
+            | SGXError::UnsupportedTsmProvider(_) => SGXErrorKind::Other,
+            #[cfg(feature = "tokio")]
+            SGXError::BlockingTaskFailed(_) => SGXErrorKind::Other,
+            #[cfg(feature = "eat")]
+            SGXError::TokenEncodingError(_) => SGXErrorKind::Other,
+            #[cfg(feature = "qvl")]
+            SGXError::LibraryLoadFailed(_) | SGXError::QvlError { .. } => SGXErrorKind::Other,
+        }
+    }
+
+    /// OS error code of the failed `open` or `ioctl`
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            SGXError::DeviceOpenFailed { source, .. }
+            | SGXError::IoctlClientError { source, .. }
+            | SGXError::IoError(source) => source.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the same request may succeed later, e.g. when PCCS is back
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SGXError::IoError(e) => matches!(
+                e.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ),
+            SGXError::TsmGenerationMismatch { .. } => true,
+            _ => self.kind() == SGXErrorKind::PccsUnreachable,
         }
     }
 }
 
-impl error::Error for SGXError {}
+impl error::Error for SGXError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        let source = match self {
+            SGXError::DeviceOpenFailed { source, .. }
+            | SGXError::IoctlClientError { source, .. }
+            | SGXError::IoError(source) => return Some(source),
+            SGXError::CertificateParseError(source)
+            | SGXError::DerParseError(source)
+            | SGXError::SGXMeasurementParseError(source)
+            | SGXError::SigningKeyParseError(source)
+            | SGXError::SigStructParseError(source)
+            | SGXError::EnclaveImageParseError(source) => source,
+            #[cfg(feature = "tokio")]
+            SGXError::BlockingTaskFailed(source) => source,
+            #[cfg(feature = "cmw")]
+            SGXError::CmwParseError(source) => source,
+            #[cfg(feature = "qvl")]
+            SGXError::LibraryLoadFailed(source) => source,
+            #[cfg(feature = "eat")]
+            SGXError::TokenKeyParseError(source)
+            | SGXError::TokenEncodingError(source)
+            | SGXError::TokenParseError(source) => source,
+            #[cfg(feature = "dcap")]
+            SGXError::CollateralParseError(source) => source,
+            #[cfg(any(feature = "broker", feature = "channel"))]
+            SGXError::DecryptionFailed(source) => source,
+            _ => return None,
+        };
+        // The message is already part of the display
+        if source.is::<Message>() {
+            return None;
+        }
+        Some(source.as_ref())
+    }
+}
+
+impl From<io::Error> for SGXError {
+    fn from(e: io::Error) -> Self {
+        SGXError::IoError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_and_retryability() {
+        let ioctl_error = |errno| SGXError::IoctlClientError {
+            request_type: "IOCTL_GEN_DCAP_QUOTE",
+            source: io::Error::from_raw_os_error(errno),
+        };
+
+        let e = ioctl_error(libc::EAGAIN);
+        assert_eq!(e.kind(), SGXErrorKind::PccsUnreachable);
+        assert!(e.is_retryable());
+        assert_eq!(e.errno(), Some(libc::EAGAIN));
+        assert!(error::Error::source(&e).is_some());
+        assert_eq!(
+            e.to_string(),
+            format!("Failed IOCTL_GEN_DCAP_QUOTE (errno {})", libc::EAGAIN)
+        );
+
+        let e = ioctl_error(libc::EINVAL);
+        assert_eq!(e.kind(), SGXErrorKind::Malformed);
+        assert!(!e.is_retryable());
+
+        let e = SGXError::DeviceOpenFailed {
+            path: "/dev/sgx".into(),
+            source: io::Error::from_raw_os_error(libc::ENOENT),
+        };
+        assert_eq!(e.kind(), SGXErrorKind::NotInOcclum);
+        assert!(!e.is_retryable());
+
+        let e = SGXError::VerifyQuoteFailed(SGXQuoteVerifyResult::Revoked);
+        assert_eq!(e.kind(), SGXErrorKind::Rejected);
+        assert!(error::Error::source(&e).is_none());
+    }
+
+    #[test]
+    fn boxed_source() {
+        let e = crate::SGXMeasurement::from_hex("zz").unwrap_err();
+        assert_eq!(e.kind(), SGXErrorKind::Malformed);
+        let source = error::Error::source(&e).unwrap();
+        assert!(source.is::<hex::FromHexError>());
+        assert_eq!(
+            e.to_string(),
+            format!("Failed to parse SGX measurement: {}", source)
+        );
+
+        // A message is only displayed, not repeated as the source
+        let e = crate::SGXMeasurement::from_hex("00").unwrap_err();
+        assert!(error::Error::source(&e).is_none());
+        assert_eq!(
+            e.to_string(),
+            "Failed to parse SGX measurement: Bad length, required 32 bytes, received: 1"
+        );
+    }
+}
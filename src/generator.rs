@@ -0,0 +1,107 @@
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::gramine::GramineAttestation;
+use crate::instrument;
+use crate::ioctl::{self, IoctlClient};
+use crate::retry::RetryPolicy;
+use crate::telemetry::{self, debug};
+use crate::{ReportData, SGXError, SGXReportData};
+
+/// Backend which produces raw quotes inside the enclave, e.g. the Occlum ioctls or the Gramine
+/// `/dev/attestation` pseudo-files.
+pub trait QuoteGenerator: Send + Sync {
+    fn generate_quote(&self, report_data: &ReportData) -> Result<Vec<u8>, SGXError>;
+}
+
+impl QuoteGenerator for IoctlClient {
+    fn generate_quote(&self, report_data: &ReportData) -> Result<Vec<u8>, SGXError> {
+        IoctlClient::generate_quote(self, SGXReportData::new(*report_data))
+    }
+}
+
+impl<F> QuoteGenerator for F
+where
+    F: Fn(&ReportData) -> Result<Vec<u8>, SGXError> + Send + Sync,
+{
+    fn generate_quote(&self, report_data: &ReportData) -> Result<Vec<u8>, SGXError> {
+        self(report_data)
+    }
+}
+
+/// Occlum ioctls through the client configured with
+/// [`IoctlClientConfig::set_global`](crate::IoctlClientConfig::set_global)
+struct GlobalIoctlClient;
+
+impl QuoteGenerator for GlobalIoctlClient {
+    fn generate_quote(&self, report_data: &ReportData) -> Result<Vec<u8>, SGXError> {
+        QuoteGenerator::generate_quote(ioctl::global_client(), report_data)
+    }
+}
+
+static QUOTE_GENERATOR: OnceLock<Box<dyn QuoteGenerator>> = OnceLock::new();
+
+/// Use `generator` for [`SGXQuote::from_report_data`](crate::SGXQuote::from_report_data).
+///
+/// Must be called before the first quote is generated, otherwise the generator is returned back
+/// as an error. If not set, the backend is detected on the first call: Gramine if
+/// `/dev/attestation/quote` exists, the Occlum ioctls otherwise.
+pub fn set_quote_generator<G: QuoteGenerator + 'static>(generator: G) -> Result<(), G> {
+    let mut generator = Some(generator);
+    QUOTE_GENERATOR.get_or_init(|| Box::new(generator.take().unwrap()));
+    match generator {
+        Some(generator) => Err(generator),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn global_generator() -> &'static dyn QuoteGenerator {
+    QUOTE_GENERATOR
+        .get_or_init(|| {
+            let gramine = GramineAttestation::default();
+            if gramine.is_available() {
+                debug!("Using Gramine attestation backend");
+                Box::new(gramine)
+            } else {
+                debug!("Using Occlum ioctl attestation backend");
+                Box::new(GlobalIoctlClient)
+            }
+        })
+        .as_ref()
+}
+
+/// Generator of [`SGXQuote::from_report_data`](crate::SGXQuote::from_report_data), for the
+/// components which can be given another one
+#[cfg(any(feature = "broker", feature = "channel", feature = "grpc"))]
+pub(crate) struct GlobalGenerator;
+
+#[cfg(any(feature = "broker", feature = "channel", feature = "grpc"))]
+impl QuoteGenerator for GlobalGenerator {
+    fn generate_quote(&self, report_data: &ReportData) -> Result<Vec<u8>, SGXError> {
+        global_generator().generate_quote(report_data)
+    }
+}
+
+/// [`QuoteGenerator::generate_quote`] retried according to the `policy`
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "generate_quote", skip_all, fields(quote_hash, mrenclave))
+)]
+pub(crate) fn generate_with_retry(
+    generator: &dyn QuoteGenerator,
+    report_data: &ReportData,
+    policy: &RetryPolicy,
+) -> Result<Vec<u8>, SGXError> {
+    let start = Instant::now();
+    let mut attempt = 0;
+    let result = policy.run("generate quote", || {
+        attempt += 1;
+        instrument::quote_generation_attempt(attempt > 1);
+        generator.generate_quote(report_data)
+    });
+    if let Ok(quote) = &result {
+        telemetry::record_quote(quote);
+    }
+    instrument::quote_generated(start, &result);
+    result
+}
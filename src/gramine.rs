@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use crate::generator::QuoteGenerator;
+use crate::telemetry::trace;
+use crate::{ReportData, SGXError};
+
+/// Default mount point of the Gramine attestation pseudo-filesystem
+pub const GRAMINE_ATTESTATION_PATH: &str = "/dev/attestation";
+
+/// Gramine backend, generates quotes with the `/dev/attestation` pseudo-files: the report data is
+/// written to `user_report_data`, then the quote is read from `quote`.
+///
+/// @see https://gramine.readthedocs.io/en/stable/attestation.html
+///
+/// # Example
+/// ```rust ignore
+/// set_quote_generator(GramineAttestation::default()).unwrap();
+/// let quote = SGXQuote::from_report_data(&report_data)?;
+/// ```
+pub struct GramineAttestation {
+    root: PathBuf,
+    // `user_report_data` and `quote` are per-process, writes and reads must not interleave
+    lock: Mutex<()>,
+}
+
+impl GramineAttestation {
+    /// Backend with the pseudo-files in `root` instead of `/dev/attestation`
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns `true` if running inside Gramine with DCAP attestation
+    pub fn is_available(&self) -> bool {
+        self.root.join("quote").exists()
+            && self
+                .attestation_type()
+                .is_ok_and(|attestation_type| attestation_type == "dcap")
+    }
+
+    /// Attestation type configured in the manifest (`sgx.remote_attestation`): `none`, `epid` or
+    /// `dcap`
+    pub fn attestation_type(&self) -> Result<String, SGXError> {
+        let attestation_type = fs::read_to_string(self.root.join("attestation_type"))?;
+        Ok(attestation_type.trim().to_string())
+    }
+}
+
+impl Default for GramineAttestation {
+    fn default() -> Self {
+        Self::new(GRAMINE_ATTESTATION_PATH)
+    }
+}
+
+impl QuoteGenerator for GramineAttestation {
+    fn generate_quote(&self, report_data: &ReportData) -> Result<Vec<u8>, SGXError> {
+        let attestation_type = self.attestation_type()?;
+        if attestation_type != "dcap" {
+            return Err(SGXError::UnsupportedAttestationType(attestation_type));
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        trace!("Write Gramine user_report_data");
+        fs::write(self.root.join("user_report_data"), report_data)?;
+        trace!("Read Gramine quote");
+        Ok(fs::read(self.root.join("quote"))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SGXQuote;
+
+    #[test]
+    fn generate_quote() {
+        let root = tempfile::tempdir().unwrap();
+        let gramine = GramineAttestation::new(root.path());
+        assert!(!gramine.is_available());
+
+        let quote = include_bytes!("../tests/fixtures/quote.raw");
+        fs::write(root.path().join("attestation_type"), "dcap\n").unwrap();
+        fs::write(root.path().join("user_report_data"), [0u8; 64]).unwrap();
+        fs::write(root.path().join("quote"), quote).unwrap();
+        assert!(gramine.is_available());
+
+        let buf = gramine.generate_quote(&[7u8; 64]).unwrap();
+        assert_eq!(SGXQuote::from_slice(&buf).unwrap().as_slice(), quote);
+        assert_eq!(
+            fs::read(root.path().join("user_report_data")).unwrap(),
+            [7u8; 64]
+        );
+
+        fs::write(root.path().join("attestation_type"), "epid").unwrap();
+        assert!(!gramine.is_available());
+        assert!(matches!(
+            gramine.generate_quote(&[7u8; 64]),
+            Err(SGXError::UnsupportedAttestationType(_))
+        ));
+    }
+}
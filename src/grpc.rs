@@ -0,0 +1,467 @@
+//! gRPC attestation service
+//!
+//! The [`proto`] definitions of `proto/attestation.proto` with a tonic server, [`AttestationAgent`],
+//! and client, [`AttestationGrpcClient`]. The agent serves the enabled backends only, the others
+//! fail with `UNIMPLEMENTED`:
+//!
+//! - a local agent next to the enclave workloads generates quotes in the Occlum instance
+//! - a central verifier verifies quotes with a `VerificationService`, issues nonces and serves
+//!   the collateral of a [`CollateralProvider`]
+//!
+//! The verification backend, [`AttestationAgent::with_verification`], needs the `grpc-verifier`
+//! feature, so that the local agent doesn't link the verification service.
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::grpc::{AttestationAgent, AttestationGrpcClient};
+//!
+//! // local agent, in the Occlum instance
+//! let agent = AttestationAgent::new().with_quote_generation(RetryPolicy::default());
+//! Server::builder()
+//!     .add_service(agent.into_server())
+//!     .serve("127.0.0.1:50051".parse()?)
+//!     .await?;
+//!
+//! // enclave workload
+//! let agent = AttestationGrpcClient::connect("http://127.0.0.1:50051").await?;
+//! let quote = agent.generate_quote(&report_data).await?;
+//! ```
+use std::sync::Arc;
+
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status};
+
+use crate::constants::SGX_REPORT_DATA_SIZE;
+use crate::dcap::{CollateralProvider, PckCa};
+use crate::error::message;
+use crate::generator::{generate_with_retry, GlobalGenerator};
+use crate::nonblocking::run_blocking;
+#[cfg(feature = "grpc-verifier")]
+use crate::service::{VerificationService, VerifyRequest};
+use crate::{QuoteGenerator, RetryPolicy, SGXError, SGXErrorKind, SGXQuote, SGXQuoteCollateral};
+
+/// Messages and services generated from `proto/attestation.proto`
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("occlum_sgx.v1");
+}
+
+use proto::attestation_client::AttestationClient;
+use proto::attestation_server::{Attestation, AttestationServer};
+
+pub use crate::api::{NonceResponse, VerifyResponse};
+
+/// Server of the `Attestation` service, see the [module documentation](self)
+#[derive(Clone, Default)]
+pub struct AttestationAgent {
+    generator: Option<(Arc<dyn QuoteGenerator>, RetryPolicy)>,
+    #[cfg(feature = "grpc-verifier")]
+    verification: Option<Arc<VerificationService>>,
+    collateral: Option<Arc<dyn CollateralProvider>>,
+}
+
+fn status(e: SGXError) -> Status {
+    let code = match e.kind() {
+        SGXErrorKind::Malformed => Code::InvalidArgument,
+        SGXErrorKind::Rejected => Code::PermissionDenied,
+        SGXErrorKind::PccsUnreachable => Code::Unavailable,
+        SGXErrorKind::NotInOcclum => Code::FailedPrecondition,
+        _ => Code::Internal,
+    };
+    Status::new(code, e.to_string())
+}
+
+fn unimplemented(name: &str) -> Status {
+    Status::unimplemented(format!("{} is not served by this agent", name))
+}
+
+impl AttestationAgent {
+    /// Agent without backends
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `GenerateQuote` with the backend of [`SGXQuote::from_report_data`]
+    pub fn with_quote_generation(self, retry_policy: RetryPolicy) -> Self {
+        self.with_quote_generator(GlobalGenerator, retry_policy)
+    }
+
+    /// Serve `GenerateQuote` with `generator`
+    pub fn with_quote_generator<G: QuoteGenerator + 'static>(
+        mut self,
+        generator: G,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        self.generator = Some((Arc::new(generator), retry_policy));
+        self
+    }
+
+    /// Serve `VerifyQuote` and `GetNonce` with the service, which may be shared with the HTTP
+    /// [`router`](crate::service::router)
+    #[cfg(feature = "grpc-verifier")]
+    pub fn with_verification(mut self, service: Arc<VerificationService>) -> Self {
+        self.verification = Some(service);
+        self
+    }
+
+    /// Serve `GetCollateral` with the provider
+    pub fn with_collateral<C: CollateralProvider + 'static>(mut self, collateral: C) -> Self {
+        self.collateral = Some(Arc::new(collateral));
+        self
+    }
+
+    pub fn into_server(self) -> AttestationServer<Self> {
+        AttestationServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl Attestation for AttestationAgent {
+    async fn generate_quote(
+        &self,
+        request: Request<proto::GenerateQuoteRequest>,
+    ) -> Result<Response<proto::GenerateQuoteResponse>, Status> {
+        let Some((generator, retry_policy)) = self.generator.clone() else {
+            return Err(unimplemented("GenerateQuote"));
+        };
+        let data = request.into_inner().report_data;
+        if data.len() > SGX_REPORT_DATA_SIZE {
+            return Err(Status::invalid_argument(format!(
+                "report data is longer than {} bytes",
+                SGX_REPORT_DATA_SIZE
+            )));
+        }
+        let mut report_data = [0u8; SGX_REPORT_DATA_SIZE];
+        report_data[..data.len()].copy_from_slice(&data);
+
+        let quote = run_blocking(move || {
+            generate_with_retry(generator.as_ref(), &report_data, &retry_policy)
+        })
+        .await
+        .map_err(status)?;
+        Ok(Response::new(proto::GenerateQuoteResponse { quote }))
+    }
+
+    async fn verify_quote(
+        &self,
+        request: Request<proto::VerifyQuoteRequest>,
+    ) -> Result<Response<proto::VerifyQuoteResponse>, Status> {
+        #[cfg(feature = "grpc-verifier")]
+        if let Some(service) = self.verification.clone() {
+            let request = request.into_inner();
+            let request = VerifyRequest {
+                quote: request.quote,
+                nonce: request.nonce,
+                policy_id: Some(request.policy_id).filter(|id| !id.is_empty()),
+            };
+
+            let response = run_blocking(move || service.verify(&request))
+                .await
+                .map_err(status)?;
+            return Ok(Response::new(proto::VerifyQuoteResponse {
+                token: response.token,
+                tcb_status: response.tcb_status,
+            }));
+        }
+        let _ = request;
+        Err(unimplemented("VerifyQuote"))
+    }
+
+    async fn get_nonce(
+        &self,
+        _request: Request<proto::GetNonceRequest>,
+    ) -> Result<Response<proto::GetNonceResponse>, Status> {
+        #[cfg(feature = "grpc-verifier")]
+        if let Some(service) = &self.verification {
+            return Ok(Response::new(proto::GetNonceResponse {
+                nonce: service.issue_nonce().map_err(status)?,
+                expires_in: service.nonce_ttl().as_secs(),
+            }));
+        }
+        Err(unimplemented("GetNonce"))
+    }
+
+    async fn get_collateral(
+        &self,
+        request: Request<proto::GetCollateralRequest>,
+    ) -> Result<Response<proto::Collateral>, Status> {
+        let Some(provider) = self.collateral.clone() else {
+            return Err(unimplemented("GetCollateral"));
+        };
+        let request = request.into_inner();
+        let ca = match proto::PckCa::try_from(request.ca) {
+            Ok(proto::PckCa::Platform) => PckCa::Platform,
+            Ok(proto::PckCa::Processor) => PckCa::Processor,
+            Err(_) => {
+                return Err(Status::invalid_argument(format!(
+                    "unknown PCK CA {}",
+                    request.ca
+                )))
+            }
+        };
+
+        let collateral = run_blocking(move || provider.collateral(&request.fmspc, ca))
+            .await
+            .map_err(status)?;
+        Ok(Response::new(collateral.into()))
+    }
+}
+
+impl From<SGXQuoteCollateral> for proto::Collateral {
+    fn from(collateral: SGXQuoteCollateral) -> Self {
+        Self {
+            major_version: collateral.major_version.into(),
+            minor_version: collateral.minor_version.into(),
+            tee_type: collateral.tee_type,
+            pck_crl_issuer_chain: collateral.pck_crl_issuer_chain,
+            root_ca_crl: collateral.root_ca_crl,
+            pck_crl: collateral.pck_crl,
+            tcb_info_issuer_chain: collateral.tcb_info_issuer_chain,
+            tcb_info: collateral.tcb_info,
+            qe_identity_issuer_chain: collateral.qe_identity_issuer_chain,
+            qe_identity: collateral.qe_identity,
+        }
+    }
+}
+
+impl TryFrom<proto::Collateral> for SGXQuoteCollateral {
+    type Error = SGXError;
+
+    fn try_from(collateral: proto::Collateral) -> Result<Self, Self::Error> {
+        let version = |version: u32| {
+            u16::try_from(version).map_err(|_| {
+                SGXError::CollateralParseError(message(format!("bad version {}", version)))
+            })
+        };
+        Ok(Self {
+            major_version: version(collateral.major_version)?,
+            minor_version: version(collateral.minor_version)?,
+            tee_type: collateral.tee_type,
+            pck_crl_issuer_chain: collateral.pck_crl_issuer_chain,
+            root_ca_crl: collateral.root_ca_crl,
+            pck_crl: collateral.pck_crl,
+            tcb_info_issuer_chain: collateral.tcb_info_issuer_chain,
+            tcb_info: collateral.tcb_info,
+            qe_identity_issuer_chain: collateral.qe_identity_issuer_chain,
+            qe_identity: collateral.qe_identity,
+        })
+    }
+}
+
+fn rpc_error(status: Status) -> SGXError {
+    SGXError::RpcFailed {
+        code: status.code() as i32,
+        message: status.message().to_string(),
+    }
+}
+
+/// Client of a local or central [`AttestationAgent`], cheap to clone
+#[derive(Clone)]
+pub struct AttestationGrpcClient {
+    client: AttestationClient<Channel>,
+}
+
+impl AttestationGrpcClient {
+    /// Connect to the agent at `endpoint`, e.g. `http://127.0.0.1:50051`
+    pub async fn connect<S: Into<String>>(endpoint: S) -> Result<Self, SGXError> {
+        let channel = Endpoint::from_shared(endpoint.into())
+            .map_err(|e| SGXError::RpcFailed {
+                code: Code::InvalidArgument as i32,
+                message: e.to_string(),
+            })?
+            .connect()
+            .await
+            .map_err(|e| SGXError::RpcFailed {
+                code: Code::Unavailable as i32,
+                message: e.to_string(),
+            })?;
+        Ok(Self::new(channel))
+    }
+
+    /// Client on an existing channel, e.g. with TLS
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            client: AttestationClient::new(channel),
+        }
+    }
+
+    /// Same as [`SGXQuote::from_report_data`], in the Occlum instance of the agent
+    pub async fn generate_quote(&self, report_data: &[u8]) -> Result<SGXQuote, SGXError> {
+        let response = self
+            .client
+            .clone()
+            .generate_quote(proto::GenerateQuoteRequest {
+                report_data: report_data.to_vec(),
+            })
+            .await
+            .map_err(rpc_error)?;
+        SGXQuote::try_from(response.into_inner().quote)
+    }
+
+    /// Verify and appraise the quote with the policy, the default policy if `None`
+    pub async fn verify_quote(
+        &self,
+        quote: &SGXQuote,
+        nonce: &[u8],
+        policy_id: Option<&str>,
+    ) -> Result<VerifyResponse, SGXError> {
+        let response = self
+            .client
+            .clone()
+            .verify_quote(proto::VerifyQuoteRequest {
+                quote: quote.to_vec(),
+                nonce: nonce.to_vec(),
+                policy_id: policy_id.unwrap_or_default().to_string(),
+            })
+            .await
+            .map_err(rpc_error)?
+            .into_inner();
+        Ok(VerifyResponse {
+            token: response.token,
+            tcb_status: response.tcb_status,
+        })
+    }
+
+    pub async fn get_nonce(&self) -> Result<NonceResponse, SGXError> {
+        let response = self
+            .client
+            .clone()
+            .get_nonce(proto::GetNonceRequest {})
+            .await
+            .map_err(rpc_error)?
+            .into_inner();
+        Ok(NonceResponse {
+            nonce: response.nonce,
+            expires_in: response.expires_in,
+        })
+    }
+
+    pub async fn get_collateral(
+        &self,
+        fmspc: &[u8],
+        ca: PckCa,
+    ) -> Result<SGXQuoteCollateral, SGXError> {
+        let ca = match ca {
+            PckCa::Platform => proto::PckCa::Platform,
+            PckCa::Processor => proto::PckCa::Processor,
+        };
+        self.client
+            .clone()
+            .get_collateral(proto::GetCollateralRequest {
+                fmspc: fmspc.to_vec(),
+                ca: ca.into(),
+            })
+            .await
+            .map_err(rpc_error)?
+            .into_inner()
+            .try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+
+    use super::*;
+    use crate::dcap::tests::{Synthetic, FMSPC};
+    use crate::ReportData;
+
+    async fn serve(agent: AttestationAgent) -> AttestationGrpcClient {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(agent.into_server())
+                .serve_with_incoming(incoming),
+        );
+        AttestationGrpcClient::connect(url).await.unwrap()
+    }
+
+    fn local_agent(synthetic: &Arc<Synthetic>) -> AttestationAgent {
+        let generator = synthetic.clone();
+        AttestationAgent::new().with_quote_generator(
+            move |report_data: &ReportData| Ok(generator.quote(*report_data).to_vec()),
+            RetryPolicy::none(),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn local_agent_backends() {
+        let synthetic = Arc::new(Synthetic::new(5, false));
+        let local =
+            serve(local_agent(&synthetic).with_collateral(synthetic.collateral.clone())).await;
+
+        let quote = local.generate_quote(b"nonce").await.unwrap();
+        assert_eq!(&quote.report_data()[..5], b"nonce");
+        assert_eq!(
+            local.get_collateral(&FMSPC, PckCa::Platform).await.unwrap(),
+            synthetic.collateral
+        );
+
+        // Backends which are not served, too long report data, unknown CA
+        let e = local.get_nonce().await.err().unwrap();
+        assert!(
+            matches!(e, SGXError::RpcFailed { code, .. } if code == Code::Unimplemented as i32)
+        );
+        let e = local
+            .verify_quote(&quote, b"nonce", None)
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(e, SGXError::RpcFailed { code, .. } if code == Code::Unimplemented as i32)
+        );
+        let e = local.generate_quote(&[0u8; 65]).await.err().unwrap();
+        assert_eq!(e.kind(), SGXErrorKind::Malformed);
+        let e = local
+            .client
+            .clone()
+            .get_collateral(proto::GetCollateralRequest {
+                fmspc: FMSPC.to_vec(),
+                ca: 7,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(e.code(), Code::InvalidArgument);
+    }
+
+    #[cfg(feature = "grpc-verifier")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn local_agent_and_central_verifier() {
+        use p256::ecdsa::SigningKey;
+
+        use crate::dcap::DcapVerifier;
+        use crate::eat::EatIssuer;
+        use crate::{AppraisalPolicy, DEFAULT_POLICY_ID};
+
+        let synthetic = Arc::new(Synthetic::new(5, false));
+        let local = serve(local_agent(&synthetic)).await;
+
+        let verifier = DcapVerifier::new(synthetic.collateral.clone())
+            .with_trusted_root_pem(&synthetic.root_pem)
+            .unwrap();
+        let issuer = EatIssuer::new(SigningKey::from_slice(&[7u8; 32]).unwrap());
+        let service = VerificationService::new(verifier, issuer)
+            .with_policy(DEFAULT_POLICY_ID, AppraisalPolicy::default());
+        let central = serve(AttestationAgent::new().with_verification(Arc::new(service))).await;
+
+        let nonce = central.get_nonce().await.unwrap();
+        let quote = local.generate_quote(&nonce.nonce).await.unwrap();
+        assert_eq!(&quote.report_data()[..nonce.nonce.len()], nonce.nonce);
+        let response = central
+            .verify_quote(&quote, &nonce.nonce, None)
+            .await
+            .unwrap();
+        assert_eq!(response.tcb_status, "UpToDate");
+
+        // Replayed nonce
+        let e = central
+            .verify_quote(&quote, &nonce.nonce, None)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), SGXErrorKind::Rejected);
+    }
+}
@@ -0,0 +1,161 @@
+//! Single-shot HPKE ([RFC 9180]) in the base mode
+//!
+//! The only supported suite is DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and ChaCha20Poly1305, so the
+//! ciphertexts interoperate with other HPKE implementations configured with it.
+//!
+//! [RFC 9180]: https://www.rfc-editor.org/rfc/rfc9180
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+pub use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::error::message;
+use crate::SGXError;
+
+/// Size of the X25519 keys and of the encapsulated key
+pub const KEY_SIZE: usize = 32;
+
+const MODE_BASE: u8 = 0;
+const KEM_SUITE_ID: &[u8] = b"KEM\x00\x20";
+const HPKE_SUITE_ID: &[u8] = b"HPKE\x00\x20\x00\x01\x00\x03";
+const AEAD_KEY_SIZE: usize = 32;
+const AEAD_NONCE_SIZE: usize = 12;
+
+fn failed(msg: &str) -> SGXError {
+    SGXError::DecryptionFailed(message(msg))
+}
+
+/// New random X25519 key
+pub fn generate_key() -> Result<StaticSecret, SGXError> {
+    let mut key = [0u8; KEY_SIZE];
+    getrandom::getrandom(&mut key).map_err(|e| SGXError::IoError(e.into()))?;
+    Ok(StaticSecret::from(key))
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> [u8; 32] {
+    let labeled_ikm = [&b"HPKE-v1"[..], suite_id, label, ikm].concat();
+    Hkdf::<Sha256>::extract(Some(salt), &labeled_ikm).0.into()
+}
+
+fn labeled_expand<const L: usize>(
+    prk: &[u8; 32],
+    suite_id: &[u8],
+    label: &[u8],
+    info: &[u8],
+) -> [u8; L] {
+    let length = (L as u16).to_be_bytes();
+    let labeled_info = [&length[..], b"HPKE-v1", suite_id, label, info].concat();
+    let mut okm = [0u8; L];
+    Hkdf::<Sha256>::from_prk(prk)
+        .ok()
+        .and_then(|hkdf| hkdf.expand(&labeled_info, &mut okm).ok())
+        .expect("SHA-256 PRK and at most 8160 bytes of output");
+    okm
+}
+
+/// DHKEM shared secret of the DH output and `enc || pkR`
+fn shared_secret(dh: &[u8], enc: &[u8], recipient: &PublicKey) -> [u8; 32] {
+    let eae_prk = labeled_extract(KEM_SUITE_ID, &[], b"eae_prk", dh);
+    let kem_context = [enc, recipient.as_bytes()].concat();
+    labeled_expand(&eae_prk, KEM_SUITE_ID, b"shared_secret", &kem_context)
+}
+
+/// AEAD of the base mode key schedule
+fn key_schedule(shared_secret: &[u8], info: &[u8]) -> (ChaCha20Poly1305, [u8; AEAD_NONCE_SIZE]) {
+    let psk_id_hash = labeled_extract(HPKE_SUITE_ID, &[], b"psk_id_hash", &[]);
+    let info_hash = labeled_extract(HPKE_SUITE_ID, &[], b"info_hash", info);
+    let context = [&[MODE_BASE][..], &psk_id_hash, &info_hash].concat();
+
+    let secret = labeled_extract(HPKE_SUITE_ID, shared_secret, b"secret", &[]);
+    let key: [u8; AEAD_KEY_SIZE] = labeled_expand(&secret, HPKE_SUITE_ID, b"key", &context);
+    let nonce = labeled_expand(&secret, HPKE_SUITE_ID, b"base_nonce", &context);
+    (ChaCha20Poly1305::new(&key.into()), nonce)
+}
+
+/// Encrypt `plaintext` to the `recipient`, returns the encapsulated key and the ciphertext
+pub fn seal(
+    recipient: &PublicKey,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; KEY_SIZE], Vec<u8>), SGXError> {
+    let ephemeral = generate_key()?;
+    let enc = PublicKey::from(&ephemeral).to_bytes();
+    let dh = ephemeral.diffie_hellman(recipient);
+    if !dh.was_contributory() {
+        return Err(SGXError::KeyExchangeFailed(
+            "recipient key is a low order point".to_string(),
+        ));
+    }
+
+    let (aead, nonce) = key_schedule(&shared_secret(dh.as_bytes(), &enc, recipient), info);
+    let ciphertext = aead
+        .encrypt(
+            &nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| failed("encryption failed"))?;
+    Ok((enc, ciphertext))
+}
+
+/// Decrypt the `ciphertext` sealed to the public key of `secret`
+pub fn open(
+    secret: &StaticSecret,
+    enc: &[u8],
+    info: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, SGXError> {
+    let ephemeral: [u8; KEY_SIZE] = enc
+        .try_into()
+        .map_err(|_| failed("bad encapsulated key length"))?;
+    let dh = secret.diffie_hellman(&PublicKey::from(ephemeral));
+    if !dh.was_contributory() {
+        return Err(failed("encapsulated key is a low order point"));
+    }
+
+    let recipient = PublicKey::from(secret);
+    let (aead, nonce) = key_schedule(&shared_secret(dh.as_bytes(), enc, &recipient), info);
+    aead.decrypt(
+        &nonce.into(),
+        Payload {
+            msg: ciphertext,
+            aad,
+        },
+    )
+    .map_err(|_| failed("authentication failed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let secret = StaticSecret::from([0x42u8; KEY_SIZE]);
+        let (enc, ciphertext) =
+            seal(&PublicKey::from(&secret), b"info", b"aad", b"secret").unwrap();
+        assert_eq!(
+            open(&secret, &enc, b"info", b"aad", &ciphertext).unwrap(),
+            b"secret"
+        );
+        assert!(open(&secret, &enc, b"info", b"other", &ciphertext).is_err());
+        assert!(open(&secret, &[0u8; KEY_SIZE], b"info", b"aad", &ciphertext).is_err());
+
+        // Sealed by another implementation (pyca/cryptography) with an empty AAD
+        let interop = hex::decode(INTEROP).unwrap();
+        let (enc, ciphertext) = interop.split_at(KEY_SIZE);
+        assert_eq!(
+            open(&secret, enc, b"info", &[], ciphertext).unwrap(),
+            b"interop"
+        );
+    }
+
+    const INTEROP: &str = "132f40579159bb833ac30fc8432b6401dbd590821a8001f3967decacf832b858\
+                           347e82ad241343dfc5b774cf78665363f070999c1a99ef";
+}
@@ -0,0 +1,124 @@
+//! HTTP building blocks of the verification service and the key broker
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+
+use crate::nonblocking::run_blocking;
+use crate::telemetry::info;
+use crate::{SGXError, SGXErrorKind};
+
+pub(crate) const NONCE_SIZE: usize = 32;
+
+/// Outstanding nonces above which `GET /nonce` fails until some are consumed or expire
+const MAX_OUTSTANDING_NONCES: usize = 65536;
+
+/// Issued single-use nonces which are not expired yet
+pub(crate) struct Nonces {
+    ttl: Duration,
+    capacity: usize,
+    issued: Mutex<HashMap<Vec<u8>, Instant>>,
+}
+
+impl Nonces {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            capacity: MAX_OUTSTANDING_NONCES,
+            issued: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub(crate) fn issue(&self) -> Result<Vec<u8>, SGXError> {
+        let mut nonce = vec![0u8; NONCE_SIZE];
+        getrandom::getrandom(&mut nonce).map_err(|e| SGXError::IoError(e.into()))?;
+
+        let mut issued = self.issued.lock().unwrap();
+        issued.retain(|_, at| at.elapsed() < self.ttl);
+        if issued.len() >= self.capacity {
+            return Err(SGXError::IoError(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "too many outstanding nonces",
+            )));
+        }
+        issued.insert(nonce.clone(), Instant::now());
+        Ok(nonce)
+    }
+
+    /// Consume the nonce, fails if it was not issued, expired or was already used
+    pub(crate) fn consume(&self, nonce: &[u8]) -> Result<(), SGXError> {
+        match self.issued.lock().unwrap().remove(nonce) {
+            Some(at) if at.elapsed() < self.ttl => Ok(()),
+            _ => Err(SGXError::AppraisalRejected(
+                "unknown or expired nonce".to_string(),
+            )),
+        }
+    }
+}
+
+/// Error response `{"error": "..."}` with the status of the [`SGXErrorKind`]
+pub(crate) struct ErrorResponse(pub SGXError);
+
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Response {
+        let status = match self.0.kind() {
+            SGXErrorKind::Malformed => StatusCode::BAD_REQUEST,
+            SGXErrorKind::Rejected => StatusCode::FORBIDDEN,
+            _ if self.0.is_retryable() => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        info!("Request failed: {}", self.0);
+        let body = serde_json::json!({ "error": self.0.to_string() });
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Run the blocking handler on the tokio blocking pool
+pub(crate) async fn blocking<T, F>(f: F) -> Result<Json<T>, ErrorResponse>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, SGXError> + Send + 'static,
+{
+    run_blocking(f).await.map(Json).map_err(ErrorResponse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outstanding_nonces_bounded() {
+        let nonces = Nonces {
+            capacity: 2,
+            ..Nonces::new(Duration::from_secs(60))
+        };
+        let first = nonces.issue().unwrap();
+        nonces.issue().unwrap();
+        let e = nonces.issue().unwrap_err();
+        assert!(e.is_retryable());
+        assert_eq!(
+            ErrorResponse(e).into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        // Consuming a nonce makes room for the next one
+        nonces.consume(&first).unwrap();
+        nonces.issue().unwrap();
+
+        // As does expiring them
+        let nonces = Nonces {
+            capacity: 1,
+            ..Nonces::new(Duration::ZERO)
+        };
+        nonces.issue().unwrap();
+        nonces.issue().unwrap();
+    }
+}
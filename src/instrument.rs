@@ -0,0 +1,266 @@
+//! Instrumentation with the [metrics] crate
+//!
+//! With the `metrics` feature, the attestation requests are recorded to the recorder installed by
+//! the application (e.g. the Prometheus exporter), see [`describe_metrics`] for the names. Without
+//! it, these are no-ops.
+//!
+//! [metrics]: https://docs.rs/metrics
+use std::time::Instant;
+
+use crate::{SGXError, SGXQuoteVerifyResult};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "metrics")] {
+        use metrics::{counter, histogram};
+
+        const QUOTE_GENERATION_SECONDS: &str = "occlum_sgx_quote_generation_seconds";
+        const QUOTE_GENERATION_ATTEMPTS: &str = "occlum_sgx_quote_generation_attempts_total";
+        const QUOTE_VERIFICATION_SECONDS: &str = "occlum_sgx_quote_verification_seconds";
+        const QUOTE_VERIFICATIONS: &str = "occlum_sgx_quote_verifications_total";
+        const COLLATERAL_CACHE_HITS: &str = "occlum_sgx_collateral_cache_hits_total";
+        const COLLATERAL_CACHE_MISSES: &str = "occlum_sgx_collateral_cache_misses_total";
+        const PCCS_FETCH_ERRORS: &str = "occlum_sgx_pccs_fetch_errors_total";
+        const IOCTL_ERRORS: &str = "occlum_sgx_ioctl_errors_total";
+        const VERIFY_REQUESTS: &str = "occlum_sgx_verify_requests_total";
+        const NONCES_ISSUED: &str = "occlum_sgx_nonces_issued_total";
+
+        /// Register the descriptions of the crate metrics with the installed recorder:
+        ///
+        /// | Metric                                     | Type      | Labels                          |
+        /// |--------------------------------------------|-----------|---------------------------------|
+        /// | `occlum_sgx_quote_generation_seconds`      | histogram | `outcome`: `ok` or `error`      |
+        /// | `occlum_sgx_quote_generation_attempts_total` | counter | `retry`: `true` or `false`      |
+        /// | `occlum_sgx_quote_verification_seconds`    | histogram | `backend`: `ioctl`, `dcap`, `qvl` |
+        /// | `occlum_sgx_quote_verifications_total`     | counter   | `backend`, `result`: TCB status or `error` |
+        /// | `occlum_sgx_collateral_cache_hits_total`   | counter   |                                 |
+        /// | `occlum_sgx_collateral_cache_misses_total` | counter   |                                 |
+        /// | `occlum_sgx_pccs_fetch_errors_total`       | counter   | `status`: HTTP status or `none` |
+        /// | `occlum_sgx_ioctl_errors_total`            | counter   | `request`, `errno`              |
+        /// | `occlum_sgx_verify_requests_total`         | counter   | `outcome`: `accepted`, `rejected` or `failed` |
+        /// | `occlum_sgx_nonces_issued_total`           | counter   |                                 |
+        pub fn describe_metrics() {
+            use metrics::{describe_counter, describe_histogram, Unit};
+
+            describe_histogram!(
+                QUOTE_GENERATION_SECONDS,
+                Unit::Seconds,
+                "Quote generation latency, including retries"
+            );
+            describe_counter!(QUOTE_GENERATION_ATTEMPTS, "Quote generation attempts");
+            describe_histogram!(
+                QUOTE_VERIFICATION_SECONDS,
+                Unit::Seconds,
+                "Quote verification latency, including collateral fetches"
+            );
+            describe_counter!(QUOTE_VERIFICATIONS, "Quote verifications by result");
+            describe_counter!(COLLATERAL_CACHE_HITS, "Collateral served from the cache");
+            describe_counter!(COLLATERAL_CACHE_MISSES, "Collateral fetched from PCCS");
+            describe_counter!(PCCS_FETCH_ERRORS, "Failed PCCS requests");
+            describe_counter!(IOCTL_ERRORS, "Failed Occlum ioctls by errno");
+            describe_counter!(VERIFY_REQUESTS, "Requests to the verification service by outcome");
+            describe_counter!(NONCES_ISSUED, "Nonces issued by the verification service");
+        }
+
+        fn outcome<T, E>(result: &Result<T, E>) -> &'static str {
+            if result.is_ok() { "ok" } else { "error" }
+        }
+
+        pub(crate) fn quote_generated<T>(start: Instant, result: &Result<T, SGXError>) {
+            histogram!(QUOTE_GENERATION_SECONDS, "outcome" => outcome(result))
+                .record(start.elapsed());
+        }
+
+        pub(crate) fn quote_generation_attempt(retry: bool) {
+            counter!(QUOTE_GENERATION_ATTEMPTS, "retry" => retry.to_string()).increment(1);
+        }
+
+        pub(crate) fn quote_verified(
+            backend: &'static str,
+            start: Instant,
+            result: Result<SGXQuoteVerifyResult, &SGXError>,
+        ) {
+            histogram!(QUOTE_VERIFICATION_SECONDS, "backend" => backend).record(start.elapsed());
+            let result = result.map_or("error", |result| result.tcb_status());
+            counter!(QUOTE_VERIFICATIONS, "backend" => backend, "result" => result).increment(1);
+        }
+
+        #[cfg_attr(not(feature = "pccs"), allow(dead_code))]
+        pub(crate) fn collateral_cache(hit: bool) {
+            let name = if hit { COLLATERAL_CACHE_HITS } else { COLLATERAL_CACHE_MISSES };
+            counter!(name).increment(1);
+        }
+
+        #[cfg_attr(not(feature = "pccs"), allow(dead_code))]
+        pub(crate) fn pccs_fetch_failed(status: Option<u16>) {
+            let status = status.map_or_else(|| "none".to_string(), |status| status.to_string());
+            counter!(PCCS_FETCH_ERRORS, "status" => status).increment(1);
+        }
+
+        pub(crate) fn ioctl_failed(request: &'static str, errno: Option<i32>) {
+            let errno = errno.map_or_else(|| "none".to_string(), |errno| errno.to_string());
+            counter!(IOCTL_ERRORS, "request" => request, "errno" => errno).increment(1);
+        }
+
+        #[cfg_attr(not(feature = "service"), allow(dead_code))]
+        pub(crate) fn verify_request(outcome: &'static str) {
+            counter!(VERIFY_REQUESTS, "outcome" => outcome).increment(1);
+        }
+
+        #[cfg_attr(not(feature = "service"), allow(dead_code))]
+        pub(crate) fn nonce_issued() {
+            counter!(NONCES_ISSUED).increment(1);
+        }
+    } else {
+        pub(crate) fn quote_generated<T>(_: Instant, _: &Result<T, SGXError>) {}
+
+        pub(crate) fn quote_generation_attempt(_: bool) {}
+
+        pub(crate) fn quote_verified(
+            _: &'static str,
+            _: Instant,
+            _: Result<SGXQuoteVerifyResult, &SGXError>,
+        ) {
+        }
+
+        #[cfg_attr(not(feature = "pccs"), allow(dead_code))]
+        pub(crate) fn collateral_cache(_: bool) {}
+
+        #[cfg_attr(not(feature = "pccs"), allow(dead_code))]
+        pub(crate) fn pccs_fetch_failed(_: Option<u16>) {}
+
+        pub(crate) fn ioctl_failed(_: &'static str, _: Option<i32>) {}
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+
+    use super::*;
+    use crate::generator::generate_with_retry;
+    use crate::{ReportData, RetryPolicy};
+
+    /// Counter value or number of samples of a histogram
+    struct Samples(AtomicU64);
+
+    impl CounterFn for Samples {
+        fn increment(&self, value: u64) {
+            self.0.fetch_add(value, Ordering::Relaxed);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0.store(value, Ordering::Relaxed);
+        }
+    }
+
+    impl HistogramFn for Samples {
+        fn record(&self, _: f64) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counter values and histogram sample counts by `Key(name, [label = value, ...])`
+    #[derive(Default)]
+    struct TestRecorder(Mutex<HashMap<String, Arc<Samples>>>);
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Samples> {
+            let mut handles = self.0.lock().unwrap();
+            let handle = handles
+                .entry(key.to_string())
+                .or_insert_with(|| Arc::new(Samples(AtomicU64::new(0))));
+            handle.clone()
+        }
+
+        fn value(&self, key: &str) -> u64 {
+            self.0.lock().unwrap()[key].0.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    #[test]
+    fn record_metrics() {
+        let recorder = TestRecorder::default();
+        metrics::with_local_recorder(&recorder, || {
+            describe_metrics();
+            let generator = |_: &ReportData| Ok(vec![0u8; 16]);
+            generate_with_retry(&generator, &[0; 64], &RetryPolicy::none()).unwrap();
+            verify_request("accepted");
+            nonce_issued();
+            quote_verified("ioctl", Instant::now(), Ok(SGXQuoteVerifyResult::OutOfDate));
+            quote_verified("ioctl", Instant::now(), Ok(SGXQuoteVerifyResult::OutOfDate));
+            quote_verified("dcap", Instant::now(), Err(&SGXError::CollateralExpired));
+            collateral_cache(true);
+            collateral_cache(false);
+            collateral_cache(false);
+            pccs_fetch_failed(Some(404));
+            ioctl_failed("IOCTL_GEN_DCAP_QUOTE", Some(libc::EBUSY));
+        });
+
+        let value = |key: &str| recorder.value(key);
+        assert_eq!(
+            value("Key(occlum_sgx_quote_generation_seconds, [outcome = ok])"),
+            1
+        );
+        assert_eq!(
+            value("Key(occlum_sgx_quote_generation_attempts_total, [retry = false])"),
+            1
+        );
+        assert_eq!(
+            value("Key(occlum_sgx_verify_requests_total, [outcome = accepted])"),
+            1
+        );
+        assert_eq!(value("Key(occlum_sgx_nonces_issued_total)"), 1);
+        assert_eq!(
+            value("Key(occlum_sgx_quote_verification_seconds, [backend = ioctl])"),
+            2
+        );
+        assert_eq!(
+            value(
+                "Key(occlum_sgx_quote_verifications_total, [backend = ioctl, result = OutOfDate])"
+            ),
+            2
+        );
+        assert_eq!(
+            value("Key(occlum_sgx_quote_verifications_total, [backend = dcap, result = error])"),
+            1
+        );
+        assert_eq!(value("Key(occlum_sgx_collateral_cache_hits_total)"), 1);
+        assert_eq!(value("Key(occlum_sgx_collateral_cache_misses_total)"), 2);
+        assert_eq!(
+            value("Key(occlum_sgx_pccs_fetch_errors_total, [status = 404])"),
+            1
+        );
+        assert_eq!(
+            value(&format!(
+                "Key(occlum_sgx_ioctl_errors_total, [request = IOCTL_GEN_DCAP_QUOTE, errno = {}])",
+                libc::EBUSY
+            )),
+            1
+        );
+    }
+}
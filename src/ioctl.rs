@@ -1,13 +1,22 @@
-use std::{ffi::CString, sync::Mutex};
-
-use log::trace;
+use std::{
+    ffi::CString,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, OnceLock,
+    },
+};
 
+use crate::telemetry::{trace, warn};
 use crate::{
     constants::{
-        IOCTL_GEN_DCAP_QUOTE, IOCTL_GET_DCAP_QUOTE_SIZE, IOCTL_GET_DCAP_SUPPLEMENTAL_SIZE,
-        IOCTL_VER_DCAP_QUOTE,
+        IoctlRequest, OCCLUM_SGX_DEVICE_PATH, SGXIOC_GEN_DCAP_QUOTE, SGXIOC_GET_DCAP_QUOTE_SIZE,
+        SGXIOC_GET_DCAP_SUPPLEMENTAL_SIZE, SGXIOC_VER_DCAP_QUOTE,
     },
     error::SGXError,
+    instrument,
     types::{SGXQuoteVerifyResult, SGXReportData},
 };
 
@@ -21,120 +30,287 @@ pub struct IoctlGenDCAPQuoteArg {
 
 #[repr(C)]
 pub struct IoctlVerDCAPQuoteArg {
-    pub quote_buf: *const u8,                                 // Input
-    pub quote_size: u32,                                      // Input
-    pub collateral_expiration_status: *mut u32,               // Output
-    pub quote_verification_result: *mut SGXQuoteVerifyResult, // Output
-    pub supplemental_data_size: u32,                          // Input (optional)
-    pub supplemental_data: *mut u8,                           // Output (optional)
+    pub quote_buf: *const u8,                   // Input
+    pub quote_size: u32,                        // Input
+    pub collateral_expiration_status: *mut u32, // Output
+    pub quote_verification_result: *mut u32,    // Output, `sgx_ql_qv_result_t`
+    pub supplemental_data_size: u32,            // Input (optional)
+    pub supplemental_data: *mut u8,             // Output (optional)
+}
+
+/// Device and ioctl request codes of the LibOS.
+///
+/// The default is [`IoctlClientConfig::occlum`], other values allow to drive forks of Occlum or
+/// custom LibOS builds with the same DCAP ioctl interface.
+///
+/// # Example
+/// ```rust ignore
+/// IoctlClientConfig {
+///     device_path: "/dev/attestation".into(),
+///     ..IoctlClientConfig::occlum()
+/// }
+/// .set_global()
+/// .unwrap();
+/// let quote = SGXQuote::from_report_data(&report_data)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoctlClientConfig {
+    pub device_path: PathBuf,
+    /// Flags of `open(2)`
+    pub open_flags: i32,
+    pub get_quote_size: u64,
+    pub gen_quote: u64,
+    pub get_supplemental_size: u64,
+    pub verify_quote: u64,
 }
 
-lazy_static! {
-    pub static ref IOCTL_CLIENT: Mutex<IoctlClient> = {
-        let client = IoctlClient::new();
-        Mutex::new(client)
-    };
+impl IoctlClientConfig {
+    /// `/dev/sgx` of upstream Occlum with the DCAP ioctls.
+    ///
+    /// Occlum builds the request codes with `StructuredIoctlNum::new::<Arg>(nr, b's', dir)` from
+    /// the command numbers `SGX_CMD_NUM_GET_DCAP_QUOTE_SIZE` (7), `SGX_CMD_NUM_GEN_DCAP_QUOTE` (8),
+    /// `SGX_CMD_NUM_GET_DCAP_SUPPLEMENTAL_SIZE` (9) and `SGX_CMD_NUM_VER_DCAP_QUOTE` (10), which
+    /// follow the EPID and report commands 0 to 6, and the sizes of `IoctlGenDCAPQuoteArg` and
+    /// `IoctlVerDCAPQuoteArg`. Releases before DCAP support have no DCAP commands at all, so
+    /// there is no preset for them; a LibOS with other codes needs its own config.
+    ///
+    /// @see https://github.com/occlum/occlum/blob/master/src/libos/src/fs/dev_fs/dev_sgx/mod.rs
+    pub fn occlum() -> Self {
+        Self {
+            device_path: OCCLUM_SGX_DEVICE_PATH.into(),
+            open_flags: libc::O_RDONLY,
+            get_quote_size: SGXIOC_GET_DCAP_QUOTE_SIZE,
+            gen_quote: SGXIOC_GEN_DCAP_QUOTE,
+            get_supplemental_size: SGXIOC_GET_DCAP_SUPPLEMENTAL_SIZE,
+            verify_quote: SGXIOC_VER_DCAP_QUOTE,
+        }
+    }
+
+    /// Use this config for [`SGXQuote::from_report_data`](crate::SGXQuote::from_report_data) and
+    /// [`SGXQuote::verify_result`](crate::SGXQuote::verify_result).
+    ///
+    /// Must be called before the first quote is generated or verified, otherwise the config is
+    /// returned back as an error.
+    pub fn set_global(self) -> Result<(), Self> {
+        IOCTL_CLIENT
+            .set(IoctlClient::new(self))
+            .map_err(|client| client.config.clone())
+    }
+}
+
+impl Default for IoctlClientConfig {
+    fn default() -> Self {
+        Self::occlum()
+    }
+}
+
+static IOCTL_CLIENT: OnceLock<IoctlClient> = OnceLock::new();
+
+/// Client shared by [`SGXQuote::from_report_data`](crate::SGXQuote::from_report_data) and
+/// [`SGXQuote::verify_result`](crate::SGXQuote::verify_result)
+pub fn global_client() -> &'static IoctlClient {
+    IOCTL_CLIENT.get_or_init(|| IoctlClient::new(IoctlClientConfig::default()))
+}
+
+/// `ioctl(2)` on `fd`, replaced by a fake LibOS in the tests
+pub(crate) trait Ioctl: Send + Sync {
+    /// # Safety
+    /// `arg` must point to the argument of `request`
+    unsafe fn ioctl(&self, fd: i32, request: u64, arg: *mut libc::c_void) -> io::Result<()>;
+}
+
+struct SysIoctl;
+
+impl Ioctl for SysIoctl {
+    unsafe fn ioctl(&self, fd: i32, request: u64, arg: *mut libc::c_void) -> io::Result<()> {
+        if libc::ioctl(fd, request as IoctlRequest, arg) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
 }
 
 // Client which send ioctls to the Occlum LibOS
 // @see https://github.com/occlum/occlum/blob/master/src/libos/src/fs/dev_fs/dev_sgx/mod.rs
+//
+// The device is opened once and sizes are cached, ioctls are issued concurrently without locks.
+// Cached sizes are invalidated on failure, as they may become stale after a QE update.
 pub struct IoctlClient {
-    fd: Option<i32>,
-    quote_size: Option<u32>,
-    supplemental_size: Option<u32>,
+    config: IoctlClientConfig,
+    ioctl: Arc<dyn Ioctl>,
+    fd: OnceLock<i32>,
+    // 0 if unknown
+    quote_size: AtomicU32,
+    supplemental_size: AtomicU32,
+}
+
+/// Number of times quote generation is repeated with a bigger buffer
+const QUOTE_BUFFER_GROW_ATTEMPTS: usize = 2;
+
+/// Errnos returned when the quote buffer is too small
+const BUFFER_TOO_SMALL_ERRNOS: &[i32] = &[libc::ENOSPC, libc::ERANGE, libc::EOVERFLOW, libc::E2BIG];
+
+/// Error of the failed ioctl, counted in the metrics
+fn ioctl_error(request_type: &'static str, source: io::Error) -> SGXError {
+    instrument::ioctl_failed(request_type, source.raw_os_error());
+    SGXError::IoctlClientError {
+        request_type,
+        source,
+    }
 }
 
 impl IoctlClient {
-    fn new() -> Self {
+    pub fn new(config: IoctlClientConfig) -> Self {
         Self {
-            fd: None,
-            quote_size: None,
-            supplemental_size: None,
+            config,
+            ioctl: Arc::new(SysIoctl),
+            fd: OnceLock::new(),
+            quote_size: AtomicU32::new(0),
+            supplemental_size: AtomicU32::new(0),
         }
     }
 
-    fn fd(&mut self) -> Result<i32, SGXError> {
-        if self.fd.is_none() {
-            let path = CString::new("/dev/sgx").expect("CString::new failed");
-            let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+    #[cfg(test)]
+    pub(crate) fn with_ioctl(mut self, ioctl: Arc<dyn Ioctl>) -> Self {
+        self.ioctl = ioctl;
+        self
+    }
+
+    fn fd(&self) -> Result<i32, SGXError> {
+        if let Some(fd) = self.fd.get() {
+            return Ok(*fd);
+        }
 
-            if fd <= 0 {
-                return Err(SGXError::DeviceOpenFailed("/dev/sgx"));
+        let device_path = &self.config.device_path;
+        let path = CString::new(device_path.as_os_str().as_bytes()).map_err(|_| {
+            SGXError::DeviceOpenFailed {
+                path: device_path.display().to_string(),
+                source: io::ErrorKind::InvalidInput.into(),
             }
+        })?;
+        let fd = unsafe { libc::open(path.as_ptr(), self.config.open_flags) };
 
-            self.fd = Some(fd);
-            Ok(fd)
-        } else {
-            Ok(*self.fd.as_ref().unwrap())
+        if fd < 0 {
+            return Err(SGXError::DeviceOpenFailed {
+                path: device_path.display().to_string(),
+                source: io::Error::last_os_error(),
+            });
         }
-    }
 
-    fn get_quote_size(&mut self) -> Result<u32, SGXError> {
-        if self.quote_size.is_none() {
-            let size: u32 = 0;
-            trace!("ioctl(SGX_IOCTL_GET_DCAP_QUOTE_SIZE): Get DCAP Quote size");
-            let ret = unsafe { libc::ioctl(self.fd()?, IOCTL_GET_DCAP_QUOTE_SIZE, &size) };
+        // Another thread opened the device first
+        if let Err(fd) = self.fd.set(fd) {
+            unsafe { libc::close(fd) };
+        }
+        Ok(*self.fd.get().unwrap())
+    }
 
-            if ret < 0 {
-                return Err(SGXError::IoctlClientError {
-                    request_type: "IOCTL_GET_DCAP_QUOTE_SIZE",
-                    ret,
-                });
-            }
+    fn get_size(
+        &self,
+        cache: &AtomicU32,
+        request: u64,
+        request_type: &'static str,
+    ) -> Result<u32, SGXError> {
+        let size = cache.load(Ordering::Relaxed);
+        if size != 0 {
+            return Ok(size);
+        }
 
-            self.quote_size = Some(size);
+        let mut size: u32 = 0;
+        trace!("ioctl({}): Get size", request_type);
+        unsafe {
+            self.ioctl
+                .ioctl(self.fd()?, request, &mut size as *mut u32 as *mut _)
         }
+        .map_err(|e| ioctl_error(request_type, e))?;
 
-        Ok(*self.quote_size.as_ref().unwrap())
+        cache.store(size, Ordering::Relaxed);
+        Ok(size)
     }
 
-    pub fn generate_quote(&mut self, report_data: SGXReportData) -> Result<Vec<u8>, SGXError> {
-        let mut quote_size = self.get_quote_size()?;
-        let mut quote_buf: Vec<u8> = vec![0; quote_size as usize];
+    fn get_quote_size(&self) -> Result<u32, SGXError> {
+        self.get_size(
+            &self.quote_size,
+            self.config.get_quote_size,
+            "IOCTL_GET_DCAP_QUOTE_SIZE",
+        )
+    }
 
-        let quote_arg: IoctlGenDCAPQuoteArg = IoctlGenDCAPQuoteArg {
-            report_data: &report_data,
-            quote_size: &mut quote_size,
-            quote_buf: quote_buf.as_mut_ptr(),
-        };
+    /// Generate the quote, the buffer is truncated to the size returned by the LibOS.
+    ///
+    /// If the buffer is too small (e.g. the quote size changed after a QE update), the size is
+    /// queried again and the generation is repeated with a bigger buffer.
+    pub fn generate_quote(&self, report_data: SGXReportData) -> Result<Vec<u8>, SGXError> {
+        let mut buf_size = self.get_quote_size()?;
+        let mut grow_attempts = 0;
 
-        trace!("ioctl(IOCTL_GEN_DCAP_QUOTE): Generate SGX DCAP Quote");
-        let ret = unsafe { libc::ioctl(self.fd()?, IOCTL_GEN_DCAP_QUOTE, &quote_arg) };
-        if ret < 0 {
-            return Err(SGXError::IoctlClientError {
-                request_type: "IOCTL_GEN_DCAP_QUOTE",
-                ret,
-            });
-        }
-        Ok(quote_buf)
-    }
+        loop {
+            let mut quote_size = buf_size;
+            let mut quote_buf: Vec<u8> = vec![0; buf_size as usize];
+
+            let mut quote_arg: IoctlGenDCAPQuoteArg = IoctlGenDCAPQuoteArg {
+                report_data: &report_data,
+                quote_size: &mut quote_size,
+                quote_buf: quote_buf.as_mut_ptr(),
+            };
+
+            trace!("ioctl(IOCTL_GEN_DCAP_QUOTE): Generate SGX DCAP Quote");
+            let ret = unsafe {
+                self.ioctl.ioctl(
+                    self.fd()?,
+                    self.config.gen_quote,
+                    &mut quote_arg as *mut IoctlGenDCAPQuoteArg as *mut _,
+                )
+            };
+            let source = match ret {
+                Ok(()) => {
+                    if quote_size > 0 && quote_size < buf_size {
+                        trace!("Truncate quote buffer from {} to {}", buf_size, quote_size);
+                        quote_buf.truncate(quote_size as usize);
+                    }
+                    return Ok(quote_buf);
+                }
+                Err(source) => source,
+            };
 
-    fn get_supplemental_size(&mut self) -> Result<u32, SGXError> {
-        if self.supplemental_size.is_none() {
-            let size: u32 = 0;
-            trace!("ioctl(IOCTL_GET_DCAP_SUPPLEMENTAL_SIZE): Get Supplemental size");
-            let ret = unsafe { libc::ioctl(self.fd()?, IOCTL_GET_DCAP_SUPPLEMENTAL_SIZE, &size) };
+            let e = ioctl_error("IOCTL_GEN_DCAP_QUOTE", source);
+            self.quote_size.store(0, Ordering::Relaxed);
 
-            if ret < 0 {
-                return Err(SGXError::IoctlClientError {
-                    request_type: "IOCTL_GET_DCAP_SUPPLEMENTAL_SIZE",
-                    ret,
-                });
+            let too_small = quote_size > buf_size
+                || e.errno()
+                    .is_some_and(|errno| BUFFER_TOO_SMALL_ERRNOS.contains(&errno));
+            if !too_small || grow_attempts == QUOTE_BUFFER_GROW_ATTEMPTS {
+                return Err(e);
             }
 
-            self.supplemental_size = Some(size);
+            let required = self.get_quote_size()?.max(quote_size);
+            if required <= buf_size {
+                return Err(e);
+            }
+            warn!(
+                "Quote buffer of {} bytes is too small, retrying with {} bytes",
+                buf_size, required
+            );
+            buf_size = required;
+            grow_attempts += 1;
         }
+    }
 
-        Ok(*self.supplemental_size.as_ref().unwrap())
+    fn get_supplemental_size(&self) -> Result<u32, SGXError> {
+        self.get_size(
+            &self.supplemental_size,
+            self.config.get_supplemental_size,
+            "IOCTL_GET_DCAP_SUPPLEMENTAL_SIZE",
+        )
     }
 
-    pub fn verify_quote(&mut self, quote_buf: &[u8]) -> Result<SGXQuoteVerifyResult, SGXError> {
+    pub fn verify_quote(&self, quote_buf: &[u8]) -> Result<SGXQuoteVerifyResult, SGXError> {
         let supplemental_data_size = self.get_supplemental_size()?;
-        let mut result = SGXQuoteVerifyResult::Unspecified;
+        // Any value the LibOS writes is valid, unknown results map to `Unspecified`
+        let mut result = SGXQuoteVerifyResult::Unspecified as u32;
         let mut status = 1;
         let mut suppl_buf: Vec<u8> = vec![0; supplemental_data_size as usize];
 
-        let verify_arg = IoctlVerDCAPQuoteArg {
+        let mut verify_arg = IoctlVerDCAPQuoteArg {
             quote_buf: quote_buf.as_ptr(),
             quote_size: quote_buf.len() as u32,
             collateral_expiration_status: &mut status,
@@ -144,23 +320,170 @@ impl IoctlClient {
         };
 
         trace!("ioctl(IOCTL_VER_DCAP_QUOTE): Verify SGX DCAP Quote");
-        let ret = unsafe { libc::ioctl(self.fd()?, IOCTL_VER_DCAP_QUOTE, &verify_arg) };
-        if ret < 0 {
-            return Err(SGXError::IoctlClientError {
-                request_type: "IOCTL_VER_DCAP_QUOTE",
-                ret,
-            });
+        let ret = unsafe {
+            self.ioctl.ioctl(
+                self.fd()?,
+                self.config.verify_quote,
+                &mut verify_arg as *mut IoctlVerDCAPQuoteArg as *mut _,
+            )
+        };
+        if let Err(source) = ret {
+            let e = ioctl_error("IOCTL_VER_DCAP_QUOTE", source);
+            self.supplemental_size.store(0, Ordering::Relaxed);
+            return Err(e);
         }
-        Ok(result)
+        Ok(result.into())
     }
 }
 
 impl Drop for IoctlClient {
     fn drop(&mut self) {
         unsafe {
-            if let Some(fd) = self.fd {
-                libc::close(fd);
+            if let Some(fd) = self.fd.get() {
+                libc::close(*fd);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::FakeLibOs;
+
+    /// Generate a quote from the fake LibOS, returns the result and the generation buffer sizes
+    fn generate(
+        sizes: &[u32],
+        generations: &[(Option<i32>, u32)],
+    ) -> (Result<Vec<u8>, SGXError>, Vec<u32>) {
+        let libos = FakeLibOs::scripted(sizes, generations);
+        let result = libos.client().generate_quote(SGXReportData::default());
+        let state = libos.state();
+        assert!(state.sizes.is_empty() && state.generations.is_empty());
+        (
+            result,
+            state.generated.iter().map(|(_, size)| *size).collect(),
+        )
+    }
+
+    #[test]
+    fn generate_quote_buffer() {
+        // Truncated to the written size, kept whole if the LibOS doesn't report it
+        let (quote, buffers) = generate(&[4600], &[(None, 4400)]);
+        assert_eq!(quote.unwrap(), vec![0xAB; 4400]);
+        assert_eq!(buffers, [4600]);
+        let (quote, _) = generate(&[4600], &[(None, 0)]);
+        assert_eq!(quote.unwrap().len(), 4600);
+
+        // Grown to the size queried again after E2BIG
+        let (quote, buffers) = generate(&[4600, 5000], &[(Some(libc::E2BIG), 0), (None, 4900)]);
+        assert_eq!(quote.unwrap(), vec![0xAB; 4900]);
+        assert_eq!(buffers, [4600, 5000]);
+
+        // Or to the size written back, if it is bigger
+        let (quote, buffers) = generate(&[4600, 4600], &[(Some(libc::EINVAL), 5200), (None, 5200)]);
+        assert_eq!(quote.unwrap().len(), 5200);
+        assert_eq!(buffers, [4600, 5200]);
+
+        // Other errors are returned as is
+        let (quote, buffers) = generate(&[4600], &[(Some(libc::EINVAL), 4600)]);
+        assert_eq!(quote.unwrap_err().errno(), Some(libc::EINVAL));
+        assert_eq!(buffers, [4600]);
+
+        // Same size after E2BIG
+        let (quote, buffers) = generate(&[4600, 4600], &[(Some(libc::E2BIG), 0)]);
+        assert_eq!(quote.unwrap_err().errno(), Some(libc::E2BIG));
+        assert_eq!(buffers, [4600]);
+
+        // Growth is bounded
+        let (quote, buffers) = generate(
+            &[4600, 5000, 6000],
+            &[
+                (Some(libc::E2BIG), 0),
+                (Some(libc::E2BIG), 0),
+                (Some(libc::E2BIG), 7000),
+            ],
+        );
+        assert_eq!(quote.unwrap_err().errno(), Some(libc::E2BIG));
+        assert_eq!(buffers, [4600, 5000, 6000]);
+    }
+
+    #[test]
+    fn verify_quote_result() {
+        let libos = FakeLibOs::new();
+        let client = libos.client();
+        libos.state().verify_result = 0xA002;
+        let result = client.verify_quote(&[0; 64]).unwrap();
+        assert_eq!(result, SGXQuoteVerifyResult::OutOfDate);
+        libos.state().verify_result = 0xDEAD;
+        let result = client.verify_quote(&[0; 64]).unwrap();
+        assert_eq!(result, SGXQuoteVerifyResult::Unspecified);
+    }
+
+    #[test]
+    fn size_cache_invalidated_on_failure() {
+        let libos = FakeLibOs::scripted(
+            &[4600, 4600],
+            &[(None, 4600), (Some(libc::EINVAL), 0), (None, 4600)],
+        );
+        let client = libos.client();
+        client.generate_quote(SGXReportData::default()).unwrap();
+        assert_eq!(client.quote_size.load(Ordering::Relaxed), 4600);
+        client.generate_quote(SGXReportData::default()).unwrap_err();
+        assert_eq!(client.quote_size.load(Ordering::Relaxed), 0);
+        client.generate_quote(SGXReportData::default()).unwrap();
+        assert!(libos.state().sizes.is_empty());
+    }
+
+    /// Direction, type, number and argument size encoded in a Linux ioctl request code, the
+    /// layout of `_IOC` which `StructuredIoctlNum` of Occlum follows
+    fn decode(request: u64) -> (u64, u8, u8, usize) {
+        (
+            request >> 30,
+            (request >> 8) as u8,
+            request as u8,
+            ((request >> 16) & 0x3fff) as usize,
+        )
+    }
+
+    #[test]
+    fn occlum_config() {
+        const READ: u64 = 2;
+        const READ_WRITE: u64 = 3;
+
+        let config = IoctlClientConfig::occlum();
+        assert_eq!(config, IoctlClientConfig::default());
+        assert_eq!(config.device_path, PathBuf::from("/dev/sgx"));
+        assert_eq!(config.open_flags, libc::O_RDONLY);
+        assert_eq!(decode(config.get_quote_size), (READ, b's', 7, 4));
+        assert_eq!(
+            decode(config.gen_quote),
+            (READ_WRITE, b's', 8, size_of::<IoctlGenDCAPQuoteArg>())
+        );
+        assert_eq!(decode(config.get_supplemental_size), (READ, b's', 9, 4));
+        assert_eq!(
+            decode(config.verify_quote),
+            (READ_WRITE, b's', 10, size_of::<IoctlVerDCAPQuoteArg>())
+        );
+
+        let custom = IoctlClientConfig {
+            device_path: "/dev/attestation".into(),
+            open_flags: libc::O_RDWR,
+            ..IoctlClientConfig::occlum()
+        };
+        assert_eq!(custom.device_path, PathBuf::from("/dev/attestation"));
+        assert_eq!(custom.gen_quote, config.gen_quote);
+        assert_eq!(custom.verify_quote, config.verify_quote);
+    }
+
+    #[test]
+    fn set_global_after_first_use() {
+        let config = global_client().config.clone();
+        let custom = IoctlClientConfig {
+            device_path: "/dev/attestation".into(),
+            ..IoctlClientConfig::occlum()
+        };
+        assert_eq!(custom.clone().set_global(), Err(custom));
+        assert_eq!(global_client().config, config);
+    }
+}
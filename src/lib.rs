@@ -36,18 +36,85 @@ use std::mem::size_of;
 use std::ops::Deref;
 use std::time::Instant;
 
-#[macro_use]
-extern crate lazy_static;
-
-pub use error::SGXError;
-use ioctl::IOCTL_CLIENT;
-use log::{trace, warn};
+pub use client::AttestationClient;
+pub use collateral::{CollateralVersion, CollateralVersions, SGXQuoteCollateral, SGX_TEE_TYPE};
+use constants::SGX_FLAGS_DEBUG;
+pub use constants::{
+    SGX_EEXTEND_SIZE, SGX_PAGE_SIZE, SGX_SECINFO_R, SGX_SECINFO_REG, SGX_SECINFO_TCS,
+    SGX_SECINFO_W, SGX_SECINFO_X,
+};
+pub use diff::{SGXQuoteDiff, SGXQuoteFieldDiff};
+pub use error::{BoxError, SGXError, SGXErrorKind};
+pub use generator::{set_quote_generator, QuoteGenerator};
+pub use gramine::GramineAttestation;
+#[cfg(feature = "metrics")]
+pub use instrument::describe_metrics;
+pub use ioctl::IoctlClientConfig;
+pub use mrenclave::{EnclaveMeasurement, OcclumBuild};
+pub use pck::{SGXPckExtensions, SGXTcbComponents};
+pub use policy::{AppraisalPolicy, DEFAULT_POLICY_ID};
+#[cfg(feature = "qvl")]
+pub use qvl::{QvlVerification, QvlVerifier, QVL_LIBRARY_NAMES};
+pub use retry::RetryPolicy;
+pub use signature::{SGXCertificationData, SGXQuoteSignature, PCK_CERT_CHAIN_TYPE};
+pub use sigstruct::SGXSigStruct;
+use telemetry::{trace, warn};
+pub use tsm::{TsmReport, TsmReportOutput, TSM_REPORT_PATH};
 pub use types::*;
-
+pub use verifier::{IoctlVerifier, QuoteVerifier};
+
+#[cfg(any(feature = "broker", feature = "grpc", feature = "service"))]
+mod api;
+#[cfg(feature = "audit")]
+pub mod audit;
+#[cfg(feature = "broker")]
+pub mod broker;
+#[cfg(feature = "channel")]
+pub mod channel;
+mod client;
+#[cfg(feature = "cmw")]
+pub mod cmw;
+mod collateral;
 mod constants;
+#[cfg(feature = "dcap")]
+pub mod dcap;
+mod der;
+mod diff;
+#[cfg(feature = "eat")]
+pub mod eat;
 mod error;
+mod generator;
+mod gramine;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(any(feature = "broker", feature = "channel"))]
+pub mod hpke;
+#[cfg(any(feature = "broker", feature = "service"))]
+mod http;
+mod instrument;
 mod ioctl;
+mod mrenclave;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+#[cfg(feature = "pccs")]
+pub mod pccs;
+mod pck;
+mod policy;
+#[cfg(feature = "qvl")]
+mod qvl;
+#[cfg(feature = "ratls")]
+pub mod ratls;
+mod retry;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "service")]
+pub mod service;
+mod signature;
+mod sigstruct;
+mod telemetry;
+mod tsm;
 mod types;
+mod verifier;
 
 /// SGX Quote
 pub struct SGXQuote {
@@ -74,6 +141,10 @@ impl TryFrom<Vec<u8>> for SGXQuote {
     }
 }
 
+// `report_body` points into the heap buffer owned by `buf`, which is never mutated
+unsafe impl Send for SGXQuote {}
+unsafe impl Sync for SGXQuote {}
+
 impl TryFrom<&[u8]> for SGXQuote {
     type Error = SGXError;
 
@@ -90,6 +161,17 @@ impl TryFrom<ReportData> for SGXQuote {
     }
 }
 
+impl TryFrom<&SGXQuoteView> for SGXQuote {
+    type Error = SGXError;
+
+    fn try_from(view: &SGXQuoteView) -> Result<Self, Self::Error> {
+        let mut buf = view.header.as_bytes().to_vec();
+        buf.extend_from_slice(view.report_body.as_bytes());
+        buf.append(&mut view.signature.to_bytes());
+        buf.try_into()
+    }
+}
+
 impl Deref for SGXQuote {
     type Target = [u8];
 
@@ -99,7 +181,9 @@ impl Deref for SGXQuote {
 }
 
 impl SGXQuote {
-    /// Create a new [SGXQuote] from [ReportData], it needs to be run on the SGX server in an [Occlum] instance, also requires [PCCS].
+    /// Create a new [SGXQuote] from [ReportData], it needs to be run on the SGX server in an [Occlum] or [Gramine] instance, also requires [PCCS].
+    ///
+    /// The backend may be replaced with [`set_quote_generator`].
     ///
     /// # Example
     /// ```rust ignore
@@ -107,14 +191,21 @@ impl SGXQuote {
     /// ```
     ///
     /// [Occlum]: https://github.com/occlum/occlum
+    /// [Gramine]: https://github.com/gramineproject/gramine
     /// [PCCS]: https://github.com/intel/SGXDataCenterAttestationPrimitives/blob/master/QuoteGeneration/pccs/README.md
     pub fn from_report_data(data: &ReportData) -> Result<Self, SGXError> {
+        Self::from_report_data_with_retry(data, &RetryPolicy::none())
+    }
+
+    /// Same as [`SGXQuote::from_report_data`], but retries transient failures (e.g. PCCS is
+    /// unavailable) according to the [`RetryPolicy`]
+    pub fn from_report_data_with_retry(
+        data: &ReportData,
+        policy: &RetryPolicy,
+    ) -> Result<Self, SGXError> {
         let start = Instant::now();
 
-        let result = IOCTL_CLIENT
-            .lock()
-            .unwrap()
-            .generate_quote(SGXReportData::new(*data))?;
+        let result = generator::generate_with_retry(generator::global_generator(), data, policy)?;
 
         trace!("Generated quote in {:?}ms", start.elapsed().as_millis());
 
@@ -156,10 +247,21 @@ impl SGXQuote {
     ///     _ => println!("SGX Quote Verification failed"),
     /// }
     /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "verify_quote",
+            skip_all,
+            fields(backend = "ioctl", quote_hash, mrenclave, result, error)
+        )
+    )]
     pub fn verify_result(&self) -> Result<SGXQuoteVerifyResult, SGXError> {
+        telemetry::record_quote(self);
         let start = Instant::now();
-        let result = IOCTL_CLIENT.lock().unwrap().verify_quote(self.buf.as_ref());
+        let result = ioctl::global_client().verify_quote(self.buf.as_ref());
         trace!("Verify quote in {:?}ms", start.elapsed().as_millis());
+        instrument::quote_verified("ioctl", start, result.as_ref().copied());
+        telemetry::record_result(result.as_ref().copied());
         result
     }
 
@@ -171,11 +273,7 @@ impl SGXQuote {
 
         match result {
             SGXQuoteVerifyResult::Ok => Ok(()),
-            SGXQuoteVerifyResult::ConfigNeeded
-            | SGXQuoteVerifyResult::OutOfDate
-            | SGXQuoteVerifyResult::OutOfDateConfigNeeded
-            | SGXQuoteVerifyResult::SwHardeningNeeded
-            | SGXQuoteVerifyResult::ConfigAndSwHardeningNeeded => {
+            _ if !result.is_terminal() => {
                 warn!(
                     "SGX Quote Verification completed with non-terminal result: {:?}",
                     result
@@ -186,6 +284,30 @@ impl SGXQuote {
         }
     }
 
+    /// Quote header
+    pub fn header(&self) -> SGXQuoteHeader {
+        unsafe { std::ptr::read_unaligned(self.buf.as_ptr() as *const SGXQuoteHeader) }
+    }
+
+    /// Report body of the attested enclave
+    pub fn report_body(&self) -> SGXReportBody {
+        unsafe { std::ptr::read_unaligned(self.report_body) }
+    }
+
+    /// Parse the signature section, see [`SGXQuoteSignature`]
+    pub fn signature(&self) -> Result<SGXQuoteSignature, SGXError> {
+        SGXQuoteSignature::parse(&self.buf)
+    }
+
+    /// Decode the whole quote, see [`SGXQuoteView`]
+    pub fn view(&self) -> Result<SGXQuoteView, SGXError> {
+        Ok(SGXQuoteView {
+            header: self.header(),
+            report_body: self.report_body(),
+            signature: self.signature()?,
+        })
+    }
+
     pub fn isv_family_id(&self) -> SGXFamilyId {
         unsafe { (*self.report_body).isv_family_id }
     }
@@ -217,6 +339,23 @@ impl SGXQuote {
     pub fn report_data(&self) -> SGXReportData {
         unsafe { (*self.report_body).report_data }
     }
+
+    /// Returns `true` if the enclave was launched in debug mode
+    pub fn is_debug(&self) -> bool {
+        self.report_body().attributes.flags & SGX_FLAGS_DEBUG != 0
+    }
+}
+
+/// Decoded [`SGXQuote`] with all of its sections.
+///
+/// With the `serde` feature it gives a structured JSON representation of the quote, which may be
+/// converted back with [`SGXQuote::try_from`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SGXQuoteView {
+    pub header: SGXQuoteHeader,
+    pub report_body: SGXReportBody,
+    pub signature: SGXQuoteSignature,
 }
 
 impl Debug for SGXQuote {
@@ -235,9 +374,120 @@ impl Debug for SGXQuote {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::{io, ptr, thread};
+
     use crate::*;
 
+    /// Offset of the report data in the quote, after the header and the start of the report body
+    pub(crate) const REPORT_DATA_OFFSET: usize = 48 + 320;
+
+    /// Scripted Occlum `/dev/sgx`: answers the quote size queries with `sizes` and the quote
+    /// generations with `generations` (errno, size written back) in order, then with the fixture
+    /// quote. The quote verification returns `verify_result`.
+    #[derive(Default)]
+    pub(crate) struct FakeLibOs {
+        state: Mutex<FakeLibOsState>,
+    }
+
+    #[derive(Default)]
+    pub(crate) struct FakeLibOsState {
+        pub(crate) sizes: VecDeque<u32>,
+        pub(crate) generations: VecDeque<(Option<i32>, u32)>,
+        pub(crate) verify_result: u32,
+        /// Descriptor and buffer size of every quote generation
+        pub(crate) generated: Vec<(i32, u32)>,
+    }
+
+    impl FakeLibOs {
+        pub(crate) fn new() -> Arc<Self> {
+            Arc::default()
+        }
+
+        pub(crate) fn scripted(sizes: &[u32], generations: &[(Option<i32>, u32)]) -> Arc<Self> {
+            let libos = Self::new();
+            let mut state = libos.state();
+            state.sizes = sizes.iter().copied().collect();
+            state.generations = generations.iter().copied().collect();
+            drop(state);
+            libos
+        }
+
+        pub(crate) fn state(&self) -> MutexGuard<'_, FakeLibOsState> {
+            self.state.lock().unwrap()
+        }
+
+        /// Client of this LibOS, with its own descriptor
+        pub(crate) fn client(self: &Arc<Self>) -> ioctl::IoctlClient {
+            ioctl::IoctlClient::new(IoctlClientConfig {
+                device_path: "/dev/null".into(),
+                ..IoctlClientConfig::occlum()
+            })
+            .with_ioctl(self.clone())
+        }
+    }
+
+    impl ioctl::Ioctl for FakeLibOs {
+        unsafe fn ioctl(&self, fd: i32, request: u64, arg: *mut libc::c_void) -> io::Result<()> {
+            let fixture_size = fixture_quote(&[0; 64]).unwrap().len() as u32;
+            let mut state = self.state();
+            match request {
+                constants::SGXIOC_GET_DCAP_QUOTE_SIZE => {
+                    *(arg as *mut u32) = state.sizes.pop_front().unwrap_or(fixture_size);
+                }
+                constants::SGXIOC_GEN_DCAP_QUOTE => {
+                    let arg = &*(arg as *const ioctl::IoctlGenDCAPQuoteArg);
+                    let buf_size = *arg.quote_size;
+                    state.generated.push((fd, buf_size));
+                    match state.generations.pop_front() {
+                        Some((Some(errno), quote_size)) => {
+                            *arg.quote_size = quote_size;
+                            return Err(io::Error::from_raw_os_error(errno));
+                        }
+                        Some((None, quote_size)) => {
+                            ptr::write_bytes(arg.quote_buf, 0xAB, quote_size.min(buf_size) as _);
+                            *arg.quote_size = quote_size;
+                        }
+                        None => {
+                            let report_data = &*arg.report_data;
+                            let quote =
+                                fixture_quote(&report_data[..].try_into().unwrap()).unwrap();
+                            assert!(buf_size as usize >= quote.len());
+                            ptr::copy_nonoverlapping(quote.as_ptr(), arg.quote_buf, quote.len());
+                            *arg.quote_size = quote.len() as u32;
+                        }
+                    }
+                    // Let the other threads run into the same descriptor
+                    drop(state);
+                    thread::yield_now();
+                }
+                constants::SGXIOC_GET_DCAP_SUPPLEMENTAL_SIZE => *(arg as *mut u32) = 0,
+                constants::SGXIOC_VER_DCAP_QUOTE => {
+                    let arg = &*(arg as *const ioctl::IoctlVerDCAPQuoteArg);
+                    *arg.collateral_expiration_status = 0;
+                    *arg.quote_verification_result = state.verify_result;
+                }
+                _ => return Err(io::Error::from_raw_os_error(libc::ENOTTY)),
+            }
+            Ok(())
+        }
+    }
+
+    /// Verifier which accepts any quote
+    pub(crate) fn accept_all(_: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> {
+        Ok(SGXQuoteVerifyResult::Ok)
+    }
+
+    /// Generator of the fixture quote with `report_data`
+    pub(crate) fn fixture_quote(report_data: &ReportData) -> Result<Vec<u8>, SGXError> {
+        let mut quote = include_bytes!("../tests/fixtures/quote.raw").to_vec();
+        quote[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + constants::SGX_REPORT_DATA_SIZE]
+            .copy_from_slice(report_data);
+        Ok(quote)
+    }
+
     #[test]
     fn create_from_vec() {
         let quote_buf = include_bytes!("../tests/fixtures/quote.raw");
@@ -245,4 +495,74 @@ mod tests {
 
         insta::assert_yaml_snapshot!(format!("{:?}", quote));
     }
+
+    #[test]
+    fn view_round_trip() {
+        let quote_buf = include_bytes!("../tests/fixtures/quote.raw");
+        let quote = SGXQuote::from_slice(quote_buf.as_slice()).unwrap();
+        let view = quote.view().unwrap();
+
+        assert_eq!(view.header.version, 3);
+        assert_eq!(
+            view.signature.certification_data.cert_type,
+            PCK_CERT_CHAIN_TYPE
+        );
+        assert_eq!(
+            view.signature
+                .certification_data
+                .pck_cert_chain()
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(
+            SGXQuote::try_from(&view).unwrap().as_slice(),
+            quote.as_slice()
+        );
+    }
+
+    #[test]
+    fn function_generator_and_verifier() {
+        let quote = fixture_quote.generate_quote(&[7; 64]).unwrap();
+        let quote = SGXQuote::try_from(quote).unwrap();
+        assert_eq!(*quote.report_data(), [7; 64]);
+        assert_eq!(
+            accept_all.verify_quote(&quote).unwrap(),
+            SGXQuoteVerifyResult::Ok
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let quote_buf = include_bytes!("../tests/fixtures/quote.raw");
+        let quote = SGXQuote::from_slice(quote_buf.as_slice()).unwrap();
+
+        let json = serde_json::to_string(&quote).unwrap();
+        let restored: SGXQuote = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.as_slice(), quote.as_slice());
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&quote, &mut cbor).unwrap();
+        let restored: SGXQuote = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(restored.as_slice(), quote.as_slice());
+
+        let view = quote.view().unwrap();
+        insta::assert_yaml_snapshot!(view.report_body);
+
+        let json = serde_json::to_string(&view).unwrap();
+        let restored: SGXQuoteView = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            SGXQuote::try_from(&restored).unwrap().as_slice(),
+            quote.as_slice()
+        );
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&view, &mut cbor).unwrap();
+        let restored: SGXQuoteView = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(
+            SGXQuote::try_from(&restored).unwrap().as_slice(),
+            quote.as_slice()
+        );
+    }
 }
@@ -1,20 +1,472 @@
-use occlum_sgx::SGXQuote;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Generate SGX Quote from report data
-    let quote: SGXQuote = [0u8; 64].try_into()?;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use occlum_sgx::dcap::DcapVerifier;
+use occlum_sgx::pccs::PccsClient;
+use occlum_sgx::{
+    AppraisalPolicy, IoctlVerifier, QuoteVerifier, ReportData, SGXError, SGXQuote,
+    SGXQuoteCollateral, SGXQuoteVerifyResult, SGXReportBody,
+};
+use sha2::{Digest, Sha256};
 
-    let mrenclave = quote.mrenclave();
-    let mrsigner = quote.mrsigner();
-    let product_id = quote.product_id();
-    let version = quote.version();
+/// Inspect, generate and verify SGX DCAP quotes
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a quote, must be run in an Occlum instance
+    Generate(GenerateArgs),
+    /// Decode a quote
+    Inspect {
+        #[command(flatten)]
+        quote: QuoteArgs,
+        #[arg(long, value_enum, default_value_t = InspectFormat::Text)]
+        format: InspectFormat,
+    },
+    /// Verify a quote and optionally check it against a policy
+    ///
+    /// Exit codes: 0 - accepted, 2 - verification failed, 3 - rejected by the policy
+    Verify(VerifyArgs),
+    /// Compare two quotes field by field
+    Diff {
+        /// Left quote file
+        left: PathBuf,
+        /// Right quote file
+        right: PathBuf,
+        #[arg(long, value_enum, default_value_t = Encoding::Raw)]
+        encoding: Encoding,
+        #[arg(long, value_enum, default_value_t = InspectFormat::Text)]
+        format: InspectFormat,
+    },
+    /// Print the PEM-encoded PCK certificate chain of a quote
+    ExtractCerts {
+        #[command(flatten)]
+        quote: QuoteArgs,
+        /// Write `pck.pem`, `intermediate.pem` and `root.pem` to the directory instead of stdout,
+        /// the chain must have exactly these 3 certificates
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct ReportDataArgs {
+    /// Report data as hex, up to 64 bytes
+    #[arg(long)]
+    hex: Option<String>,
+    /// Report data as base64, up to 64 bytes
+    #[arg(long)]
+    base64: Option<String>,
+    /// File with raw report data, up to 64 bytes
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// File whose SHA-256 hash is used as report data
+    #[arg(long)]
+    hash_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+    #[command(flatten)]
+    report_data: ReportDataArgs,
+    /// Write the quote to the file instead of printing it
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Encoding of the quote, `raw` with `--output` and `base64` otherwise by default
+    #[arg(long, value_enum)]
+    encoding: Option<Encoding>,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    quote: QuoteArgs,
+    #[arg(long, value_enum, default_value_t = Backend::Ioctl)]
+    backend: Backend,
+    /// JSON file with an appraisal policy
+    #[arg(long)]
+    policy: Option<PathBuf>,
+    /// JSON file with the verification collateral for the `dcap` and `qvl` backends. The `qvl`
+    /// backend fetches it from PCCS if not set
+    #[arg(long, conflicts_with = "pccs_url")]
+    collateral: Option<PathBuf>,
+    /// PCCS base URL to fetch the collateral from for the `dcap` backend
+    #[arg(long)]
+    pccs_url: Option<String>,
+    /// PEM root CA to trust instead of the Intel SGX Root CA for the `dcap` backend
+    #[arg(long)]
+    trusted_root: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct QuoteArgs {
+    /// Quote file, `-` for stdin
+    quote: PathBuf,
+    #[arg(long, value_enum, default_value_t = Encoding::Raw)]
+    encoding: Encoding,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Encoding {
+    Raw,
+    Hex,
+    Base64,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InspectFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    /// Occlum `/dev/sgx` ioctls, must be run in an Occlum instance
+    Ioctl,
+    /// Pure-Rust verification with the collateral from `--collateral` or `--pccs-url`
+    Dcap,
+    /// Intel DCAP Quote Verification Library, `libsgx_dcap_quoteverify.so` on the host
+    #[cfg(feature = "qvl")]
+    Qvl,
+}
+
+const EXIT_VERIFICATION_FAILED: u8 = 2;
+const EXIT_POLICY_REJECTED: u8 = 3;
+
+type Error = Box<dyn std::error::Error>;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Generate(args) => generate(args),
+        Command::Inspect { quote, format } => inspect(quote, format),
+        Command::Verify(args) => verify(args),
+        Command::Diff {
+            left,
+            right,
+            encoding,
+            format,
+        } => diff(
+            QuoteArgs {
+                quote: left,
+                encoding,
+            },
+            QuoteArgs {
+                quote: right,
+                encoding,
+            },
+            format,
+        ),
+        Command::ExtractCerts { quote, output_dir } => extract_certs(quote, output_dir),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn decode(data: &[u8], encoding: Encoding) -> Result<Vec<u8>, Error> {
+    let text = || String::from_utf8_lossy(data).trim().to_string();
+    Ok(match encoding {
+        Encoding::Raw => data.to_vec(),
+        Encoding::Hex => hex::decode(text())?,
+        Encoding::Base64 => STANDARD.decode(text())?,
+    })
+}
+
+fn report_data(args: ReportDataArgs) -> Result<ReportData, Error> {
+    let data = if let Some(hex) = args.hex {
+        decode(hex.as_bytes(), Encoding::Hex)?
+    } else if let Some(b64) = args.base64 {
+        decode(b64.as_bytes(), Encoding::Base64)?
+    } else if let Some(path) = args.file {
+        fs::read(path)?
+    } else if let Some(path) = args.hash_file {
+        Sha256::digest(fs::read(path)?).to_vec()
+    } else {
+        unreachable!("clap requires one of the report data arguments")
+    };
+
+    let mut report_data = [0u8; 64];
+    if data.len() > report_data.len() {
+        return Err(format!("Report data is too long: {} bytes, max 64", data.len()).into());
+    }
+    report_data[..data.len()].copy_from_slice(&data);
+    Ok(report_data)
+}
 
-    println!("MrEnclave:\t{}", mrenclave);
-    println!("MrSigner:\t{}", mrsigner);
-    println!("ProdID:\t{}", product_id);
-    println!("Version:\t{}", version);
+fn read_quote(args: &QuoteArgs) -> Result<SGXQuote, Error> {
+    let data = if args.quote == Path::new("-") {
+        let mut buf = Vec::new();
+        io::Read::read_to_end(&mut io::stdin(), &mut buf)?;
+        buf
+    } else {
+        fs::read(&args.quote)?
+    };
+    Ok(SGXQuote::from_slice(&decode(&data, args.encoding)?)?)
+}
+
+fn generate(args: GenerateArgs) -> Result<ExitCode, Error> {
+    let quote = SGXQuote::from_report_data(&report_data(args.report_data)?)?;
+    write_quote(&quote, args.output, args.encoding)?;
+    Ok(ExitCode::SUCCESS)
+}
 
-    println!("\n{:#?}", quote);
+/// Write the quote to `output` (raw by default) or to stdout (base64 by default)
+fn write_quote(
+    quote: &[u8],
+    output: Option<PathBuf>,
+    encoding: Option<Encoding>,
+) -> Result<(), Error> {
+    let encoding = encoding.unwrap_or(match output {
+        Some(_) => Encoding::Raw,
+        None => Encoding::Base64,
+    });
+    let encoded = match encoding {
+        Encoding::Raw => quote.to_vec(),
+        Encoding::Hex => hex::encode(quote).into_bytes(),
+        Encoding::Base64 => STANDARD.encode(quote).into_bytes(),
+    };
 
+    match output {
+        Some(path) => fs::write(path, encoded)?,
+        None => {
+            let mut stdout = io::stdout();
+            stdout.write_all(&encoded)?;
+            if !matches!(encoding, Encoding::Raw) {
+                writeln!(stdout)?;
+            }
+        }
+    }
     Ok(())
 }
+
+fn print_report_body(name: &str, body: &SGXReportBody) {
+    println!("{}:", name);
+    println!("  cpu_svn:          {}", hex::encode(body.cpu_svn.svn));
+    println!("  misc_select:      {:#010x}", body.misc_select);
+    println!("  attributes.flags: {:#018x}", body.attributes.flags);
+    println!("  attributes.xfrm:  {:#018x}", body.attributes.xfrm);
+    println!("  mrenclave:        {}", body.mr_enclave);
+    println!("  mrsigner:         {}", body.mr_signer);
+    println!("  isv_prod_id:      {}", body.isv_prod_id);
+    println!("  isv_svn:          {}", body.isv_svn);
+    println!("  config_svn:       {}", body.config_svn);
+    println!("  config_id:        {:?}", body.config_id);
+    println!("  isv_ext_prod_id:  {:?}", body.isv_ext_prod_id);
+    println!("  isv_family_id:    {:?}", body.isv_family_id);
+    println!("  report_data:      {}", hex::encode(&*body.report_data));
+}
+
+fn inspect(args: QuoteArgs, format: InspectFormat) -> Result<ExitCode, Error> {
+    let view = read_quote(&args)?.view()?;
+
+    match format {
+        InspectFormat::Json => println!("{}", serde_json::to_string_pretty(&view)?),
+        InspectFormat::Text => {
+            let header = &view.header;
+            println!("Header:");
+            println!("  version:          {}", header.version);
+            println!("  att_key_type:     {}", header.att_key_type);
+            println!("  qe_svn:           {}", header.qe_svn);
+            println!("  pce_svn:          {}", header.pce_svn);
+            println!("  vendor_id:        {}", hex::encode(header.vendor_id));
+            println!("  user_data:        {}", hex::encode(header.user_data));
+            print_report_body("Report body", &view.report_body);
+
+            let signature = &view.signature;
+            println!("Signature:");
+            println!("  signature:        {}", hex::encode(signature.signature));
+            println!(
+                "  attestation_key:  {}",
+                hex::encode(signature.attestation_key)
+            );
+            println!(
+                "  qe_auth_data:     {}",
+                hex::encode(&signature.qe_auth_data)
+            );
+            println!(
+                "  cert_type:        {}",
+                signature.certification_data.cert_type
+            );
+            if let Some(chain) = signature.certification_data.pck_cert_chain() {
+                println!("  pck_cert_chain:   {} certificates", chain.len());
+            }
+            print_report_body("QE report", &signature.qe_report);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify(args: VerifyArgs) -> Result<ExitCode, Error> {
+    let quote = read_quote(&args.quote)?;
+    let policy: AppraisalPolicy = match args.policy {
+        Some(path) => serde_json::from_slice(&fs::read(path)?)?,
+        None => AppraisalPolicy::default(),
+    };
+    let dcap_only = |backend: &str| -> Result<(), Error> {
+        if args.pccs_url.is_some() || args.trusted_root.is_some() {
+            return Err(format!(
+                "--pccs-url and --trusted-root are not supported by the {} backend",
+                backend
+            )
+            .into());
+        }
+        Ok(())
+    };
+
+    let result = match args.backend {
+        Backend::Ioctl => {
+            dcap_only("ioctl")?;
+            if args.collateral.is_some() {
+                return Err("--collateral is not supported by the ioctl backend".into());
+            }
+            IoctlVerifier.verify_quote(&quote)?
+        }
+        Backend::Dcap => {
+            let mut verifier = match (args.collateral, args.pccs_url) {
+                (Some(path), _) => DcapVerifier::new(serde_json::from_slice::<SGXQuoteCollateral>(
+                    &fs::read(path)?,
+                )?),
+                (None, Some(url)) => DcapVerifier::new(PccsClient::new(url)),
+                (None, None) => {
+                    return Err("The dcap backend requires --collateral or --pccs-url".into())
+                }
+            };
+            if let Some(path) = args.trusted_root {
+                verifier = verifier.with_trusted_root_pem(&fs::read_to_string(path)?)?;
+            }
+            verifier.verify_quote(&quote)?
+        }
+        #[cfg(feature = "qvl")]
+        Backend::Qvl => {
+            dcap_only("qvl")?;
+            let mut verifier = occlum_sgx::QvlVerifier::load()?;
+            if let Some(path) = args.collateral {
+                verifier = verifier.with_collateral(serde_json::from_slice(&fs::read(path)?)?);
+            }
+            verifier.verify_quote(&quote)?
+        }
+    };
+    println!("Verification result: {:?}", result);
+
+    match policy.appraise(&quote, result) {
+        Ok(()) => {
+            if result != SGXQuoteVerifyResult::Ok {
+                eprintln!(
+                    "Warning: quote accepted with non-terminal result {:?}",
+                    result
+                );
+            }
+            println!("Quote accepted");
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(SGXError::VerifyQuoteFailed(result)) => {
+            eprintln!("Quote rejected: verification result {:?}", result);
+            Ok(ExitCode::from(EXIT_VERIFICATION_FAILED))
+        }
+        Err(e) => {
+            eprintln!("Quote rejected: {}", e);
+            Ok(ExitCode::from(EXIT_POLICY_REJECTED))
+        }
+    }
+}
+
+fn diff(left: QuoteArgs, right: QuoteArgs, format: InspectFormat) -> Result<ExitCode, Error> {
+    let diff = read_quote(&left)?.diff(&read_quote(&right)?);
+
+    match format {
+        InspectFormat::Text => print!("{}", diff),
+        InspectFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn extract_certs(args: QuoteArgs, output_dir: Option<PathBuf>) -> Result<ExitCode, Error> {
+    let signature = read_quote(&args)?.signature()?;
+    let chain = signature
+        .certification_data
+        .pck_cert_chain()
+        .ok_or_else(|| {
+            format!(
+                "Quote doesn't contain a PCK certificate chain (certification data type {})",
+                signature.certification_data.cert_type
+            )
+        })?;
+
+    match output_dir {
+        Some(dir) => {
+            let names = ["pck.pem", "intermediate.pem", "root.pem"];
+            if chain.len() != names.len() {
+                return Err(format!(
+                    "PCK certificate chain has {} certificates, expected {}",
+                    chain.len(),
+                    names.len()
+                )
+                .into());
+            }
+            fs::create_dir_all(&dir)?;
+            for (name, cert) in names.iter().zip(&chain) {
+                fs::write(dir.join(name), cert)?;
+            }
+        }
+        None => print!("{}", chain.concat()),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTE: &[u8] = include_bytes!("../tests/fixtures/quote.raw");
+
+    /// Write the quote as `generate` with `args` does, read it back as `inspect` does
+    fn round_trip(generate: &[&str], inspect: &[&str]) -> Result<SGXQuote, Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quote.raw");
+        let path = path.to_str().unwrap();
+        let args = [
+            &["occlum-sgx", "generate", "--hex", "00", "-o", path],
+            generate,
+        ]
+        .concat();
+        let Command::Generate(args) = Cli::try_parse_from(args)?.command else {
+            unreachable!()
+        };
+        write_quote(QUOTE, args.output, args.encoding)?;
+
+        let args = [&["occlum-sgx", "inspect", path], inspect].concat();
+        let Command::Inspect { quote, .. } = Cli::try_parse_from(args)?.command else {
+            unreachable!()
+        };
+        read_quote(&quote)
+    }
+
+    #[test]
+    fn generate_output_round_trip() {
+        assert_eq!(round_trip(&[], &[]).unwrap().as_slice(), QUOTE);
+        let quote = round_trip(&["--encoding", "base64"], &["--encoding", "base64"]).unwrap();
+        assert_eq!(quote.as_slice(), QUOTE);
+        let quote = round_trip(&["--encoding", "hex"], &["--encoding", "hex"]).unwrap();
+        assert_eq!(quote.as_slice(), QUOTE);
+    }
+}
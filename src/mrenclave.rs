@@ -0,0 +1,734 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::constants::{
+    SGX_EEXTEND_SIZE, SGX_PAGE_SIZE, SGX_SECINFO_R, SGX_SECINFO_REG, SGX_SECINFO_TCS,
+    SGX_SECINFO_W, SGX_SECINFO_X, SGX_SIGSTRUCT_SIZE,
+};
+use crate::error::{message, SGXError};
+use crate::sigstruct::{u16_at, u32_at, u64_at, SGXSigStruct};
+use crate::types::SGXMeasurement;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_REL: u64 = 17;
+const DT_RELSZ: u64 = 18;
+const DT_RELENT: u64 = 19;
+const DT_TEXTREL: u64 = 22;
+const DT_FLAGS: u64 = 30;
+const DF_TEXTREL: u64 = 0x4;
+
+/// ELF section with the enclave metadata written by `sgx_sign`
+const METADATA_SECTION: &[u8] = b".note.sgxmeta";
+const METADATA_MAGIC: u64 = 0x86A8_0294_635D_0E4C;
+const METADATA_ENCLAVE_CSS_OFFSET: usize = 64;
+const METADATA_DIRS_OFFSET: usize = METADATA_ENCLAVE_CSS_OFFSET + SGX_SIGSTRUCT_SIZE;
+const LAYOUT_ENTRY_SIZE: usize = 32;
+const PATCH_ENTRY_SIZE: usize = 32;
+const GROUP_FLAG: u16 = 1 << 12;
+const PAGE_ATTR_EADD: u16 = 0x1;
+const PAGE_ATTR_EEXTEND: u16 = 0x2;
+const PAGE_ATTR_POST_ADD: u16 = 0x8;
+
+/// Offsets of `OSSA`, `OFSBASE` and `OGSBASE` in TCS, which the loader rebases to the TCS page
+const TCS_RELATIVE_OFFSETS: [usize; 3] = [16, 48, 56];
+
+fn image_error(msg: &str) -> SGXError {
+    SGXError::EnclaveImageParseError(message(msg))
+}
+
+/// `size` bytes of `buf` at `offset`, `what` is the error if they are out of `buf`
+fn bytes<'b>(buf: &'b [u8], offset: u64, size: u64, what: &str) -> Result<&'b [u8], SGXError> {
+    offset
+        .checked_add(size)
+        .and_then(|end| buf.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+        .ok_or_else(|| image_error(what))
+}
+
+/// `a + b`, an error on overflow
+fn add(a: u64, b: u64) -> Result<u64, SGXError> {
+    a.checked_add(b)
+        .ok_or_else(|| image_error("address overflows"))
+}
+
+/// MRENCLAVE simulator, replays the measurement of ECREATE, EADD and EEXTEND.
+///
+/// RFC: "Intel 64 and IA-32 Architectures Software Developer's Manual", Vol. 3D, "ECREATE",
+/// "EADD" and "EEXTEND"
+///
+/// # Example
+/// ```rust ignore
+/// let mut measurement = EnclaveMeasurement::ecreate(1, 0x10000);
+/// measurement.add_page(0, &page, SGX_SECINFO_REG | SGX_SECINFO_R, true);
+/// let mrenclave = measurement.finalize();
+/// ```
+#[derive(Clone)]
+pub struct EnclaveMeasurement {
+    hasher: Sha256,
+}
+
+impl EnclaveMeasurement {
+    /// Start the measurement, `ssa_frame_size` is in pages and `enclave_size` in bytes
+    pub fn ecreate(ssa_frame_size: u32, enclave_size: u64) -> Self {
+        let mut block = [0u8; 64];
+        block[..8].copy_from_slice(b"ECREATE\0");
+        block[8..12].copy_from_slice(&ssa_frame_size.to_le_bytes());
+        block[12..20].copy_from_slice(&enclave_size.to_le_bytes());
+
+        let mut hasher = Sha256::new();
+        hasher.update(block);
+        Self { hasher }
+    }
+
+    /// Measure EADD of the page at `offset` with SECINFO `flags`
+    pub fn eadd(&mut self, offset: u64, flags: u64) {
+        let mut block = [0u8; 64];
+        block[..8].copy_from_slice(b"EADD\0\0\0\0");
+        block[8..16].copy_from_slice(&offset.to_le_bytes());
+        block[16..24].copy_from_slice(&flags.to_le_bytes());
+        self.hasher.update(block);
+    }
+
+    /// Measure EEXTEND of the 256-byte chunk at `offset`
+    pub fn eextend(&mut self, offset: u64, chunk: &[u8; SGX_EEXTEND_SIZE]) {
+        let mut block = [0u8; 64];
+        block[..8].copy_from_slice(b"EEXTEND\0");
+        block[8..16].copy_from_slice(&offset.to_le_bytes());
+        self.hasher.update(block);
+        self.hasher.update(chunk);
+    }
+
+    /// EADD the page and, if `extend` is set, EEXTEND its content
+    pub fn add_page(&mut self, offset: u64, page: &[u8; SGX_PAGE_SIZE], flags: u64, extend: bool) {
+        self.eadd(offset, flags);
+        if extend {
+            for (i, chunk) in page.chunks_exact(SGX_EEXTEND_SIZE).enumerate() {
+                let offset = offset + (i * SGX_EEXTEND_SIZE) as u64;
+                self.eextend(offset, chunk.try_into().unwrap());
+            }
+        }
+    }
+
+    /// Final MRENCLAVE value, as after EINIT
+    pub fn finalize(self) -> SGXMeasurement {
+        SGXMeasurement::new(self.hasher.finalize().into())
+    }
+
+    /// Replay the loading of the enclave image signed by `sgx_sign` (e.g.
+    /// `libocclum-libos.signed.so`) the same way as the Intel SGX SDK loader does:
+    /// `PT_LOAD` segments first, then the layout (TCS, SSA, stacks, heap) from the enclave metadata.
+    pub fn from_enclave_image(image: &[u8]) -> Result<SGXMeasurement, SGXError> {
+        let elf = Elf::parse(image)?;
+        let metadata = elf.metadata()?;
+
+        let mut image = image.to_vec();
+        metadata.apply_patches(&mut image)?;
+        let elf = Elf::parse(&image)?;
+
+        let mut measurement = Self::ecreate(metadata.ssa_frame_size, metadata.enclave_size);
+        elf.measure_segments(&mut measurement)?;
+        metadata.measure_layout(&mut measurement)?;
+        Ok(measurement.finalize())
+    }
+}
+
+struct Segment {
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+    memsz: u64,
+}
+
+struct Elf<'a> {
+    image: &'a [u8],
+    segments: Vec<Segment>,
+    dynamic: Vec<(u64, u64)>,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(image: &'a [u8]) -> Result<Self, SGXError> {
+        if image.len() < 64 || &image[..4] != ELF_MAGIC {
+            return Err(image_error("not an ELF file"));
+        }
+        if image[4] != ELFCLASS64 || image[5] != ELFDATA2LSB {
+            return Err(image_error("only 64-bit little-endian ELF is supported"));
+        }
+
+        let phoff = u64_at(image, 0x20);
+        let phentsize = u16_at(image, 0x36) as u64;
+        let phnum = u16_at(image, 0x38) as u64;
+
+        let mut segments = Vec::new();
+        let mut dynamic = Vec::new();
+        for i in 0..phnum {
+            let header = add(phoff, i * phentsize)
+                .and_then(|offset| bytes(image, offset, phentsize, "truncated program header"))
+                .ok()
+                .filter(|header| header.len() >= 56)
+                .ok_or_else(|| image_error("truncated program header"))?;
+            let segment = Segment {
+                flags: u32_at(header, 4),
+                offset: u64_at(header, 8),
+                vaddr: u64_at(header, 16),
+                filesz: u64_at(header, 32),
+                memsz: u64_at(header, 40),
+            };
+            match u32_at(header, 0) {
+                PT_LOAD => {
+                    // Pages of the segment are measured up to the end of its memory
+                    add(segment.vaddr, segment.memsz.max(segment.filesz))?
+                        .checked_next_multiple_of(SGX_PAGE_SIZE as u64)
+                        .ok_or_else(|| image_error("address overflows"))?;
+                    segments.push(segment)
+                }
+                PT_DYNAMIC => {
+                    let entries = bytes(
+                        image,
+                        segment.offset,
+                        segment.filesz,
+                        "truncated dynamic segment",
+                    )?;
+                    dynamic = entries
+                        .chunks_exact(16)
+                        .map(|entry| (u64_at(entry, 0), u64_at(entry, 8)))
+                        .take_while(|(tag, _)| *tag != DT_NULL)
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            image,
+            segments,
+            dynamic,
+        })
+    }
+
+    fn section(&self, name: &[u8]) -> Result<&'a [u8], SGXError> {
+        let image = self.image;
+        let shoff = u64_at(image, 0x28);
+        let shentsize = u16_at(image, 0x3A) as u64;
+        let shnum = u16_at(image, 0x3C) as u64;
+        let shstrndx = u16_at(image, 0x3E) as u64;
+
+        let header = |i: u64| -> Result<(usize, &'a [u8]), SGXError> {
+            let header = add(shoff, i * shentsize)
+                .and_then(|offset| bytes(image, offset, shentsize, "truncated section header"))
+                .ok()
+                .filter(|header| header.len() >= 64)
+                .ok_or_else(|| image_error("truncated section header"))?;
+            let content = bytes(
+                image,
+                u64_at(header, 24),
+                u64_at(header, 32),
+                "truncated section",
+            )?;
+            Ok((u32_at(header, 0) as usize, content))
+        };
+
+        let (_, names) = header(shstrndx)?;
+        for i in 0..shnum {
+            let (name_offset, content) = header(i)?;
+            let section_name = names
+                .get(name_offset..)
+                .and_then(|names| names.split(|c| *c == 0).next());
+            if section_name == Some(name) {
+                return Ok(content);
+            }
+        }
+        Err(image_error(&format!(
+            "section {} not found",
+            String::from_utf8_lossy(name)
+        )))
+    }
+
+    /// Enclave metadata from the `sgx_metadata` note
+    fn metadata(&self) -> Result<Metadata<'a>, SGXError> {
+        let mut notes = self.section(METADATA_SECTION)?;
+        while notes.len() >= 12 {
+            let name_size = u32_at(notes, 0) as usize;
+            let desc_size = u32_at(notes, 4) as usize;
+            let desc_offset = 12 + name_size.next_multiple_of(4);
+            let desc = notes
+                .get(desc_offset..desc_offset + desc_size)
+                .ok_or_else(|| image_error("truncated metadata note"))?;
+            if desc.len() >= METADATA_DIRS_OFFSET + 16 && u64_at(desc, 0) == METADATA_MAGIC {
+                return Ok(Metadata {
+                    buf: desc,
+                    ssa_frame_size: u32_at(desc, 24),
+                    enclave_size: u64_at(desc, 40),
+                });
+            }
+            notes = &notes[(desc_offset + desc_size.next_multiple_of(4)).min(notes.len())..];
+        }
+        Err(image_error("enclave metadata not found"))
+    }
+
+    fn vaddr_to_offset(&self, vaddr: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|segment| segment.vaddr <= vaddr && vaddr - segment.vaddr < segment.filesz)
+            .and_then(|segment| (vaddr - segment.vaddr).checked_add(segment.offset))
+    }
+
+    /// Pages with text relocations, which the loader adds writable
+    fn relocated_pages(&self) -> Result<BTreeSet<u64>, SGXError> {
+        let dynamic = |tag: u64| {
+            self.dynamic
+                .iter()
+                .find(|(entry_tag, _)| *entry_tag == tag)
+                .map(|(_, value)| *value)
+        };
+        let has_text_relocations = dynamic(DT_TEXTREL).is_some()
+            || dynamic(DT_FLAGS).is_some_and(|flags| flags & DF_TEXTREL != 0);
+
+        let mut pages = BTreeSet::new();
+        if !has_text_relocations {
+            return Ok(pages);
+        }
+        for (table, size, entry_size, default_entry_size) in [
+            (DT_RELA, DT_RELASZ, DT_RELAENT, 24),
+            (DT_REL, DT_RELSZ, DT_RELENT, 16),
+        ] {
+            let (Some(table), Some(size)) = (dynamic(table), dynamic(size)) else {
+                continue;
+            };
+            let entry_size = dynamic(entry_size).unwrap_or(default_entry_size) as usize;
+            if entry_size < 8 {
+                return Err(image_error("bad relocation entry size"));
+            }
+            let entries = self
+                .vaddr_to_offset(table)
+                .ok_or_else(|| image_error("truncated relocation table"))
+                .and_then(|offset| bytes(self.image, offset, size, "truncated relocation table"))?;
+            for entry in entries.chunks_exact(entry_size) {
+                pages.insert(u64_at(entry, 0) / SGX_PAGE_SIZE as u64);
+            }
+        }
+        Ok(pages)
+    }
+
+    fn measure_segments(&self, measurement: &mut EnclaveMeasurement) -> Result<(), SGXError> {
+        let relocated_pages = self.relocated_pages()?;
+        let page_size = SGX_PAGE_SIZE as u64;
+
+        for segment in &self.segments {
+            let content = bytes(
+                self.image,
+                segment.offset,
+                segment.filesz,
+                "truncated segment",
+            )?;
+
+            let mut flags = SGX_SECINFO_REG;
+            for (segment_flag, secinfo_flag) in [
+                (PF_R, SGX_SECINFO_R),
+                (PF_W, SGX_SECINFO_W),
+                (PF_X, SGX_SECINFO_X),
+            ] {
+                if segment.flags & segment_flag != 0 {
+                    flags |= secinfo_flag;
+                }
+            }
+
+            let start = segment.vaddr / page_size * page_size;
+            let end = (segment.vaddr + segment.memsz).next_multiple_of(page_size);
+            for page_vaddr in (start..end).step_by(SGX_PAGE_SIZE) {
+                let mut page = [0u8; SGX_PAGE_SIZE];
+                let from = page_vaddr.max(segment.vaddr);
+                let to = (page_vaddr + page_size).min(segment.vaddr + segment.filesz);
+                if from < to {
+                    page[(from - page_vaddr) as usize..(to - page_vaddr) as usize].copy_from_slice(
+                        &content[(from - segment.vaddr) as usize..(to - segment.vaddr) as usize],
+                    );
+                }
+
+                let flags = match relocated_pages.contains(&(page_vaddr / page_size)) {
+                    true => flags | SGX_SECINFO_W,
+                    false => flags,
+                };
+                measurement.add_page(page_vaddr, &page, flags, true);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `metadata_t` of the Intel SGX SDK
+struct Metadata<'a> {
+    buf: &'a [u8],
+    ssa_frame_size: u32,
+    enclave_size: u64,
+}
+
+impl<'a> Metadata<'a> {
+    /// `(offset, size)` of the data directory, 0 - patches, 1 - layout
+    fn directory(&self, index: usize) -> Result<&'a [u8], SGXError> {
+        let offset = u32_at(self.buf, METADATA_DIRS_OFFSET + index * 8);
+        let size = u32_at(self.buf, METADATA_DIRS_OFFSET + index * 8 + 4);
+        bytes(
+            self.buf,
+            offset.into(),
+            size.into(),
+            "truncated metadata directory",
+        )
+    }
+
+    fn data(&self, offset: u32, size: u32) -> Result<&'a [u8], SGXError> {
+        bytes(
+            self.buf,
+            offset.into(),
+            size.into(),
+            "truncated metadata data",
+        )
+    }
+
+    /// Apply the patches made by `sgx_sign` to the image (global data, e.g. heap size)
+    fn apply_patches(&self, image: &mut [u8]) -> Result<(), SGXError> {
+        for patch in self.directory(0)?.chunks_exact(PATCH_ENTRY_SIZE) {
+            let data = self.data(u32_at(patch, 8), u32_at(patch, 12))?;
+            usize::try_from(u64_at(patch, 0))
+                .ok()
+                .and_then(|dst| image.get_mut(dst..dst.checked_add(data.len())?))
+                .ok_or_else(|| image_error("patch is out of image"))?
+                .copy_from_slice(data);
+        }
+        Ok(())
+    }
+
+    fn measure_layout(&self, measurement: &mut EnclaveMeasurement) -> Result<(), SGXError> {
+        let entries: Vec<&[u8]> = self.directory(1)?.chunks_exact(LAYOUT_ENTRY_SIZE).collect();
+        self.measure_entries(&entries, 0, measurement)
+    }
+
+    fn measure_entries(
+        &self,
+        entries: &[&[u8]],
+        delta: u64,
+        measurement: &mut EnclaveMeasurement,
+    ) -> Result<(), SGXError> {
+        for (i, entry) in entries.iter().enumerate() {
+            let id = u16_at(entry, 0);
+            if id & GROUP_FLAG != 0 {
+                let entry_count = u16_at(entry, 2) as usize;
+                let load_times = u32_at(entry, 4);
+                let load_step = u64_at(entry, 8);
+                let group = entries
+                    .get(
+                        i.checked_sub(entry_count)
+                            .ok_or_else(|| image_error("bad layout group"))?
+                            ..i,
+                    )
+                    .unwrap_or_default();
+                for time in 1..=load_times as u64 {
+                    let step = load_step
+                        .checked_mul(time)
+                        .ok_or_else(|| image_error("address overflows"))?;
+                    self.measure_entries(group, add(delta, step)?, measurement)?;
+                }
+            } else {
+                self.measure_entry(entry, delta, measurement)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn measure_entry(
+        &self,
+        entry: &[u8],
+        delta: u64,
+        measurement: &mut EnclaveMeasurement,
+    ) -> Result<(), SGXError> {
+        let attributes = u16_at(entry, 2);
+        if attributes & PAGE_ATTR_EADD == 0 || attributes & PAGE_ATTR_POST_ADD != 0 {
+            return Ok(());
+        }
+        let page_count = u32_at(entry, 4) as u64;
+        let rva = add(u64_at(entry, 8), delta)?;
+        let content_size = u32_at(entry, 16);
+        let content_offset = u32_at(entry, 20);
+        let flags = u64_at(entry, 24);
+
+        let mut page = [0u8; SGX_PAGE_SIZE];
+        if content_offset != 0 {
+            let content = self.data(content_offset, content_size)?;
+            if flags == SGX_SECINFO_TCS {
+                if content.len() > SGX_PAGE_SIZE {
+                    return Err(image_error("TCS is larger than a page"));
+                }
+                page[..content.len()].copy_from_slice(content);
+                for offset in TCS_RELATIVE_OFFSETS {
+                    let value = add(u64_at(&page, offset), rva)?;
+                    page[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+                }
+            } else if !content.is_empty() {
+                for (byte, pattern) in page.iter_mut().zip(content.iter().cycle()) {
+                    *byte = *pattern;
+                }
+            }
+        }
+
+        let size = page_count * SGX_PAGE_SIZE as u64;
+        add(rva, size)?;
+        for i in 0..page_count {
+            let offset = rva + i * SGX_PAGE_SIZE as u64;
+            measurement.add_page(offset, &page, flags, attributes & PAGE_ATTR_EEXTEND != 0);
+        }
+        Ok(())
+    }
+}
+
+/// Signed enclave image of the Occlum instance, produced by `occlum build`
+pub struct OcclumBuild {
+    path: PathBuf,
+    image: Vec<u8>,
+}
+
+impl OcclumBuild {
+    /// Path of the signed LibOS enclave in the Occlum instance directory
+    pub const SIGNED_ENCLAVE_PATH: &'static str = "build/lib/libocclum-libos.signed.so";
+
+    /// Read the build artifacts of the Occlum instance directory (the one with `Occlum.json`)
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// let build = OcclumBuild::open("occlum_instance")?;
+    /// let policy = AppraisalPolicy {
+    ///     mrenclave: vec![build.mrenclave()?],
+    ///     mrsigner: vec![build.mrsigner()?],
+    ///     ..Default::default()
+    /// };
+    /// ```
+    pub fn open<P: AsRef<Path>>(instance_dir: P) -> Result<Self, SGXError> {
+        Self::from_signed_enclave(instance_dir.as_ref().join(Self::SIGNED_ENCLAVE_PATH))
+    }
+
+    /// Read the signed enclave image, e.g. `libocclum-libos.signed.so`
+    pub fn from_signed_enclave<P: AsRef<Path>>(path: P) -> Result<Self, SGXError> {
+        let path = path.as_ref().to_path_buf();
+        let image = std::fs::read(&path).map_err(SGXError::IoError)?;
+        Ok(Self { path, image })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// SIGSTRUCT embedded by `sgx_sign`
+    pub fn sigstruct(&self) -> Result<SGXSigStruct, SGXError> {
+        SGXSigStruct::from_enclave(&self.image)
+    }
+
+    /// MRENCLAVE replayed from the image with [`EnclaveMeasurement::from_enclave_image`].
+    ///
+    /// Returns an error if it doesn't match the enclave hash signed in SIGSTRUCT.
+    pub fn mrenclave(&self) -> Result<SGXMeasurement, SGXError> {
+        let mrenclave = EnclaveMeasurement::from_enclave_image(&self.image)?;
+        let signed = self.sigstruct()?.enclave_hash;
+        if mrenclave != signed {
+            return Err(image_error(&format!(
+                "computed MRENCLAVE {} doesn't match SIGSTRUCT {}",
+                mrenclave, signed
+            )));
+        }
+        Ok(mrenclave)
+    }
+
+    pub fn mrsigner(&self) -> Result<SGXMeasurement, SGXError> {
+        Ok(self.sigstruct()?.mrsigner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA_DATA_OFFSET: usize = METADATA_DIRS_OFFSET + 16;
+
+    /// Minimal signed enclave: one RX segment, one TCS and two EADD-only heap pages
+    fn enclave_image() -> Vec<u8> {
+        let code: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+
+        let mut metadata = vec![0u8; METADATA_DATA_OFFSET];
+        metadata[..8].copy_from_slice(&METADATA_MAGIC.to_le_bytes());
+        metadata[24..28].copy_from_slice(&1u32.to_le_bytes());
+        metadata[40..48].copy_from_slice(&0x10000u64.to_le_bytes());
+
+        let tcs_offset = metadata.len() as u32;
+        let mut tcs = vec![0u8; 72];
+        tcs[16..24].copy_from_slice(&0x1000u64.to_le_bytes());
+        tcs[48..56].copy_from_slice(&0x4000u64.to_le_bytes());
+        tcs[56..64].copy_from_slice(&0x5000u64.to_le_bytes());
+        tcs[64..72].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes().repeat(2));
+        metadata.extend_from_slice(&tcs);
+
+        let layout_offset = metadata.len() as u32;
+        let mut layout = vec![0u8; 2 * LAYOUT_ENTRY_SIZE];
+        layout[2..4].copy_from_slice(&(PAGE_ATTR_EADD | PAGE_ATTR_EEXTEND).to_le_bytes());
+        layout[4..8].copy_from_slice(&1u32.to_le_bytes());
+        layout[8..16].copy_from_slice(&0x2000u64.to_le_bytes());
+        layout[16..20].copy_from_slice(&72u32.to_le_bytes());
+        layout[20..24].copy_from_slice(&tcs_offset.to_le_bytes());
+        layout[24..32].copy_from_slice(&SGX_SECINFO_TCS.to_le_bytes());
+        layout[34..36].copy_from_slice(&PAGE_ATTR_EADD.to_le_bytes());
+        layout[36..40].copy_from_slice(&2u32.to_le_bytes());
+        layout[40..48].copy_from_slice(&0x3000u64.to_le_bytes());
+        layout[56..64]
+            .copy_from_slice(&(SGX_SECINFO_REG | SGX_SECINFO_R | SGX_SECINFO_W).to_le_bytes());
+        metadata.extend_from_slice(&layout);
+        let dirs = METADATA_DIRS_OFFSET + 8;
+        metadata[dirs..dirs + 4].copy_from_slice(&layout_offset.to_le_bytes());
+        metadata[dirs + 4..dirs + 8].copy_from_slice(&(layout.len() as u32).to_le_bytes());
+
+        let mut note = Vec::new();
+        note.extend_from_slice(&13u32.to_le_bytes());
+        note.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        note.extend_from_slice(&1u32.to_le_bytes());
+        note.extend_from_slice(b"sgx_metadata\0\0\0\0");
+        note.extend_from_slice(&metadata);
+
+        let names = b"\0.note.sgxmeta\0.shstrtab\0";
+
+        // ELF header, program header, code, note, names, section headers
+        let code_offset = 0x1000;
+        let note_offset = code_offset + code.len();
+        let names_offset = note_offset + note.len();
+        let shoff = names_offset + names.len();
+
+        let mut image = vec![0u8; code_offset];
+        image[..4].copy_from_slice(ELF_MAGIC);
+        image[4] = ELFCLASS64;
+        image[5] = ELFDATA2LSB;
+        image[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        image[0x28..0x30].copy_from_slice(&(shoff as u64).to_le_bytes());
+        image[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        image[0x38..0x3A].copy_from_slice(&1u16.to_le_bytes());
+        image[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+        image[0x3C..0x3E].copy_from_slice(&3u16.to_le_bytes());
+        image[0x3E..0x40].copy_from_slice(&2u16.to_le_bytes());
+
+        let phdr = &mut image[64..120];
+        phdr[..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+        phdr[4..8].copy_from_slice(&(PF_R | PF_X).to_le_bytes());
+        phdr[8..16].copy_from_slice(&(code_offset as u64).to_le_bytes());
+        phdr[32..40].copy_from_slice(&(code.len() as u64).to_le_bytes());
+        phdr[40..48].copy_from_slice(&(code.len() as u64).to_le_bytes());
+
+        image.extend_from_slice(&code);
+        image.extend_from_slice(&note);
+        image.extend_from_slice(names);
+
+        let mut section = |name: u32, offset: usize, size: usize| {
+            let mut header = [0u8; 64];
+            header[..4].copy_from_slice(&name.to_le_bytes());
+            header[24..32].copy_from_slice(&(offset as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(size as u64).to_le_bytes());
+            image.extend_from_slice(&header);
+        };
+        section(0, 0, 0);
+        section(1, note_offset, note.len());
+        section(15, names_offset, names.len());
+
+        image
+    }
+
+    #[test]
+    fn mrenclave_from_enclave_image() {
+        let code: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        let mut expected = EnclaveMeasurement::ecreate(1, 0x10000);
+        let code_flags = SGX_SECINFO_REG | SGX_SECINFO_R | SGX_SECINFO_X;
+        let mut page = [0u8; SGX_PAGE_SIZE];
+        page.copy_from_slice(&code[..SGX_PAGE_SIZE]);
+        expected.add_page(0, &page, code_flags, true);
+        page = [0u8; SGX_PAGE_SIZE];
+        page[..5000 - SGX_PAGE_SIZE].copy_from_slice(&code[SGX_PAGE_SIZE..]);
+        expected.add_page(0x1000, &page, code_flags, true);
+        // TCS with OSSA, OFSBASE and OGSBASE relocated to its RVA, FSLIMIT and GSLIMIT as is
+        page = [0u8; SGX_PAGE_SIZE];
+        page[16..24].copy_from_slice(&0x3000u64.to_le_bytes());
+        page[48..56].copy_from_slice(&0x6000u64.to_le_bytes());
+        page[56..64].copy_from_slice(&0x7000u64.to_le_bytes());
+        page[64..72].copy_from_slice(&[0xFF; 8]);
+        expected.add_page(0x2000, &page, SGX_SECINFO_TCS, true);
+        let heap_flags = SGX_SECINFO_REG | SGX_SECINFO_R | SGX_SECINFO_W;
+        expected.eadd(0x3000, heap_flags);
+        expected.eadd(0x4000, heap_flags);
+        let expected = expected.finalize();
+
+        let mrenclave = EnclaveMeasurement::from_enclave_image(&enclave_image()).unwrap();
+        assert_eq!(mrenclave, expected);
+        // SHA-256 of the ECREATE, EADD and EEXTEND blocks of the SDM, computed outside of this crate
+        assert_eq!(
+            mrenclave.to_string(),
+            "dffb899199a97aae694b85b13be47d86db310a31bff0b96acff2b915d8105431"
+        );
+    }
+
+    #[test]
+    fn malformed_enclave_image() {
+        let image = enclave_image();
+        for len in (0..image.len()).step_by(61) {
+            assert!(EnclaveMeasurement::from_enclave_image(&image[..len]).is_err());
+        }
+
+        let position = |pattern: &[u8]| {
+            image
+                .windows(pattern.len())
+                .position(|window| window == pattern)
+                .unwrap()
+        };
+        let shoff = u64_at(&image, 0x28) as usize;
+        let tcs_entry =
+            position(&[&[0, 0, 3, 0, 1, 0, 0, 0][..], &0x2000u64.to_le_bytes()].concat());
+        let tcs = position(&[0x4000u64.to_le_bytes(), 0x5000u64.to_le_bytes()].concat()) - 48;
+        let corruptions: [(usize, &[u8]); 6] = [
+            // Program header, segment size, section offset
+            (0x20, &u64::MAX.to_le_bytes()),
+            (64 + 32, &u64::MAX.to_le_bytes()),
+            (shoff + 64 + 24, &u64::MAX.to_le_bytes()),
+            // TCS content size and offset, OSSA
+            (tcs_entry + 16, &u32::MAX.to_le_bytes()),
+            (tcs_entry + 20, &u32::MAX.to_le_bytes()),
+            (tcs + 16, &u64::MAX.to_le_bytes()),
+        ];
+        for (offset, value) in corruptions {
+            let mut image = image.clone();
+            image[offset..offset + value.len()].copy_from_slice(value);
+            let e = EnclaveMeasurement::from_enclave_image(&image).unwrap_err();
+            assert!(matches!(e, SGXError::EnclaveImageParseError(_)), "{}", e);
+        }
+
+        let metadata = Metadata {
+            buf: &[0; 2 * SGX_PAGE_SIZE],
+            ssa_frame_size: 1,
+            enclave_size: 0x10000,
+        };
+        let mut entry = [0u8; LAYOUT_ENTRY_SIZE];
+        entry[2..4].copy_from_slice(&PAGE_ATTR_EADD.to_le_bytes());
+        entry[16..20].copy_from_slice(&(SGX_PAGE_SIZE as u32 + 1).to_le_bytes());
+        entry[20..24].copy_from_slice(&1u32.to_le_bytes());
+        entry[24..32].copy_from_slice(&SGX_SECINFO_TCS.to_le_bytes());
+        let mut measurement = EnclaveMeasurement::ecreate(1, 0x10000);
+        let e = metadata
+            .measure_entry(&entry, 0, &mut measurement)
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            image_error("TCS is larger than a page").to_string()
+        );
+    }
+}
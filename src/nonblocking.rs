@@ -0,0 +1,92 @@
+//! Async counterparts of the blocking API for the [tokio] runtime.
+//!
+//! Ioctls to the Occlum LibOS (and collateral fetching by the verifiers) block the thread for
+//! hundreds of milliseconds, so they are offloaded to the blocking pool with
+//! [`tokio::task::spawn_blocking`].
+//!
+//! All futures are cancel-safe: dropping the future detaches the blocking task, which completes in
+//! the background and its result is discarded. Nothing is shared with the caller in the meantime.
+use std::sync::Arc;
+
+use tokio::task::{spawn_blocking, JoinError};
+
+use crate::{QuoteVerifier, ReportData, SGXError, SGXQuote, SGXQuoteVerifyResult};
+
+fn join_error(e: JoinError) -> SGXError {
+    match e.try_into_panic() {
+        Ok(panic) => std::panic::resume_unwind(panic),
+        Err(e) => SGXError::BlockingTaskFailed(e.into()),
+    }
+}
+
+/// Run the blocking call on the blocking pool, resuming its panic in the caller
+pub(crate) async fn run_blocking<T, F>(f: F) -> Result<T, SGXError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, SGXError> + Send + 'static,
+{
+    spawn_blocking(f).await.map_err(join_error)?
+}
+
+impl SGXQuote {
+    /// Async version of [`SGXQuote::from_report_data`]
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// let quote = SGXQuote::from_report_data_async(&[0u8; 64]).await?;
+    /// ```
+    pub async fn from_report_data_async(data: &ReportData) -> Result<Self, SGXError> {
+        let data = *data;
+        run_blocking(move || Self::from_report_data(&data)).await
+    }
+
+    /// Async version of [`SGXQuote::verify_result`]
+    pub async fn verify_result_async(&self) -> Result<SGXQuoteVerifyResult, SGXError> {
+        let quote = Self::from_slice(self)?;
+        run_blocking(move || quote.verify_result()).await
+    }
+
+    /// Async version of [`SGXQuote::verify`]
+    pub async fn verify_async(&self) -> Result<(), SGXError> {
+        let quote = Self::from_slice(self)?;
+        run_blocking(move || quote.verify()).await
+    }
+}
+
+/// Run [`QuoteVerifier::verify_quote`] on the blocking pool, for backends which do ioctls or fetch
+/// collateral over the network
+///
+/// # Example
+/// ```rust ignore
+/// let verifier: Arc<dyn QuoteVerifier> = Arc::new(IoctlVerifier);
+/// let result = verify_quote_async(verifier, Arc::new(quote)).await?;
+/// ```
+pub async fn verify_quote_async<V>(
+    verifier: Arc<V>,
+    quote: Arc<SGXQuote>,
+) -> Result<SGXQuoteVerifyResult, SGXError>
+where
+    V: QuoteVerifier + ?Sized + 'static,
+{
+    run_blocking(move || verifier.verify_quote(&quote)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verify_quote_on_blocking_pool() {
+        let quote = SGXQuote::from_slice(include_bytes!("../tests/fixtures/quote.raw")).unwrap();
+        let runtime_thread = std::thread::current().id();
+        let verifier = move |_: &SGXQuote| {
+            assert_ne!(std::thread::current().id(), runtime_thread);
+            Ok(SGXQuoteVerifyResult::OutOfDate)
+        };
+
+        let result = verify_quote_async(Arc::new(verifier), Arc::new(quote))
+            .await
+            .unwrap();
+        assert_eq!(result, SGXQuoteVerifyResult::OutOfDate);
+    }
+}
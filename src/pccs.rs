@@ -0,0 +1,314 @@
+//! Collateral from the Intel Provisioning Certification Caching Service (PCCS)
+//!
+//! [`PccsClient`] fetches the collateral with the PCCS v4 API and caches it per FMSPC, so that
+//! [`DcapVerifier`](crate::dcap::DcapVerifier) does not query PCCS for every quote.
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use percent_encoding::percent_decode_str;
+
+use crate::dcap::{CollateralProvider, PckCa};
+use crate::error::message;
+use crate::instrument;
+use crate::telemetry::{self, debug};
+use crate::{RetryPolicy, SGXError, SGXQuoteCollateral};
+
+const PCK_CRL_ISSUER_CHAIN: &str = "SGX-PCK-CRL-Issuer-Chain";
+const TCB_INFO_ISSUER_CHAIN: &str = "TCB-Info-Issuer-Chain";
+const QE_IDENTITY_ISSUER_CHAIN: &str = "SGX-Enclave-Identity-Issuer-Chain";
+
+/// Cached collateral and the time it was fetched, by FMSPC and PCK CA
+type CollateralCache = HashMap<(Vec<u8>, PckCa), (Instant, SGXQuoteCollateral)>;
+
+/// PCCS client with a TTL cache of the collateral.
+///
+/// # Example
+/// ```rust ignore
+/// let pccs = PccsClient::new("https://localhost:8081")
+///     .with_cache_ttl(Duration::from_secs(3600))
+///     .with_retry_policy(RetryPolicy::default());
+/// let verifier = DcapVerifier::new(pccs);
+/// ```
+pub struct PccsClient {
+    base_url: String,
+    agent: ureq::Agent,
+    cache_ttl: Duration,
+    retry_policy: RetryPolicy,
+    cache: Mutex<CollateralCache>,
+}
+
+struct PccsResponse {
+    body: Vec<u8>,
+    issuer_chain: Option<Vec<u8>>,
+}
+
+impl PccsClient {
+    /// Client of the PCCS at `base_url`, e.g. `https://localhost:8081`
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+            cache_ttl: Duration::from_secs(3600),
+            retry_policy: RetryPolicy::none(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long fetched collateral is reused, 1 hour by default
+    pub fn with_cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// HTTP agent, e.g. with a custom timeout or TLS configuration
+    pub fn with_agent(mut self, agent: ureq::Agent) -> Self {
+        self.agent = agent;
+        self
+    }
+
+    /// Retry the requests which failed transiently, no retries by default
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Drop the cached collateral, e.g. after a TCB recovery
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn get(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        issuer_chain: Option<&str>,
+    ) -> Result<PccsResponse, SGXError> {
+        let url = format!("{}/sgx/certification/v4/{}", self.base_url, path);
+        self.retry_policy.run(&format!("GET {}", url), || {
+            let mut request = self.agent.get(&url);
+            for (name, value) in query {
+                request = request.query(name, value);
+            }
+            let response = request.call().map_err(|e| {
+                let (status, message) = match e {
+                    ureq::Error::Status(status, _) => (Some(status), format!("GET {}", url)),
+                    ureq::Error::Transport(e) => (None, e.to_string()),
+                };
+                instrument::pccs_fetch_failed(status);
+                SGXError::PccsRequestFailed { status, message }
+            })?;
+
+            let issuer_chain = issuer_chain
+                .map(|name| {
+                    let chain = response.header(name).ok_or_else(|| {
+                        SGXError::CollateralParseError(message(format!(
+                            "{} header is missing",
+                            name
+                        )))
+                    })?;
+                    percent_decode_str(chain)
+                        .decode_utf8()
+                        .map(|chain| chain.into_owned().into_bytes())
+                        .map_err(|e| SGXError::CollateralParseError(e.into()))
+                })
+                .transpose()?;
+            let mut body = Vec::new();
+            response.into_reader().read_to_end(&mut body)?;
+            Ok(PccsResponse { body, issuer_chain })
+        })
+    }
+
+    /// Fetch the collateral from PCCS, bypassing the cache
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "fetch_collateral",
+            skip_all,
+            fields(fmspc = %hex::encode(fmspc), ca = ca.as_str())
+        )
+    )]
+    pub fn fetch(&self, fmspc: &[u8], ca: PckCa) -> Result<SGXQuoteCollateral, SGXError> {
+        debug!("Fetch collateral for FMSPC {}", hex::encode(fmspc));
+        let pck_crl = self.get(
+            "pckcrl",
+            &[("ca", ca.as_str()), ("encoding", "der")],
+            Some(PCK_CRL_ISSUER_CHAIN),
+        )?;
+        let tcb_info = self.get(
+            "tcb",
+            &[("fmspc", &hex::encode_upper(fmspc))],
+            Some(TCB_INFO_ISSUER_CHAIN),
+        )?;
+        let qe_identity = self.get("qe/identity", &[], Some(QE_IDENTITY_ISSUER_CHAIN))?;
+        let root_ca_crl = self.get("rootcacrl", &[], None)?;
+
+        Ok(SGXQuoteCollateral {
+            pck_crl_issuer_chain: pck_crl.issuer_chain.unwrap_or_default(),
+            root_ca_crl: root_ca_crl.body,
+            pck_crl: pck_crl.body,
+            tcb_info_issuer_chain: tcb_info.issuer_chain.unwrap_or_default(),
+            tcb_info: tcb_info.body,
+            qe_identity_issuer_chain: qe_identity.issuer_chain.unwrap_or_default(),
+            qe_identity: qe_identity.body,
+            ..Default::default()
+        })
+    }
+}
+
+impl CollateralProvider for PccsClient {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "collateral",
+            skip_all,
+            fields(fmspc = %hex::encode(fmspc), ca = ca.as_str(), cache)
+        )
+    )]
+    fn collateral(&self, fmspc: &[u8], ca: PckCa) -> Result<SGXQuoteCollateral, SGXError> {
+        let key = (fmspc.to_vec(), ca);
+        if let Some((fetched, collateral)) = self.cache.lock().unwrap().get(&key) {
+            if fetched.elapsed() < self.cache_ttl {
+                instrument::collateral_cache(true);
+                telemetry::record("cache", "hit");
+                return Ok(collateral.clone());
+            }
+        }
+        instrument::collateral_cache(false);
+        telemetry::record("cache", "miss");
+
+        let collateral = self.fetch(fmspc, ca)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), collateral.clone()));
+        Ok(collateral)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::dcap::tests::{Synthetic, FMSPC};
+    use crate::dcap::DcapVerifier;
+    use crate::{QuoteVerifier, SGXQuoteVerifyResult};
+
+    /// Minimal PCCS serving fixed collateral on a local port
+    pub(crate) struct StubPccs {
+        pub url: String,
+        pub requests: Arc<AtomicUsize>,
+    }
+
+    fn encode_chain(chain: &[u8]) -> String {
+        percent_encoding::percent_encode(chain, percent_encoding::NON_ALPHANUMERIC).to_string()
+    }
+
+    impl StubPccs {
+        pub(crate) fn start(collateral: SGXQuoteCollateral) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(AtomicUsize::new(0));
+            let counter = requests.clone();
+
+            std::thread::spawn(move || {
+                let not_found = Vec::new();
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else { break };
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let mut request_line = String::new();
+                    let mut reader = BufReader::new(&stream);
+                    reader.read_line(&mut request_line).unwrap();
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap() <= 2 {
+                            break;
+                        }
+                    }
+
+                    let path = request_line.split(' ').nth(1).unwrap_or_default();
+                    let path = path.trim_start_matches("/sgx/certification/v4/");
+                    let (status, header, body) = match path.split('?').next().unwrap() {
+                        "pckcrl" => (
+                            200,
+                            Some((PCK_CRL_ISSUER_CHAIN, &collateral.pck_crl_issuer_chain)),
+                            &collateral.pck_crl,
+                        ),
+                        "tcb" if path.contains(&hex::encode_upper(FMSPC)) => (
+                            200,
+                            Some((TCB_INFO_ISSUER_CHAIN, &collateral.tcb_info_issuer_chain)),
+                            &collateral.tcb_info,
+                        ),
+                        "qe/identity" => (
+                            200,
+                            Some((
+                                QE_IDENTITY_ISSUER_CHAIN,
+                                &collateral.qe_identity_issuer_chain,
+                            )),
+                            &collateral.qe_identity,
+                        ),
+                        "rootcacrl" => (200, None, &collateral.root_ca_crl),
+                        _ => (404, None, &not_found),
+                    };
+
+                    let mut response = format!(
+                        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        status,
+                        body.len()
+                    );
+                    if let Some((name, chain)) = header {
+                        response.push_str(&format!("{}: {}\r\n", name, encode_chain(chain)));
+                    }
+                    response.push_str("\r\n");
+                    stream.write_all(response.as_bytes()).unwrap();
+                    stream.write_all(body).unwrap();
+                }
+            });
+            Self { url, requests }
+        }
+    }
+
+    #[test]
+    fn fetch_and_cache_collateral() {
+        let synthetic = Synthetic::new(5, false);
+        let pccs = StubPccs::start(synthetic.collateral.clone());
+
+        let client = PccsClient::new(&pccs.url);
+        assert_eq!(
+            client.fetch(&FMSPC, PckCa::Platform).unwrap(),
+            synthetic.collateral
+        );
+        assert_eq!(pccs.requests.load(Ordering::SeqCst), 4);
+
+        let verifier = DcapVerifier::new(client)
+            .with_trusted_root_pem(&synthetic.root_pem)
+            .unwrap();
+        for _ in 0..3 {
+            assert_eq!(
+                verifier.verify_quote(&synthetic.quote([1u8; 64])).unwrap(),
+                SGXQuoteVerifyResult::Ok
+            );
+        }
+        assert_eq!(pccs.requests.load(Ordering::SeqCst), 8);
+
+        let e = PccsClient::new(&pccs.url)
+            .fetch(&[0xFF; 6], PckCa::Platform)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            e,
+            SGXError::PccsRequestFailed {
+                status: Some(404),
+                ..
+            }
+        ));
+        assert!(!e.is_retryable());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::der::{
+    integer, pem_to_der, Der, TAG_ENUMERATED, TAG_INTEGER, TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE,
+};
+use crate::error::{message, SGXError};
+
+/// OID 1.2.840.113741.1.13.1 of the Intel SGX extension of the PCK certificate
+const SGX_EXTENSIONS_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01];
+
+const TAG_EXTENSIONS: u8 = 0xA3;
+
+/// TCB level of the platform, from the PCK certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SGXTcbComponents {
+    /// SGX TCB component SVNs 1-16
+    pub comp_svn: [u8; 16],
+    pub pce_svn: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
+    pub cpu_svn: [u8; 16],
+}
+
+/// Intel SGX extension of the PCK certificate.
+///
+/// RFC: "Intel SGX PCK Certificate and Certificate Revocation List Profile Specification"
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SGXPckExtensions {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
+    pub ppid: Vec<u8>,
+    pub tcb: SGXTcbComponents,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
+    pub pce_id: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
+    pub fmspc: Vec<u8>,
+    /// 0 - Standard, 1 - Scalable
+    pub sgx_type: u8,
+}
+
+fn parse_error(msg: &str) -> SGXError {
+    SGXError::CertificateParseError(message(msg))
+}
+
+impl SGXPckExtensions {
+    /// Parse the SGX extension of the DER-encoded PCK certificate
+    pub fn from_der(cert: &[u8]) -> Result<Self, SGXError> {
+        let certificate = Der(cert).expect(TAG_SEQUENCE)?;
+        let mut tbs = Der(Der(certificate).expect(TAG_SEQUENCE)?);
+
+        let extensions = loop {
+            match tbs.next()? {
+                Some((TAG_EXTENSIONS, value)) => break Der(value).expect(TAG_SEQUENCE)?,
+                Some(_) => continue,
+                None => return Err(parse_error("certificate has no extensions")),
+            }
+        };
+
+        let mut extensions = Der(extensions);
+        while let Some((_, extension)) = extensions.next()? {
+            let mut extension = Der(extension);
+            if extension.expect(TAG_OID)? != SGX_EXTENSIONS_OID {
+                continue;
+            }
+            let value = loop {
+                match extension.next()? {
+                    Some((TAG_OCTET_STRING, value)) => break value,
+                    Some(_) => continue,
+                    None => return Err(parse_error("SGX extension has no value")),
+                }
+            };
+            return Self::parse_sgx_extensions(Der(value).expect(TAG_SEQUENCE)?);
+        }
+
+        Err(parse_error("certificate has no SGX extension"))
+    }
+
+    /// Parse the SGX extension of the PEM-encoded PCK certificate
+    pub fn from_pem(cert: &str) -> Result<Self, SGXError> {
+        Self::from_der(&pem_to_der(cert)?.1)
+    }
+
+    fn parse_sgx_extensions(value: &[u8]) -> Result<Self, SGXError> {
+        let mut ppid = None;
+        let mut tcb = None;
+        let mut pce_id = None;
+        let mut fmspc = None;
+        let mut sgx_type = None;
+
+        let mut entries = Der(value);
+        while let Some((_, entry)) = entries.next()? {
+            let mut entry = Der(entry);
+            let oid = entry.expect(TAG_OID)?;
+            let Some(id) = oid.strip_prefix(SGX_EXTENSIONS_OID) else {
+                continue;
+            };
+            match id {
+                [1] => ppid = Some(entry.expect(TAG_OCTET_STRING)?.to_vec()),
+                [2] => tcb = Some(Self::parse_tcb(entry.expect(TAG_SEQUENCE)?)?),
+                [3] => pce_id = Some(entry.expect(TAG_OCTET_STRING)?.to_vec()),
+                [4] => fmspc = Some(entry.expect(TAG_OCTET_STRING)?.to_vec()),
+                [5] => sgx_type = Some(integer(entry.expect(TAG_ENUMERATED)?)? as u8),
+                _ => {}
+            }
+        }
+
+        let missing = |name: &str| parse_error(&format!("SGX extension has no {}", name));
+        Ok(Self {
+            ppid: ppid.ok_or_else(|| missing("PPID"))?,
+            tcb: tcb.ok_or_else(|| missing("TCB"))?,
+            pce_id: pce_id.ok_or_else(|| missing("PCE-ID"))?,
+            fmspc: fmspc.ok_or_else(|| missing("FMSPC"))?,
+            sgx_type: sgx_type.ok_or_else(|| missing("SGX Type"))?,
+        })
+    }
+
+    fn parse_tcb(value: &[u8]) -> Result<SGXTcbComponents, SGXError> {
+        let mut tcb = SGXTcbComponents {
+            comp_svn: [0; 16],
+            pce_svn: 0,
+            cpu_svn: [0; 16],
+        };
+
+        let mut components = Der(value);
+        while let Some((_, component)) = components.next()? {
+            let mut component = Der(component);
+            let oid = component.expect(TAG_OID)?;
+            match oid.strip_prefix(SGX_EXTENSIONS_OID) {
+                Some([2, n @ 1..=16]) => {
+                    tcb.comp_svn[*n as usize - 1] = integer(component.expect(TAG_INTEGER)?)? as u8
+                }
+                Some([2, 17]) => tcb.pce_svn = integer(component.expect(TAG_INTEGER)?)?,
+                Some([2, 18]) => {
+                    tcb.cpu_svn = component
+                        .expect(TAG_OCTET_STRING)?
+                        .try_into()
+                        .map_err(|_| parse_error("bad CPUSVN length"))?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(tcb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SGXQuote;
+
+    #[test]
+    fn parse_pck_extensions() {
+        let quote = SGXQuote::from_slice(include_bytes!("../tests/fixtures/quote.raw")).unwrap();
+        let pck = quote.signature().unwrap().pck_extensions().unwrap();
+
+        assert_eq!(pck.sgx_type, 1);
+        assert_eq!(hex::encode(&pck.fmspc), "30606a000000");
+        assert_eq!(pck.tcb.pce_svn, 11);
+        insta::assert_yaml_snapshot!(format!("{:?}", pck));
+    }
+}
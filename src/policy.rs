@@ -0,0 +1,141 @@
+use crate::telemetry;
+use crate::{SGXError, SGXMeasurement, SGXQuote, SGXQuoteVerifyResult, SGXReportData};
+
+/// Id of the policy of a request without `policy_id`, and of the single policy of the key broker
+/// and the channel in the audit log
+pub const DEFAULT_POLICY_ID: &str = "default";
+
+/// Enclave identity policy, which decides whether a verified [`SGXQuote`] is acceptable.
+///
+/// Empty lists and missing values allow anything. Terminal verification results are always
+/// rejected.
+///
+/// With the `serde` feature the policy may be loaded from JSON:
+/// ```json
+/// {
+///     "mrsigner": ["6d5ead54bfbe9494e1cd9042bb7c25d74c597d4700e332b1b3168a60712c1e02"],
+///     "product_id": 4000,
+///     "min_version": 5000,
+///     "allowed_results": ["Ok", "SwHardeningNeeded"]
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct AppraisalPolicy {
+    /// Allowed enclave measurements
+    pub mrenclave: Vec<SGXMeasurement>,
+    /// Allowed enclave signers
+    pub mrsigner: Vec<SGXMeasurement>,
+    /// Required `isv_prod_id`
+    pub product_id: Option<u16>,
+    /// Minimal `isv_svn`
+    pub min_version: Option<u16>,
+    /// Accept enclaves launched in debug mode
+    pub allow_debug: bool,
+    /// Accepted verification results, any non-terminal result if empty
+    pub allowed_results: Vec<SGXQuoteVerifyResult>,
+    /// Required report data
+    pub report_data: Option<SGXReportData>,
+}
+
+impl AppraisalPolicy {
+    /// Check the quote and its verification result against the policy.
+    ///
+    /// Returns [`SGXError::VerifyQuoteFailed`] for the rejected verification results and
+    /// [`SGXError::AppraisalRejected`] if the enclave identity doesn't match.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "appraise",
+            skip_all,
+            fields(mrenclave = %quote.mrenclave(), result = result.tcb_status(), verdict, error)
+        )
+    )]
+    pub fn appraise(&self, quote: &SGXQuote, result: SGXQuoteVerifyResult) -> Result<(), SGXError> {
+        let verdict = self.check(quote, result);
+        match &verdict {
+            Ok(()) => telemetry::record("verdict", "accepted"),
+            Err(e) => {
+                telemetry::record("verdict", "rejected");
+                telemetry::record_error(e);
+            }
+        }
+        verdict
+    }
+
+    fn check(&self, quote: &SGXQuote, result: SGXQuoteVerifyResult) -> Result<(), SGXError> {
+        if result.is_terminal()
+            || !(self.allowed_results.is_empty() || self.allowed_results.contains(&result))
+        {
+            return Err(SGXError::VerifyQuoteFailed(result));
+        }
+
+        let reject = |msg: String| Err(SGXError::AppraisalRejected(msg));
+
+        if !self.mrenclave.is_empty() && !self.mrenclave.contains(&quote.mrenclave()) {
+            return reject(format!("mrenclave {} is not allowed", quote.mrenclave()));
+        }
+        if !self.mrsigner.is_empty() && !self.mrsigner.contains(&quote.mrsigner()) {
+            return reject(format!("mrsigner {} is not allowed", quote.mrsigner()));
+        }
+        if let Some(product_id) = self.product_id {
+            if quote.product_id() != product_id {
+                return reject(format!("product id {} is not allowed", quote.product_id()));
+            }
+        }
+        if let Some(min_version) = self.min_version {
+            if quote.version() < min_version {
+                return reject(format!(
+                    "version {} is lower than {}",
+                    quote.version(),
+                    min_version
+                ));
+            }
+        }
+        if !self.allow_debug && quote.is_debug() {
+            return reject("debug enclave is not allowed".into());
+        }
+        if let Some(report_data) = &self.report_data {
+            if *quote.report_data() != **report_data {
+                return Err(SGXError::ReportDataMismatch);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appraise() {
+        let quote = SGXQuote::from_slice(include_bytes!("../tests/fixtures/quote.raw")).unwrap();
+
+        let policy = AppraisalPolicy {
+            mrsigner: vec![quote.mrsigner()],
+            product_id: Some(4000),
+            min_version: Some(5000),
+            ..Default::default()
+        };
+        policy.appraise(&quote, SGXQuoteVerifyResult::Ok).unwrap();
+        policy
+            .appraise(&quote, SGXQuoteVerifyResult::OutOfDate)
+            .unwrap();
+        assert!(matches!(
+            policy.appraise(&quote, SGXQuoteVerifyResult::Revoked),
+            Err(SGXError::VerifyQuoteFailed(SGXQuoteVerifyResult::Revoked))
+        ));
+
+        let policy = AppraisalPolicy {
+            min_version: Some(5001),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.appraise(&quote, SGXQuoteVerifyResult::Ok),
+            Err(SGXError::AppraisalRejected(_))
+        ));
+    }
+}
@@ -0,0 +1,285 @@
+//! Host-side quote verification with the Intel DCAP Quote Verification Library.
+//!
+//! `libsgx_dcap_quoteverify.so` is loaded at runtime, so the crate builds and runs without it.
+use std::ffi::{c_char, c_void, OsStr};
+use std::ptr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use libloading::{Library, Symbol};
+
+use crate::error::message;
+use crate::instrument;
+use crate::telemetry::{self, trace};
+use crate::{QuoteVerifier, SGXError, SGXQuote, SGXQuoteCollateral, SGXQuoteVerifyResult};
+
+/// Library names tried by [`QvlVerifier::load`]
+pub const QVL_LIBRARY_NAMES: &[&str] =
+    &["libsgx_dcap_quoteverify.so.1", "libsgx_dcap_quoteverify.so"];
+
+const SGX_QL_SUCCESS: u32 = 0x0000;
+
+#[repr(C)]
+struct RawCollateral {
+    major_version: u16,
+    minor_version: u16,
+    tee_type: u32,
+    pck_crl_issuer_chain: *const c_char,
+    pck_crl_issuer_chain_size: u32,
+    root_ca_crl: *const c_char,
+    root_ca_crl_size: u32,
+    pck_crl: *const c_char,
+    pck_crl_size: u32,
+    tcb_info_issuer_chain: *const c_char,
+    tcb_info_issuer_chain_size: u32,
+    tcb_info: *const c_char,
+    tcb_info_size: u32,
+    qe_identity_issuer_chain: *const c_char,
+    qe_identity_issuer_chain_size: u32,
+    qe_identity: *const c_char,
+    qe_identity_size: u32,
+}
+
+type GetSupplementalDataSizeFn = unsafe extern "C" fn(*mut u32) -> u32;
+type VerifyQuoteFn = unsafe extern "C" fn(
+    *const u8,
+    u32,
+    *const RawCollateral,
+    libc::time_t,
+    *mut u32,
+    *mut u32,
+    *mut c_void,
+    u32,
+    *mut u8,
+) -> u32;
+
+/// Outcome of [`QvlVerifier::verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QvlVerification {
+    pub result: SGXQuoteVerifyResult,
+    /// `true` if some collateral expired before the expiration check time
+    pub collateral_expired: bool,
+    /// Raw `sgx_ql_qv_supplemental_t`
+    pub supplemental_data: Vec<u8>,
+}
+
+/// Verifies quotes with `sgx_qv_verify_quote` of the Intel DCAP Quote Verification Library, with
+/// the same [`SGXQuoteVerifyResult`] as [`SGXQuote::verify_result`].
+///
+/// Without collateral, the library fetches it from PCCS through the configured Quote Provider
+/// Library.
+///
+/// # Example
+/// ```rust ignore
+/// let verifier = QvlVerifier::load()?.with_collateral(collateral);
+/// let result = verifier.verify_quote(&quote)?;
+/// ```
+pub struct QvlVerifier {
+    library: Library,
+    collateral: Option<SGXQuoteCollateral>,
+    expiration_time: Option<SystemTime>,
+}
+
+/// NUL-terminated copy, the library expects the sizes to include the terminator
+fn nul_terminated(data: &[u8]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    if data.last() != Some(&0) {
+        data.push(0);
+    }
+    data
+}
+
+impl QvlVerifier {
+    /// Load the library from the default search path, see [`QVL_LIBRARY_NAMES`]
+    pub fn load() -> Result<Self, SGXError> {
+        let mut errors = Vec::new();
+        for name in QVL_LIBRARY_NAMES {
+            match Self::load_from(name) {
+                Ok(verifier) => return Ok(verifier),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        Err(SGXError::LibraryLoadFailed(message(errors.join("; "))))
+    }
+
+    /// Load the library from `path`
+    pub fn load_from<P: AsRef<OsStr>>(path: P) -> Result<Self, SGXError> {
+        let library = unsafe { Library::new(path.as_ref()) }
+            .map_err(|e| SGXError::LibraryLoadFailed(e.into()))?;
+        Ok(Self {
+            library,
+            collateral: None,
+            expiration_time: None,
+        })
+    }
+
+    /// Collateral used by [`QuoteVerifier::verify_quote`]
+    pub fn with_collateral(mut self, collateral: SGXQuoteCollateral) -> Self {
+        self.collateral = Some(collateral);
+        self
+    }
+
+    /// Time to check the collateral expiration against, the current time by default
+    pub fn with_expiration_time(mut self, expiration_time: SystemTime) -> Self {
+        self.expiration_time = Some(expiration_time);
+        self
+    }
+
+    fn symbol<T>(&self, name: &'static str) -> Result<Symbol<'_, T>, SGXError> {
+        unsafe { self.library.get(name.as_bytes()) }
+            .map_err(|e| SGXError::LibraryLoadFailed(message(format!("{}: {}", name, e))))
+    }
+
+    pub fn supplemental_data_size(&self) -> Result<u32, SGXError> {
+        let get_size: Symbol<GetSupplementalDataSizeFn> =
+            self.symbol("sgx_qv_get_quote_supplemental_data_size")?;
+        let mut size = 0;
+        let ret = unsafe { get_size(&mut size) };
+        if ret != SGX_QL_SUCCESS {
+            return Err(SGXError::QvlError {
+                function: "sgx_qv_get_quote_supplemental_data_size",
+                code: ret,
+            });
+        }
+        Ok(size)
+    }
+
+    /// Verify the quote with the caller-supplied collateral (fetched by the library if `None`) and
+    /// the collateral expiration check time
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "verify_quote",
+            skip_all,
+            fields(backend = "qvl", quote_hash, mrenclave, result, error)
+        )
+    )]
+    pub fn verify(
+        &self,
+        quote: &SGXQuote,
+        collateral: Option<&SGXQuoteCollateral>,
+        expiration_time: SystemTime,
+    ) -> Result<QvlVerification, SGXError> {
+        telemetry::record_quote(quote);
+        let start = Instant::now();
+        let verification = self.verify_quote_raw(quote, collateral, expiration_time);
+        instrument::quote_verified("qvl", start, verification.as_ref().map(|v| v.result));
+        telemetry::record_result(verification.as_ref().map(|v| v.result));
+        verification
+    }
+
+    fn verify_quote_raw(
+        &self,
+        quote: &SGXQuote,
+        collateral: Option<&SGXQuoteCollateral>,
+        expiration_time: SystemTime,
+    ) -> Result<QvlVerification, SGXError> {
+        let verify_quote: Symbol<VerifyQuoteFn> = self.symbol("sgx_qv_verify_quote")?;
+        let mut supplemental_data = vec![0u8; self.supplemental_data_size()? as usize];
+
+        let buffers = collateral.map(|collateral| {
+            [
+                &collateral.pck_crl_issuer_chain,
+                &collateral.root_ca_crl,
+                &collateral.pck_crl,
+                &collateral.tcb_info_issuer_chain,
+                &collateral.tcb_info,
+                &collateral.qe_identity_issuer_chain,
+                &collateral.qe_identity,
+            ]
+            .map(|data| nul_terminated(data))
+        });
+        let raw_collateral = collateral
+            .zip(buffers.as_ref())
+            .map(|(collateral, buffers)| {
+                let field = |i: usize| {
+                    (
+                        buffers[i].as_ptr() as *const c_char,
+                        buffers[i].len() as u32,
+                    )
+                };
+                RawCollateral {
+                    major_version: collateral.major_version,
+                    minor_version: collateral.minor_version,
+                    tee_type: collateral.tee_type,
+                    pck_crl_issuer_chain: field(0).0,
+                    pck_crl_issuer_chain_size: field(0).1,
+                    root_ca_crl: field(1).0,
+                    root_ca_crl_size: field(1).1,
+                    pck_crl: field(2).0,
+                    pck_crl_size: field(2).1,
+                    tcb_info_issuer_chain: field(3).0,
+                    tcb_info_issuer_chain_size: field(3).1,
+                    tcb_info: field(4).0,
+                    tcb_info_size: field(4).1,
+                    qe_identity_issuer_chain: field(5).0,
+                    qe_identity_issuer_chain_size: field(5).1,
+                    qe_identity: field(6).0,
+                    qe_identity_size: field(6).1,
+                }
+            });
+
+        let expiration_time = expiration_time
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs() as libc::time_t)
+            .unwrap_or_default();
+        let mut collateral_expiration_status = 1;
+        let mut result = SGXQuoteVerifyResult::Unspecified as u32;
+
+        trace!("sgx_qv_verify_quote: Verify SGX DCAP Quote");
+        let ret = unsafe {
+            verify_quote(
+                quote.as_ptr(),
+                quote.len() as u32,
+                raw_collateral
+                    .as_ref()
+                    .map_or(ptr::null(), |collateral| collateral as *const _),
+                expiration_time,
+                &mut collateral_expiration_status,
+                &mut result,
+                ptr::null_mut(),
+                supplemental_data.len() as u32,
+                if supplemental_data.is_empty() {
+                    ptr::null_mut()
+                } else {
+                    supplemental_data.as_mut_ptr()
+                },
+            )
+        };
+        if ret != SGX_QL_SUCCESS {
+            return Err(SGXError::QvlError {
+                function: "sgx_qv_verify_quote",
+                code: ret,
+            });
+        }
+
+        Ok(QvlVerification {
+            result: result.into(),
+            collateral_expired: collateral_expiration_status != 0,
+            supplemental_data,
+        })
+    }
+}
+
+impl QuoteVerifier for QvlVerifier {
+    fn verify_quote(&self, quote: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> {
+        let expiration_time = self.expiration_time.unwrap_or_else(SystemTime::now);
+        Ok(self
+            .verify(quote, self.collateral.as_ref(), expiration_time)?
+            .result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_missing_library() {
+        let e = QvlVerifier::load_from("/nonexistent/libsgx_dcap_quoteverify.so")
+            .err()
+            .unwrap();
+        assert!(matches!(e, SGXError::LibraryLoadFailed(_)));
+        assert_eq!(nul_terminated(b"crl"), b"crl\0");
+        assert_eq!(nul_terminated(b"crl\0"), b"crl\0");
+    }
+}
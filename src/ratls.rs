@@ -0,0 +1,372 @@
+//! [RA-TLS] certificate verifiers for [rustls]
+//!
+//! An RA-TLS certificate is a self-signed certificate generated inside an enclave, which carries
+//! an [`SGXQuote`] in an X.509 extension. The first 32 bytes of the quote report data contain the
+//! SHA-256 hash of the certificate's DER-encoded SubjectPublicKeyInfo, which binds the TLS key to
+//! the enclave (see [`report_data_for_public_key`]).
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::{ratls::RaTlsVerifier, IoctlVerifier, SGXError, SGXMeasurement};
+//!
+//! let expected = SGXMeasurement::from_hex("9c90fd81f6e9fe64b46b14f0623523a52d6a5678482988c408f6adffe6301e2c")?;
+//! let verifier = RaTlsVerifier::new(IoctlVerifier).with_appraisal(move |quote, _| {
+//!     if quote.mrenclave() == expected {
+//!         Ok(())
+//!     } else {
+//!         Err(SGXError::AppraisalRejected("unexpected mrenclave".into()))
+//!     }
+//! });
+//!
+//! let config = rustls::ClientConfig::builder()
+//!     .dangerous()
+//!     .with_custom_certificate_verifier(verifier.into_server_verifier())
+//!     .with_no_client_auth();
+//! ```
+//!
+//! [RA-TLS]: https://arxiv.org/abs/1801.05863
+//! [rustls]: https://docs.rs/rustls
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{
+    CertificateError, DigitallySignedStruct, DistinguishedName, Error, OtherError, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use x509_parser::der_parser::{oid, Oid};
+use x509_parser::prelude::{ASN1Time, FromDer, X509Certificate};
+
+use crate::constants::SGX_REPORT_DATA_SIZE;
+use crate::{QuoteVerifier, ReportData, SGXError, SGXQuote, SGXQuoteVerifyResult};
+
+/// OIDs of the X.509 extensions which may carry a raw [`SGXQuote`], in lookup order.
+///
+/// - `1.2.840.113741.1.13.1.6`: Gramine RA-TLS
+/// - `1.2.840.113741.1337.6`: Gramine RA-TLS (legacy)
+///
+/// The TCG/Intel interoperable evidence extension (`1.2.840.113741.1.5.5.1.6`) holds tagged CBOR
+/// evidence rather than a raw quote and is not supported.
+pub const QUOTE_EXTENSION_OIDS: [Oid<'static>; 2] = [
+    oid!(1.2.840 .113741 .1 .13 .1 .6),
+    oid!(1.2.840 .113741 .1337 .6),
+];
+
+/// Appraisal callback, called for the quotes which passed verification with a non-terminal result
+pub type AppraisalFn =
+    dyn Fn(&SGXQuote, SGXQuoteVerifyResult) -> Result<(), SGXError> + Send + Sync;
+
+/// Report data which binds a certificate public key to a quote.
+///
+/// `spki_der` is the DER-encoded SubjectPublicKeyInfo of the certificate. The result contains its
+/// SHA-256 hash followed by zeroes.
+pub fn report_data_for_public_key(spki_der: &[u8]) -> ReportData {
+    let mut report_data = [0u8; SGX_REPORT_DATA_SIZE];
+    report_data[..32].copy_from_slice(&Sha256::digest(spki_der));
+    report_data
+}
+
+/// Verifier of RA-TLS certificates.
+///
+/// The certificate is accepted if:
+/// - it contains a quote in one of the [`QUOTE_EXTENSION_OIDS`] extensions,
+/// - the quote report data binds the certificate public key,
+/// - the [`QuoteVerifier`] returns a non-terminal [`SGXQuoteVerifyResult`],
+/// - the appraisal callback returns `Ok(())`.
+///
+/// Use [`RaTlsVerifier::into_server_verifier`] and [`RaTlsVerifier::into_client_verifier`] to
+/// plug it into rustls.
+#[derive(Clone)]
+pub struct RaTlsVerifier {
+    verifier: Arc<dyn QuoteVerifier>,
+    appraisal: Arc<AppraisalFn>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl RaTlsVerifier {
+    /// Create a verifier which checks quotes with `verifier` and accepts any enclave identity
+    pub fn new<V: QuoteVerifier + 'static>(verifier: V) -> Self {
+        Self {
+            verifier: Arc::new(verifier),
+            appraisal: Arc::new(|_, _| Ok(())),
+            provider: Arc::new(rustls::crypto::ring::default_provider()),
+        }
+    }
+
+    /// Set the callback which checks the enclave identity (mrenclave, mrsigner, etc.)
+    pub fn with_appraisal<F>(mut self, appraisal: F) -> Self
+    where
+        F: Fn(&SGXQuote, SGXQuoteVerifyResult) -> Result<(), SGXError> + Send + Sync + 'static,
+    {
+        self.appraisal = Arc::new(appraisal);
+        self
+    }
+
+    /// Set the crypto provider used to verify TLS handshake signatures, `ring` by default
+    pub fn with_crypto_provider(mut self, provider: Arc<CryptoProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    pub fn into_server_verifier(self) -> Arc<RaTlsServerCertVerifier> {
+        Arc::new(RaTlsServerCertVerifier(self))
+    }
+
+    pub fn into_client_verifier(self) -> Arc<RaTlsClientCertVerifier> {
+        Arc::new(RaTlsClientCertVerifier(self))
+    }
+
+    /// Verify DER-encoded RA-TLS certificate and return the [`SGXQuote`] it carries
+    pub fn verify_certificate(&self, cert_der: &[u8]) -> Result<SGXQuote, SGXError> {
+        let (_, cert) = X509Certificate::from_der(cert_der)
+            .map_err(|e| SGXError::CertificateParseError(e.into()))?;
+        self.verify_parsed(&cert)
+    }
+
+    fn verify_parsed(&self, cert: &X509Certificate<'_>) -> Result<SGXQuote, SGXError> {
+        let quote = SGXQuote::from_slice(extract_quote(cert)?)?;
+
+        let expected = report_data_for_public_key(cert.public_key().raw);
+        if *quote.report_data() != expected {
+            return Err(SGXError::ReportDataMismatch);
+        }
+
+        let result = self.verifier.verify_quote(&quote)?;
+        if result.is_terminal() {
+            return Err(SGXError::VerifyQuoteFailed(result));
+        }
+
+        (self.appraisal)(&quote, result)?;
+
+        Ok(quote)
+    }
+
+    fn verify_end_entity(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        now: UnixTime,
+    ) -> Result<(), Error> {
+        let (_, cert) = X509Certificate::from_der(end_entity)
+            .map_err(|_| Error::InvalidCertificate(CertificateError::BadEncoding))?;
+
+        let now = ASN1Time::from_timestamp(now.as_secs() as i64)
+            .map_err(|_| Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        if now < cert.validity().not_before {
+            return Err(Error::InvalidCertificate(CertificateError::NotValidYet));
+        }
+        if now > cert.validity().not_after {
+            return Err(Error::InvalidCertificate(CertificateError::Expired));
+        }
+
+        self.verify_parsed(&cert).map_err(|e| {
+            Error::InvalidCertificate(CertificateError::Other(OtherError(Arc::new(e))))
+        })?;
+
+        Ok(())
+    }
+}
+
+impl Debug for RaTlsVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RaTlsVerifier").finish_non_exhaustive()
+    }
+}
+
+fn extract_quote<'a>(cert: &'a X509Certificate<'_>) -> Result<&'a [u8], SGXError> {
+    QUOTE_EXTENSION_OIDS
+        .iter()
+        .find_map(|oid| {
+            cert.extensions()
+                .iter()
+                .find(|ext| ext.oid == *oid)
+                .map(|ext| ext.value)
+        })
+        .ok_or(SGXError::QuoteExtensionNotFound)
+}
+
+/// rustls [`ServerCertVerifier`] for RA-TLS, see [`RaTlsVerifier`].
+///
+/// The server name is ignored, the peer is identified by the quote only.
+#[derive(Debug)]
+pub struct RaTlsServerCertVerifier(RaTlsVerifier);
+
+impl ServerCertVerifier for RaTlsServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        self.0.verify_end_entity(end_entity, now)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0
+            .provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// rustls [`ClientCertVerifier`] for RA-TLS, see [`RaTlsVerifier`].
+///
+/// Client authentication is mandatory.
+#[derive(Debug)]
+pub struct RaTlsClientCertVerifier(RaTlsVerifier);
+
+impl ClientCertVerifier for RaTlsClientCertVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, Error> {
+        self.0.verify_end_entity(end_entity, now)?;
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0
+            .provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{CertificateParams, CustomExtension, KeyPair};
+
+    use super::*;
+    use crate::tests::{accept_all, REPORT_DATA_OFFSET};
+
+    fn ratls_cert(bind_key: bool) -> Vec<u8> {
+        let key = KeyPair::generate().unwrap();
+        let mut quote = include_bytes!("../tests/fixtures/quote.raw").to_vec();
+        if bind_key {
+            let report_data = report_data_for_public_key(&key.public_key_der());
+            quote[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + SGX_REPORT_DATA_SIZE]
+                .copy_from_slice(&report_data);
+        }
+
+        let mut params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+        params
+            .custom_extensions
+            .push(CustomExtension::from_oid_content(
+                &[1, 2, 840, 113741, 1, 13, 1, 6],
+                quote,
+            ));
+        params.self_signed(&key).unwrap().der().to_vec()
+    }
+
+    #[test]
+    fn verify_bound_certificate() {
+        let verifier = RaTlsVerifier::new(accept_all).with_appraisal(|quote, result| {
+            assert_eq!(quote.product_id(), 4000);
+            assert_eq!(result, SGXQuoteVerifyResult::Ok);
+            Ok(())
+        });
+
+        let cert = CertificateDer::from(ratls_cert(true));
+        verifier
+            .into_server_verifier()
+            .verify_server_cert(
+                &cert,
+                &[],
+                &ServerName::try_from("localhost").unwrap(),
+                &[],
+                UnixTime::now(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn reject_unbound_certificate() {
+        let verifier = RaTlsVerifier::new(accept_all);
+        let err = verifier.verify_certificate(&ratls_cert(false)).unwrap_err();
+        assert!(matches!(err, SGXError::ReportDataMismatch));
+    }
+
+    #[test]
+    fn reject_terminal_result_and_appraisal() {
+        let cert = ratls_cert(true);
+
+        let verifier = RaTlsVerifier::new(|_: &SGXQuote| Ok(SGXQuoteVerifyResult::Revoked));
+        let err = verifier.verify_certificate(&cert).unwrap_err();
+        assert!(matches!(
+            err,
+            SGXError::VerifyQuoteFailed(SGXQuoteVerifyResult::Revoked)
+        ));
+
+        let verifier = RaTlsVerifier::new(accept_all)
+            .with_appraisal(|_, _| Err(SGXError::AppraisalRejected("mrenclave".into())));
+        let err = verifier.verify_certificate(&cert).unwrap_err();
+        assert!(matches!(err, SGXError::AppraisalRejected(_)));
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::error::SGXError;
+use crate::telemetry::{debug, warn};
+
+/// Retry policy for the requests which may fail transiently, e.g. quote generation while PCCS is
+/// briefly unavailable.
+///
+/// Only errors with [`SGXError::is_retryable`] are retried. The delay before the attempt `n`
+/// (starting from 1 for the first retry) is `initial_backoff * multiplier^(n - 1)`, capped by
+/// `max_backoff` and randomized by `±jitter`.
+///
+/// # Example
+/// ```rust ignore
+/// let policy = RetryPolicy {
+///     max_attempts: 5,
+///     ..Default::default()
+/// };
+/// let quote = SGXQuote::from_report_data_with_retry(&report_data, &policy)?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Fraction of the delay, `0.0..=1.0`
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+/// Random number in `-1.0..1.0`
+fn random_unit() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random as f64 / u64::MAX as f64) * 2.0 - 1.0
+}
+
+impl RetryPolicy {
+    /// Single attempt without retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before the `retry`-th retry, starting from 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_backoff.as_secs_f64());
+        let jitter = delay * self.jitter.clamp(0.0, 1.0) * random_unit();
+        Duration::from_secs_f64((delay + jitter).max(0.0))
+    }
+
+    /// Call `f` until it succeeds, fails with a non-retryable error or runs out of attempts
+    pub fn run<T>(
+        &self,
+        operation: &str,
+        mut f: impl FnMut() -> Result<T, SGXError>,
+    ) -> Result<T, SGXError> {
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            debug!("{}: attempt {}/{}", operation, attempt, max_attempts);
+            match f() {
+                Err(e) if e.is_retryable() && attempt < max_attempts => {
+                    let delay = self.backoff(attempt);
+                    warn!(
+                        "{} failed (attempt {}/{}): {}, retrying in {:?}",
+                        operation, attempt, max_attempts, e, delay
+                    );
+                    sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::SGXQuoteVerifyResult;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    fn transient_error() -> SGXError {
+        SGXError::IoctlClientError {
+            request_type: "IOCTL_GEN_DCAP_QUOTE",
+            source: io::Error::from_raw_os_error(libc::EAGAIN),
+        }
+    }
+
+    #[test]
+    fn retry() {
+        let mut attempts = 0;
+        let result = policy().run("generate", || {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(transient_error()),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = policy().run("generate", || {
+            attempts += 1;
+            Err(transient_error())
+        });
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = policy().run("generate", || {
+            attempts += 1;
+            Err(SGXError::VerifyQuoteFailed(SGXQuoteVerifyResult::Revoked))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(2));
+
+        let policy = RetryPolicy::default();
+        let delay = policy.backoff(2);
+        assert!(delay >= Duration::from_millis(160) && delay <= Duration::from_millis(240));
+    }
+}
@@ -0,0 +1,239 @@
+//! [serde] support
+//!
+//! Human-readable formats (JSON, YAML, ...) get measurements and other fixed-size identifiers as hex
+//! strings, report data, config id and quotes as base64 strings. Binary formats get raw bytes.
+//!
+//! [serde]: https://serde.rs
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::SGX_HASH_SIZE;
+use crate::{SGXConfigId, SGXExtProdId, SGXFamilyId, SGXMeasurement, SGXQuote, SGXReportData};
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Hex,
+    Base64,
+}
+
+impl Encoding {
+    fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Hex => hex::encode(bytes),
+            Encoding::Base64 => base64.encode(bytes),
+        }
+    }
+
+    fn decode(self, s: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Hex => hex::decode(s).map_err(|e| e.to_string()),
+            Encoding::Base64 => base64.decode(s).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn serialize_bytes<S: Serializer>(
+    bytes: &[u8],
+    encoding: Encoding,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&encoding.encode(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+struct BytesVisitor(Encoding);
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Encoding::Hex => f.write_str("hex string or bytes"),
+            Encoding::Base64 => f.write_str("base64 string or bytes"),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        self.0.decode(v).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    encoding: Encoding,
+) -> Result<Vec<u8>, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor(encoding))
+    } else {
+        deserializer.deserialize_byte_buf(BytesVisitor(encoding))
+    }
+}
+
+fn deserialize_array<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+    encoding: Encoding,
+) -> Result<[u8; N], D::Error> {
+    deserialize_bytes(deserializer, encoding)?
+        .try_into()
+        .map_err(|x: Vec<u8>| de::Error::invalid_length(x.len(), &ByteLength(N)))
+}
+
+struct ByteLength(usize);
+
+impl de::Expected for ByteLength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes", self.0)
+    }
+}
+
+/// `#[serde(with)]` module for byte fields encoded as hex
+pub(crate) mod hex_bytes {
+    use super::*;
+
+    pub fn serialize<T: AsRef<[u8]>, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(v.as_ref(), Encoding::Hex, s)
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let bytes = deserialize_bytes(d, Encoding::Hex)?;
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| de::Error::invalid_length(len, &"fixed size bytes"))
+    }
+}
+
+/// `#[serde(with)]` module for byte fields encoded as base64
+pub(crate) mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<T: AsRef<[u8]>, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(v.as_ref(), Encoding::Base64, s)
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let bytes = deserialize_bytes(d, Encoding::Base64)?;
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| de::Error::invalid_length(len, &"fixed size bytes"))
+    }
+}
+
+impl Serialize for SGXMeasurement {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self, Encoding::Hex, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SGXMeasurement {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_array::<_, SGX_HASH_SIZE>(deserializer, Encoding::Hex).map(Self::new)
+    }
+}
+
+impl Serialize for SGXReportData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self, Encoding::Base64, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SGXReportData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_array(deserializer, Encoding::Base64).map(Self::new)
+    }
+}
+
+impl Serialize for SGXConfigId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.0, Encoding::Base64, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SGXConfigId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_array(deserializer, Encoding::Base64).map(Self)
+    }
+}
+
+fn u128_halves(bytes: [u8; 16]) -> (u64, u64) {
+    let (low, high) = bytes.split_at(8);
+    (
+        u64::from_le_bytes(low.try_into().unwrap()),
+        u64::from_le_bytes(high.try_into().unwrap()),
+    )
+}
+
+fn u128_bytes(low: u64, high: u64) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&low.to_le_bytes());
+    bytes[8..].copy_from_slice(&high.to_le_bytes());
+    bytes
+}
+
+impl Serialize for SGXFamilyId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&u128_bytes(self.0, self.1), Encoding::Hex, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SGXFamilyId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (low, high) = u128_halves(deserialize_array(deserializer, Encoding::Hex)?);
+        Ok(Self(low, high))
+    }
+}
+
+impl Serialize for SGXExtProdId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&u128_bytes(self.0, self.1), Encoding::Hex, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SGXExtProdId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (low, high) = u128_halves(deserialize_array(deserializer, Encoding::Hex)?);
+        Ok(Self(low, high))
+    }
+}
+
+impl Serialize for SGXQuote {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self, Encoding::Base64, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SGXQuote {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_bytes(deserializer, Encoding::Base64)?
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,432 @@
+//! Attestation verification HTTP service
+//!
+//! [`VerificationService`] verifies quotes for the relying parties with [`DcapVerifier`], appraises
+//! them with the [`AppraisalPolicy`] selected by the request and returns the result as an EAT JWT
+//! signed by the [`EatIssuer`].
+//!
+//! | Endpoint                      | Description                                               |
+//! |-------------------------------|-----------------------------------------------------------|
+//! | `POST /verify`                | `{"quote": base64, "nonce"?: base64, "policy_id"?: id}`   |
+//! | `GET /nonce`                  | Single-use nonce, the quote report data must start with it |
+//! | `GET /health`                 | Liveness check                                            |
+//! | `GET /metrics`                | Crate metrics in the Prometheus text format, see [`VerificationService::with_metrics`] |
+//! | `GET /.well-known/jwks.json`  | Token signing key, for [`EatVerifier`](crate::eat::EatVerifier) |
+//!
+//! # Example
+//! ```rust ignore
+//! use occlum_sgx::service::VerificationService;
+//!
+//! let verifier = DcapVerifier::new(PccsClient::new("https://localhost:8081"));
+//! let service = VerificationService::new(verifier, issuer).with_policy("default", policy);
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
+//! axum::serve(listener, service.router()).await?;
+//! ```
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+
+#[cfg(feature = "audit")]
+use crate::audit::{AuditEntry, AuditSink};
+use crate::dcap::{DcapVerification, DcapVerifier};
+use crate::eat::{AttestationClaims, EatIssuer, Jwks};
+use crate::http::{blocking, ErrorResponse, Nonces};
+use crate::instrument;
+use crate::telemetry::{self, debug};
+use crate::{AppraisalPolicy, SGXError, SGXErrorKind, SGXQuote};
+
+pub use crate::api::{NonceResponse, VerifyRequest, VerifyResponse};
+pub use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+pub use crate::DEFAULT_POLICY_ID;
+
+/// Quote verification service, see the [module documentation](self)
+pub struct VerificationService {
+    verifier: DcapVerifier,
+    issuer: EatIssuer,
+    policies: HashMap<String, AppraisalPolicy>,
+    nonces: Nonces,
+    metrics: Option<PrometheusHandle>,
+    #[cfg(feature = "audit")]
+    audit_sink: Option<Arc<dyn AuditSink>>,
+}
+
+fn rejected(msg: &str) -> SGXError {
+    SGXError::AppraisalRejected(msg.to_string())
+}
+
+impl VerificationService {
+    pub fn new(verifier: DcapVerifier, issuer: EatIssuer) -> Self {
+        Self {
+            verifier,
+            issuer,
+            policies: HashMap::new(),
+            nonces: Nonces::new(Duration::from_secs(300)),
+            metrics: None,
+            #[cfg(feature = "audit")]
+            audit_sink: None,
+        }
+    }
+
+    /// Register the policy under `id`, see [`DEFAULT_POLICY_ID`]
+    pub fn with_policy<S: Into<String>>(mut self, id: S, policy: AppraisalPolicy) -> Self {
+        self.policies.insert(id.into(), policy);
+        self
+    }
+
+    /// Register all the policies of the map
+    pub fn with_policies(mut self, policies: HashMap<String, AppraisalPolicy>) -> Self {
+        self.policies.extend(policies);
+        self
+    }
+
+    /// How long an issued nonce may be used, 5 minutes by default
+    pub fn with_nonce_ttl(mut self, nonce_ttl: Duration) -> Self {
+        self.nonces = Nonces::new(nonce_ttl);
+        self
+    }
+
+    /// Serve `GET /metrics` from the Prometheus recorder of `handle`, which must be the global
+    /// recorder to receive the [crate metrics](crate::describe_metrics):
+    /// ```rust ignore
+    /// let handle = PrometheusBuilder::new().install_recorder()?;
+    /// occlum_sgx::describe_metrics();
+    /// let service = VerificationService::new(verifier, issuer).with_metrics(handle);
+    /// ```
+    pub fn with_metrics(mut self, handle: PrometheusHandle) -> Self {
+        self.metrics = Some(handle);
+        self
+    }
+
+    /// Record every decision to `sink`, requests fail if it can't be recorded
+    #[cfg(feature = "audit")]
+    pub fn with_audit_sink<S: AuditSink + 'static>(mut self, sink: S) -> Self {
+        self.audit_sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Issue a single-use nonce
+    pub fn issue_nonce(&self) -> Result<Vec<u8>, SGXError> {
+        let nonce = self.nonces.issue()?;
+        instrument::nonce_issued();
+        Ok(nonce)
+    }
+
+    /// Verify and appraise the quote of the request, blocks while fetching the collateral
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "verify_request",
+            skip_all,
+            fields(
+                policy_id = request.policy_id.as_deref().unwrap_or(DEFAULT_POLICY_ID),
+                quote_hash,
+                mrenclave,
+                result,
+                error
+            )
+        )
+    )]
+    pub fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, SGXError> {
+        telemetry::record_quote(&request.quote);
+        let mut verification = None;
+        let result = self.verify_request(request, &mut verification);
+        #[cfg(feature = "audit")]
+        let result = self.audit(request, verification, &result).and(result);
+        let outcome = match &result {
+            Ok(response) => {
+                telemetry::record("result", &response.tcb_status);
+                "accepted"
+            }
+            Err(e) => {
+                telemetry::record("result", "error");
+                telemetry::record_error(e);
+                if e.kind() == SGXErrorKind::Rejected {
+                    "rejected"
+                } else {
+                    "failed"
+                }
+            }
+        };
+        instrument::verify_request(outcome);
+        result
+    }
+
+    #[cfg(feature = "audit")]
+    fn audit(
+        &self,
+        request: &VerifyRequest,
+        verification: Option<DcapVerification>,
+        result: &Result<VerifyResponse, SGXError>,
+    ) -> Result<(), SGXError> {
+        let Some(sink) = &self.audit_sink else {
+            return Ok(());
+        };
+        let policy_id = request.policy_id.as_deref().unwrap_or(DEFAULT_POLICY_ID);
+        let verification = verification
+            .map(|verification| (verification.result, verification.collateral_versions));
+        sink.record(&AuditEntry::of(
+            &request.quote,
+            policy_id,
+            verification,
+            result,
+        ))
+    }
+
+    /// Verification of the quote is stored in `verification` for the audit log
+    fn verify_request(
+        &self,
+        request: &VerifyRequest,
+        verification: &mut Option<DcapVerification>,
+    ) -> Result<VerifyResponse, SGXError> {
+        let policy_id = request.policy_id.as_deref().unwrap_or(DEFAULT_POLICY_ID);
+        let policy = self
+            .policies
+            .get(policy_id)
+            .ok_or_else(|| rejected(&format!("unknown policy {}", policy_id)))?;
+
+        let quote = SGXQuote::try_from(request.quote.as_slice())?;
+        if !request.nonce.is_empty() {
+            self.nonces.consume(&request.nonce)?;
+            if !quote.report_data().starts_with(&request.nonce) {
+                return Err(SGXError::ReportDataMismatch);
+            }
+        }
+
+        let verification = verification.insert(self.verifier.verify(&quote)?);
+        if verification.collateral_expired {
+            return Err(SGXError::CollateralExpired);
+        }
+        policy.appraise(&quote, verification.result)?;
+        debug!(
+            "Quote of {} accepted by policy {}: {:?}",
+            quote.mrenclave(),
+            policy_id,
+            verification.result
+        );
+
+        let claims = AttestationClaims::from_quote(&quote, verification.result)?
+            .with_advisory_ids(verification.advisory_ids.clone())
+            .with_nonce(request.nonce.clone());
+        Ok(VerifyResponse {
+            token: self.issuer.issue_jwt(&claims)?,
+            tcb_status: verification.result.tcb_status().to_string(),
+        })
+    }
+
+    /// Metrics in the Prometheus text format, `None` without [`with_metrics`](Self::with_metrics)
+    pub fn metrics(&self) -> Option<String> {
+        self.metrics.as_ref().map(|handle| {
+            handle.run_upkeep();
+            handle.render()
+        })
+    }
+
+    /// How long an issued nonce may be used
+    pub fn nonce_ttl(&self) -> Duration {
+        self.nonces.ttl()
+    }
+
+    /// HTTP routes of the service, see [`router`] to share the service with other frontends
+    pub fn router(self) -> Router {
+        router(Arc::new(self))
+    }
+}
+
+/// HTTP routes of the shared service
+pub fn router(service: Arc<VerificationService>) -> Router {
+    Router::new()
+        .route("/verify", post(verify))
+        .route("/nonce", get(nonce))
+        .route("/health", get(|| async { "OK" }))
+        .route("/metrics", get(metrics))
+        .route("/.well-known/jwks.json", get(jwks))
+        .with_state(service)
+}
+
+type Service = State<Arc<VerificationService>>;
+
+async fn verify(
+    State(service): Service,
+    Json(request): Json<VerifyRequest>,
+) -> Result<Json<VerifyResponse>, ErrorResponse> {
+    blocking(move || service.verify(&request)).await
+}
+
+async fn nonce(State(service): Service) -> Result<Json<NonceResponse>, ErrorResponse> {
+    let nonce = service.issue_nonce().map_err(ErrorResponse)?;
+    Ok(Json(NonceResponse {
+        nonce,
+        expires_in: service.nonce_ttl().as_secs(),
+    }))
+}
+
+async fn metrics(State(service): Service) -> Result<String, StatusCode> {
+    service.metrics().ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn jwks(State(service): Service) -> Json<Jwks> {
+    Json(Jwks {
+        keys: vec![service.issuer.jwk()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use p256::ecdsa::SigningKey;
+
+    use super::*;
+    use crate::dcap::tests::Synthetic;
+    use crate::eat::EatVerifier;
+    use crate::http::NONCE_SIZE;
+    use crate::pccs::tests::StubPccs;
+    use crate::pccs::PccsClient;
+    use crate::{SGXMeasurement, SGXQuoteVerifyResult};
+
+    fn json<T: serde::de::DeserializeOwned>(response: ureq::Response) -> T {
+        serde_json::from_str(&response.into_string().unwrap()).unwrap()
+    }
+
+    /// Value of `series` in the Prometheus text format
+    fn sample(metrics: &str, series: &str) -> u64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verify_with_stub_pccs() {
+        let synthetic = Synthetic::new(5, false);
+        let pccs = StubPccs::start(synthetic.collateral.clone());
+        let verifier = DcapVerifier::new(PccsClient::new(&pccs.url))
+            .with_trusted_root_pem(&synthetic.root_pem)
+            .unwrap();
+        let issuer = EatIssuer::new(SigningKey::from_slice(&[7u8; 32]).unwrap());
+        // The recorder is global, other tests may add to the counters
+        let handle = PrometheusBuilder::new().install_recorder().unwrap();
+        let service = VerificationService::new(verifier, issuer)
+            .with_metrics(handle)
+            .with_policy(DEFAULT_POLICY_ID, AppraisalPolicy::default())
+            .with_policy(
+                "other-signer",
+                AppraisalPolicy {
+                    mrsigner: vec![SGXMeasurement::new([0x55; 32])],
+                    ..Default::default()
+                },
+            );
+        #[cfg(feature = "audit")]
+        let audit_dir = tempfile::tempdir().unwrap();
+        #[cfg(feature = "audit")]
+        let service = service.with_audit_sink(
+            crate::audit::FileAuditSink::open(audit_dir.path().join("audit.jsonl")).unwrap(),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, service.router()).await });
+
+        tokio::task::spawn_blocking(move || {
+            let agent = ureq::agent();
+            let get = |path: &str| agent.get(&format!("{}{}", url, path)).call().unwrap();
+            // Response or the HTTP status of the error
+            let post = |request: &VerifyRequest| -> Result<VerifyResponse, u16> {
+                agent
+                    .post(&format!("{}/verify", url))
+                    .set("Content-Type", "application/json")
+                    .send_string(&serde_json::to_string(request).unwrap())
+                    .map(json)
+                    .map_err(|e| match e {
+                        ureq::Error::Status(status, _) => status,
+                        e => panic!("{}", e),
+                    })
+            };
+
+            assert_eq!(get("/health").into_string().unwrap(), "OK");
+            let jwks: Jwks = json(get("/.well-known/jwks.json"));
+            let nonce: NonceResponse = json(get("/nonce"));
+            let mut report_data = [0u8; 64];
+            report_data[..NONCE_SIZE].copy_from_slice(&nonce.nonce);
+
+            let request = VerifyRequest {
+                quote: synthetic.quote(report_data).to_vec(),
+                nonce: nonce.nonce.clone(),
+                policy_id: None,
+            };
+            let response = post(&request).unwrap();
+            assert_eq!(response.tcb_status, "UpToDate");
+            let token = EatVerifier::new(&jwks)
+                .unwrap()
+                .verify_jwt(&response.token)
+                .unwrap();
+            assert_eq!(token.tcb_status(), SGXQuoteVerifyResult::Ok);
+            assert_eq!(token.nonce(), nonce.nonce);
+
+            // Nonces are single-use
+            assert_eq!(post(&request).err(), Some(403));
+            let request = VerifyRequest {
+                nonce: Vec::new(),
+                policy_id: Some("other-signer".into()),
+                ..request
+            };
+            assert_eq!(post(&request).err(), Some(403));
+            let request = VerifyRequest {
+                quote: vec![0u8; 16],
+                policy_id: None,
+                ..request
+            };
+            assert_eq!(post(&request).err(), Some(400));
+
+            let metrics = get("/metrics").into_string().unwrap();
+            let requests = |outcome: &str| {
+                let series = format!(
+                    "occlum_sgx_verify_requests_total{{outcome=\"{}\"}}",
+                    outcome
+                );
+                sample(&metrics, &series)
+            };
+            assert!(requests("accepted") >= 1);
+            assert!(requests("rejected") >= 2);
+            assert!(requests("failed") >= 1);
+            assert!(sample(&metrics, "occlum_sgx_nonces_issued_total") >= 1);
+        })
+        .await
+        .unwrap();
+
+        // Collateral is fetched once and cached
+        assert_eq!(pccs.requests.load(Ordering::SeqCst), 4);
+
+        #[cfg(feature = "audit")]
+        {
+            let log = std::fs::read_to_string(audit_dir.path().join("audit.jsonl")).unwrap();
+            let head = crate::audit::verify_audit_log(log.as_bytes()).unwrap();
+            assert_eq!(head.entries, 4);
+            let verdicts = log
+                .lines()
+                .map(|line| {
+                    line.split(r#""verdict":""#)
+                        .nth(1)
+                        .unwrap()
+                        .split('"')
+                        .next()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                verdicts,
+                [
+                    Some("accepted"),
+                    Some("rejected"),
+                    Some("rejected"),
+                    Some("failed")
+                ]
+            );
+            assert!(log.contains(r#""tcb_evaluation_data_number":16"#));
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::mem::size_of;
+
+use crate::constants::{SGX_ECDSA_PUBLIC_KEY_SIZE, SGX_ECDSA_SIGNATURE_SIZE};
+use crate::error::{message, SGXError};
+use crate::pck::SGXPckExtensions;
+use crate::types::{SGXQuoteHeader, SGXReportBody};
+
+/// Certification data type which contains the PEM-encoded PCK certificate chain
+pub const PCK_CERT_CHAIN_TYPE: u16 = 5;
+
+/// ECDSA signature section of the quote, which follows the quote header and the report body.
+///
+/// RFC: "Quote Format" - "ECDSA 256-bit Quote Signature Data Structure"
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SGXQuoteSignature {
+    /// Signature of the quote header and the report body made by the attestation key
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
+    pub signature: [u8; SGX_ECDSA_SIGNATURE_SIZE],
+    /// Raw P-256 public key of the attestation key (x || y)
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
+    pub attestation_key: [u8; SGX_ECDSA_PUBLIC_KEY_SIZE],
+    /// Report of the Quoting Enclave
+    pub qe_report: SGXReportBody,
+    /// Signature of the QE report made by the PCK
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
+    pub qe_report_signature: [u8; SGX_ECDSA_SIGNATURE_SIZE],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub qe_auth_data: Vec<u8>,
+    pub certification_data: SGXCertificationData,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SGXCertificationData {
+    /// See [`PCK_CERT_CHAIN_TYPE`]
+    pub cert_type: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::base64_bytes"))]
+    pub data: Vec<u8>,
+}
+
+impl SGXCertificationData {
+    /// PEM-encoded certificates (PCK, intermediate CA, root CA), if the data is a PCK certificate chain
+    pub fn pck_cert_chain(&self) -> Option<Vec<String>> {
+        if self.cert_type != PCK_CERT_CHAIN_TYPE {
+            return None;
+        }
+
+        const END: &str = "-----END CERTIFICATE-----";
+        let chain = std::str::from_utf8(&self.data).ok()?;
+        Some(
+            chain
+                .split_inclusive(END)
+                .map(|cert| cert.trim_matches(|c: char| c.is_whitespace() || c == '\0'))
+                .filter(|cert| cert.ends_with(END))
+                .map(|cert| format!("{}\n", cert))
+                .collect(),
+        )
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SGXError> {
+        let end = self.offset + len;
+        if self.buf.len() < end {
+            return Err(SGXError::BadQuoteLength {
+                min: end,
+                actual: self.buf.len(),
+            });
+        }
+        let slice = &self.buf[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SGXError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u16(&mut self) -> Result<u16, SGXError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SGXError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn report_body(&mut self) -> Result<SGXReportBody, SGXError> {
+        let slice = self.take(size_of::<SGXReportBody>())?;
+        Ok(unsafe { std::ptr::read_unaligned(slice.as_ptr() as *const SGXReportBody) })
+    }
+}
+
+impl SGXQuoteSignature {
+    /// SGX extension (FMSPC, TCB components, etc.) of the PCK certificate from the certification data
+    pub fn pck_extensions(&self) -> Result<SGXPckExtensions, SGXError> {
+        let chain = self.certification_data.pck_cert_chain().ok_or_else(|| {
+            SGXError::CertificateParseError(message("quote has no PCK certificate chain"))
+        })?;
+        let pck = chain.first().ok_or_else(|| {
+            SGXError::CertificateParseError(message("PCK certificate chain is empty"))
+        })?;
+        SGXPckExtensions::from_pem(pck)
+    }
+
+    /// Parse the signature section from the full quote buffer
+    pub(crate) fn parse(quote: &[u8]) -> Result<Self, SGXError> {
+        let mut reader = Reader {
+            buf: quote,
+            offset: size_of::<SGXQuoteHeader>() + size_of::<SGXReportBody>(),
+        };
+
+        let signature_data_len = reader.u32()? as usize;
+        let mut reader = Reader {
+            buf: reader.take(signature_data_len)?,
+            offset: 0,
+        };
+
+        let signature = reader.array()?;
+        let attestation_key = reader.array()?;
+        let qe_report = reader.report_body()?;
+        let qe_report_signature = reader.array()?;
+        let qe_auth_data_len = reader.u16()? as usize;
+        let qe_auth_data = reader.take(qe_auth_data_len)?.to_vec();
+        let cert_type = reader.u16()?;
+        let cert_data_len = reader.u32()? as usize;
+        let data = reader.take(cert_data_len)?.to_vec();
+
+        Ok(Self {
+            signature,
+            attestation_key,
+            qe_report,
+            qe_report_signature,
+            qe_auth_data,
+            certification_data: SGXCertificationData { cert_type, data },
+        })
+    }
+
+    /// Encode the signature section, including the leading signature data length
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.signature);
+        data.extend_from_slice(&self.attestation_key);
+        data.extend_from_slice(self.qe_report.as_bytes());
+        data.extend_from_slice(&self.qe_report_signature);
+        data.extend_from_slice(&(self.qe_auth_data.len() as u16).to_le_bytes());
+        data.extend_from_slice(&self.qe_auth_data);
+        data.extend_from_slice(&self.certification_data.cert_type.to_le_bytes());
+        data.extend_from_slice(&(self.certification_data.data.len() as u32).to_le_bytes());
+        data.extend_from_slice(&self.certification_data.data);
+
+        let mut buf = (data.len() as u32).to_le_bytes().to_vec();
+        buf.append(&mut data);
+        buf
+    }
+}
@@ -0,0 +1,262 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::constants::{SGX_RSA3072_KEY_SIZE, SGX_SIGSTRUCT_SIZE};
+use crate::der::{
+    pem_to_der, Der, TAG_BIT_STRING, TAG_INTEGER, TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE,
+};
+use crate::error::{message, SGXError};
+use crate::types::{SGXAttributes, SGXExtProdId, SGXFamilyId, SGXMeasurement};
+
+/// OID 1.2.840.113549.1.1.1 rsaEncryption
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+
+const SIGSTRUCT_HEADER: [u8; 12] = [
+    0x06, 0x00, 0x00, 0x00, 0xE1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+];
+const SIGSTRUCT_HEADER2: [u8; 16] = [
+    0x01, 0x01, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+];
+
+fn key_error(msg: &str) -> SGXError {
+    SGXError::SigningKeyParseError(message(msg))
+}
+
+/// Big-endian modulus of the PEM-encoded RSA key (public or private, PKCS#1 or PKCS#8)
+fn rsa_modulus(pem: &str) -> Result<Vec<u8>, SGXError> {
+    let (label, der) = pem_to_der(pem)?;
+    let key = Der(&der).expect(TAG_SEQUENCE)?;
+
+    let rsa_key = |key: &[u8], private: bool| -> Result<Vec<u8>, SGXError> {
+        let mut key = Der(key);
+        if private {
+            key.expect(TAG_INTEGER)?;
+        }
+        Ok(key.expect(TAG_INTEGER)?.to_vec())
+    };
+    let check_algorithm = |key: &mut Der| -> Result<(), SGXError> {
+        let mut algorithm = Der(key.expect(TAG_SEQUENCE)?);
+        if algorithm.expect(TAG_OID)? != RSA_ENCRYPTION_OID {
+            return Err(key_error("not an RSA key"));
+        }
+        Ok(())
+    };
+
+    let modulus = match label.as_str() {
+        "RSA PUBLIC KEY" => rsa_key(key, false)?,
+        "RSA PRIVATE KEY" => rsa_key(key, true)?,
+        "PUBLIC KEY" => {
+            let mut key = Der(key);
+            check_algorithm(&mut key)?;
+            let bits = key.expect(TAG_BIT_STRING)?;
+            let pkcs1 = Der(bits.get(1..).unwrap_or_default()).expect(TAG_SEQUENCE)?;
+            rsa_key(pkcs1, false)?
+        }
+        "PRIVATE KEY" => {
+            let mut key = Der(key);
+            key.expect(TAG_INTEGER)?;
+            check_algorithm(&mut key)?;
+            let pkcs1 = Der(key.expect(TAG_OCTET_STRING)?).expect(TAG_SEQUENCE)?;
+            rsa_key(pkcs1, true)?
+        }
+        label => return Err(key_error(&format!("unsupported PEM block {}", label))),
+    };
+
+    let modulus = match modulus.split_first() {
+        Some((0, modulus)) => modulus.to_vec(),
+        _ => modulus,
+    };
+    if modulus.len() != SGX_RSA3072_KEY_SIZE {
+        return Err(key_error(&format!(
+            "RSA-3072 key required, received {} bits",
+            modulus.len() * 8
+        )));
+    }
+    Ok(modulus)
+}
+
+impl SGXMeasurement {
+    /// MRSIGNER for the little-endian modulus of the RSA-3072 enclave signing key
+    pub fn mrsigner_from_modulus(modulus: &[u8; SGX_RSA3072_KEY_SIZE]) -> Self {
+        Self::new(Sha256::digest(modulus).into())
+    }
+
+    /// MRSIGNER for the PEM-encoded RSA-3072 enclave signing key.
+    ///
+    /// Accepts public (`PUBLIC KEY`, `RSA PUBLIC KEY`) and private (`PRIVATE KEY`,
+    /// `RSA PRIVATE KEY`) keys.
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// let pem = std::fs::read_to_string("enclave_signing_key.pem")?;
+    /// let mrsigner = SGXMeasurement::mrsigner_from_public_key(&pem)?;
+    /// assert_eq!(quote.mrsigner(), mrsigner);
+    /// ```
+    pub fn mrsigner_from_public_key(pem: &str) -> Result<Self, SGXError> {
+        let mut modulus: [u8; SGX_RSA3072_KEY_SIZE] = rsa_modulus(pem)?.try_into().unwrap();
+        modulus.reverse();
+        Ok(Self::mrsigner_from_modulus(&modulus))
+    }
+}
+
+/// Enclave signature structure (SIGSTRUCT), produced by `sgx_sign` and embedded into the signed
+/// enclave image.
+///
+/// Multi-byte values (modulus, signature) are little-endian, as stored in SIGSTRUCT.
+#[derive(Debug, Clone)]
+pub struct SGXSigStruct {
+    /// `0x8086` for Intel enclaves, `0` otherwise
+    pub vendor: u32,
+    /// Signing date, BCD `yyyymmdd`
+    pub date: u32,
+    pub hw_version: u32,
+    pub modulus: [u8; SGX_RSA3072_KEY_SIZE],
+    pub exponent: u32,
+    pub signature: [u8; SGX_RSA3072_KEY_SIZE],
+    pub misc_select: u32,
+    pub misc_mask: u32,
+    pub isv_family_id: SGXFamilyId,
+    pub attributes: SGXAttributes,
+    pub attribute_mask: SGXAttributes,
+    /// Expected MRENCLAVE
+    pub enclave_hash: SGXMeasurement,
+    pub isv_ext_prod_id: SGXExtProdId,
+    pub isv_prod_id: u16,
+    pub isv_svn: u16,
+}
+
+pub(crate) fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+pub(crate) fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn u64_at(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+pub(crate) fn array_at<const N: usize>(buf: &[u8], offset: usize) -> [u8; N] {
+    buf[offset..offset + N].try_into().unwrap()
+}
+
+impl SGXSigStruct {
+    /// Parse SIGSTRUCT, e.g. the content of a `.sig` file
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, SGXError> {
+        if buf.len() < SGX_SIGSTRUCT_SIZE {
+            return Err(SGXError::SigStructParseError(message(format!(
+                "Bad length, required {} bytes, received: {}",
+                SGX_SIGSTRUCT_SIZE,
+                buf.len()
+            ))));
+        }
+        if buf[..12] != SIGSTRUCT_HEADER || buf[24..40] != SIGSTRUCT_HEADER2 {
+            return Err(SGXError::SigStructParseError(message("bad header")));
+        }
+
+        Ok(Self {
+            vendor: u32_at(buf, 16),
+            date: u32_at(buf, 20),
+            hw_version: u32_at(buf, 40),
+            modulus: array_at(buf, 128),
+            exponent: u32_at(buf, 512),
+            signature: array_at(buf, 516),
+            misc_select: u32_at(buf, 900),
+            misc_mask: u32_at(buf, 904),
+            isv_family_id: SGXFamilyId(u64_at(buf, 912), u64_at(buf, 920)),
+            attributes: SGXAttributes {
+                flags: u64_at(buf, 928),
+                xfrm: u64_at(buf, 936),
+            },
+            attribute_mask: SGXAttributes {
+                flags: u64_at(buf, 944),
+                xfrm: u64_at(buf, 952),
+            },
+            enclave_hash: SGXMeasurement::new(array_at(buf, 960)),
+            isv_ext_prod_id: SGXExtProdId(u64_at(buf, 1008), u64_at(buf, 1016)),
+            isv_prod_id: u16_at(buf, 1024),
+            isv_svn: u16_at(buf, 1026),
+        })
+    }
+
+    /// Find and parse SIGSTRUCT in the signed enclave image (e.g. `libocclum-libos.signed.so`)
+    pub fn from_enclave(image: &[u8]) -> Result<Self, SGXError> {
+        image
+            .windows(SIGSTRUCT_HEADER.len())
+            .enumerate()
+            .filter(|(_, window)| *window == SIGSTRUCT_HEADER)
+            .find_map(|(offset, _)| Self::from_bytes(&image[offset..]).ok())
+            .ok_or_else(|| SGXError::SigStructParseError(message("SIGSTRUCT not found")))
+    }
+
+    /// Read SIGSTRUCT from a `.sig` file or a signed enclave image
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SGXError> {
+        let buf = std::fs::read(path).map_err(SGXError::IoError)?;
+        if buf.len() == SGX_SIGSTRUCT_SIZE {
+            Self::from_bytes(&buf)
+        } else {
+            Self::from_enclave(&buf)
+        }
+    }
+
+    /// MRSIGNER of the enclave, the hash of the signing key modulus
+    pub fn mrsigner(&self) -> SGXMeasurement {
+        SGXMeasurement::mrsigner_from_modulus(&self.modulus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNING_KEY: &str = include_str!("../tests/fixtures/signing_key.pem");
+    const PUBLIC_KEY: &str = include_str!("../tests/fixtures/signing_key.pub.pem");
+    /// `openssl rsa -modulus` reversed and hashed with `sha256sum`
+    const MRSIGNER: &str = "dcbcac3cbf8986e252eba8c576265eb51b5792617d9143ad04b550e58e195cb4";
+
+    #[test]
+    fn mrsigner_from_public_key() {
+        let expected = SGXMeasurement::from_hex(MRSIGNER).unwrap();
+        assert_eq!(
+            SGXMeasurement::mrsigner_from_public_key(SIGNING_KEY).unwrap(),
+            expected
+        );
+        assert_eq!(
+            SGXMeasurement::mrsigner_from_public_key(PUBLIC_KEY).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn sigstruct_from_enclave() {
+        let mut modulus: [u8; SGX_RSA3072_KEY_SIZE] =
+            rsa_modulus(PUBLIC_KEY).unwrap().try_into().unwrap();
+        modulus.reverse();
+
+        let mut sigstruct = vec![0u8; SGX_SIGSTRUCT_SIZE];
+        sigstruct[..12].copy_from_slice(&SIGSTRUCT_HEADER);
+        sigstruct[24..40].copy_from_slice(&SIGSTRUCT_HEADER2);
+        sigstruct[128..512].copy_from_slice(&modulus);
+        sigstruct[512..516].copy_from_slice(&3u32.to_le_bytes());
+        sigstruct[960..992].copy_from_slice(&[0xAB; 32]);
+        sigstruct[1024..1026].copy_from_slice(&4000u16.to_le_bytes());
+        sigstruct[1026..1028].copy_from_slice(&5000u16.to_le_bytes());
+
+        let mut image = b"\x7fELF some enclave code".to_vec();
+        image.extend_from_slice(&SIGSTRUCT_HEADER);
+        image.extend_from_slice(&sigstruct);
+        image.extend_from_slice(b"trailing data");
+
+        let sigstruct = SGXSigStruct::from_enclave(&image).unwrap();
+        assert_eq!(
+            sigstruct.mrsigner(),
+            SGXMeasurement::from_hex(MRSIGNER).unwrap()
+        );
+        assert_eq!(*sigstruct.enclave_hash, [0xAB; 32]);
+        assert_eq!(sigstruct.exponent, 3);
+        assert_eq!(sigstruct.isv_prod_id, 4000);
+        assert_eq!(sigstruct.isv_svn, 5000);
+    }
+}
@@ -0,0 +1,260 @@
+//! Log events and [tracing] spans
+//!
+//! With the `tracing` feature, the log macros of the crate emit `tracing` events, and quote
+//! generation, verification, collateral fetches and appraisals run in spans:
+//!
+//! | Span               | Fields                                                              |
+//! |--------------------|---------------------------------------------------------------------|
+//! | `generate_quote`   | `quote_hash`, `mrenclave`                                           |
+//! | `verify_quote`     | `backend`, `quote_hash`, `mrenclave`, `fmspc`, `result`, `error`    |
+//! | `collateral`       | `fmspc`, `ca`, `cache`: `hit` or `miss`                             |
+//! | `fetch_collateral` | `fmspc`, `ca`                                                       |
+//! | `appraise`         | `mrenclave`, `result`, `verdict`: `accepted` or `rejected`, `error` |
+//! | `verify_request`   | `policy_id`, `quote_hash`, `mrenclave`, `result`, `error`           |
+//! | `release_secrets`  | `names`, `quote_hash`, `mrenclave`, `result`, `error`               |
+//! | `handshake`        | `role`, `quote_hash`, `mrenclave` and `result` of the peer          |
+//!
+//! `quote_hash` is the hex SHA-256 of the raw quote and `result` the TCB status name or `error`.
+//! `fmspc` is recorded by the DCAP verifier only. Without a `tracing` subscriber, the events are
+//! forwarded to [log]. Without the feature, the macros are the [log] ones and the spans are not
+//! created.
+//!
+//! [tracing]: https://docs.rs/tracing
+//! [log]: https://docs.rs/log
+use crate::{SGXError, SGXQuoteVerifyResult};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "tracing")] {
+        #[allow(unused_imports)]
+        pub(crate) use tracing::{debug, info, trace, warn};
+
+        use sha2::{Digest, Sha256};
+        use tracing::field::display;
+        use tracing::Span;
+
+        use crate::SGXQuote;
+
+        /// Record the hash and the MRENCLAVE of the raw quote in the current span
+        pub(crate) fn record_quote(quote: &[u8]) {
+            let span = Span::current();
+            if span.is_disabled() {
+                return;
+            }
+            span.record("quote_hash", hex::encode(Sha256::digest(quote)));
+            if let Ok(quote) = SGXQuote::from_slice(quote) {
+                span.record("mrenclave", display(quote.mrenclave()));
+            }
+        }
+
+        /// Record the verification result in the current span
+        pub(crate) fn record_result(result: Result<SGXQuoteVerifyResult, &SGXError>) {
+            let span = Span::current();
+            span.record("result", result.map_or("error", |result| result.tcb_status()));
+            if let Err(e) = result {
+                record_error(e);
+            }
+        }
+
+        /// Record the error in the current span
+        pub(crate) fn record_error(error: &SGXError) {
+            Span::current().record("error", display(error));
+        }
+
+        /// Record a field of the current span
+        pub(crate) fn record(field: &'static str, value: &str) {
+            Span::current().record(field, value);
+        }
+    } else {
+        #[allow(unused_imports)]
+        pub(crate) use log::{debug, info, trace, warn};
+
+        pub(crate) fn record_quote(_: &[u8]) {}
+
+        pub(crate) fn record_result(_: Result<SGXQuoteVerifyResult, &SGXError>) {}
+
+        pub(crate) fn record_error(_: &SGXError) {}
+
+        pub(crate) fn record(_: &'static str, _: &str) {}
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use sha2::{Digest, Sha256};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+    use tracing_core::span::Current;
+
+    use crate::generator::generate_with_retry;
+    use crate::{
+        AppraisalPolicy, ReportData, RetryPolicy, SGXError, SGXQuote, SGXQuoteVerifyResult,
+    };
+
+    type Fields = HashMap<&'static str, String>;
+
+    struct FieldVisitor<'a>(&'a mut Fields);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.insert(field.name(), format!("{:?}", value));
+        }
+    }
+
+    /// Metadata and recorded fields of the spans by id, and the stack of entered spans
+    #[derive(Clone, Default)]
+    struct TestSubscriber {
+        next_id: Arc<AtomicU64>,
+        spans: Arc<Mutex<HashMap<u64, (&'static Metadata<'static>, Fields)>>>,
+        entered: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl TestSubscriber {
+        fn spans(&self, name: &str) -> Vec<Fields> {
+            let spans = self.spans.lock().unwrap();
+            let mut ids = spans.keys().copied().collect::<Vec<_>>();
+            ids.sort();
+            ids.iter()
+                .filter(|id| spans[id].0.name() == name)
+                .map(|id| spans[id].1.clone())
+                .collect()
+        }
+    }
+
+    impl Subscriber for TestSubscriber {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+            let mut fields = Fields::new();
+            span.record(&mut FieldVisitor(&mut fields));
+            let metadata = span.metadata();
+            self.spans.lock().unwrap().insert(id, (metadata, fields));
+            Id::from_u64(id)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            let fields = &mut spans.get_mut(&span.into_u64()).unwrap().1;
+            values.record(&mut FieldVisitor(fields));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> Current {
+            match self.entered.lock().unwrap().last() {
+                Some(&id) => Current::new(Id::from_u64(id), self.spans.lock().unwrap()[&id].0),
+                None => Current::none(),
+            }
+        }
+    }
+
+    #[test]
+    fn record_spans() {
+        let raw = include_bytes!("../tests/fixtures/quote.raw");
+        let quote = SGXQuote::from_slice(raw).unwrap();
+        let subscriber = TestSubscriber::default();
+        tracing::subscriber::with_default(subscriber.clone(), || {
+            let generator = |_: &ReportData| Ok(raw.to_vec());
+            generate_with_retry(&generator, &[0; 64], &RetryPolicy::none()).unwrap();
+            let generator = |_: &ReportData| Err(SGXError::ReportDataMismatch);
+            generate_with_retry(&generator, &[0; 64], &RetryPolicy::none()).unwrap_err();
+
+            let policy = AppraisalPolicy {
+                allow_debug: true,
+                ..Default::default()
+            };
+            policy.appraise(&quote, SGXQuoteVerifyResult::Ok).unwrap();
+            let policy = AppraisalPolicy {
+                allowed_results: vec![SGXQuoteVerifyResult::OutOfDate],
+                ..policy
+            };
+            policy
+                .appraise(&quote, SGXQuoteVerifyResult::Ok)
+                .unwrap_err();
+        });
+
+        let generated = subscriber.spans("generate_quote");
+        assert_eq!(generated.len(), 2);
+        assert_eq!(generated[0]["quote_hash"], hex::encode(Sha256::digest(raw)));
+        assert_eq!(generated[0]["mrenclave"], quote.mrenclave().to_string());
+        assert!(generated[1].is_empty());
+
+        let appraised = subscriber.spans("appraise");
+        assert_eq!(appraised.len(), 2);
+        assert_eq!(appraised[0]["mrenclave"], quote.mrenclave().to_string());
+        assert_eq!(
+            appraised[0]["result"],
+            SGXQuoteVerifyResult::Ok.tcb_status()
+        );
+        assert_eq!(appraised[0]["verdict"], "accepted");
+        assert_eq!(appraised[1]["verdict"], "rejected");
+        assert_eq!(
+            appraised[1]["error"],
+            SGXError::VerifyQuoteFailed(SGXQuoteVerifyResult::Ok).to_string()
+        );
+    }
+
+    #[cfg(feature = "pccs")]
+    #[test]
+    fn record_dcap_spans() {
+        use crate::dcap::tests::{Synthetic, FMSPC};
+        use crate::dcap::DcapVerifier;
+        use crate::pccs::tests::StubPccs;
+        use crate::pccs::PccsClient;
+
+        let synthetic = Synthetic::new(5, false);
+        let pccs = StubPccs::start(synthetic.collateral.clone());
+        let verifier = DcapVerifier::new(PccsClient::new(&pccs.url))
+            .with_trusted_root_pem(&synthetic.root_pem)
+            .unwrap();
+        let quote = synthetic.quote([0; 64]);
+        let subscriber = TestSubscriber::default();
+        tracing::subscriber::with_default(subscriber.clone(), || {
+            verifier.verify(&quote).unwrap();
+            verifier.verify(&quote).unwrap();
+        });
+
+        let fmspc = hex::encode(FMSPC);
+        let verified = subscriber.spans("verify_quote");
+        assert_eq!(verified.len(), 2);
+        for span in &verified {
+            assert_eq!(span["backend"], "dcap");
+            assert_eq!(span["fmspc"], fmspc);
+            assert_eq!(span["result"], SGXQuoteVerifyResult::Ok.tcb_status());
+            assert_eq!(span["mrenclave"], quote.mrenclave().to_string());
+        }
+
+        let collateral = subscriber.spans("collateral");
+        assert_eq!(collateral.len(), 2);
+        assert_eq!(collateral[0]["fmspc"], fmspc);
+        assert_eq!(collateral[0]["cache"], "miss");
+        assert_eq!(collateral[1]["cache"], "hit");
+
+        // Fetched on the miss only
+        let fetched = subscriber.spans("fetch_collateral");
+        assert_eq!(fetched.len(), 1);
+        assert_eq!(fetched[0]["fmspc"], fmspc);
+        assert_eq!(fetched[0]["ca"], "platform");
+    }
+}
@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::generator::QuoteGenerator;
+use crate::telemetry::{trace, warn};
+use crate::{ReportData, SGXError};
+
+/// Default mount point of the configfs-tsm reports
+pub const TSM_REPORT_PATH: &str = "/sys/kernel/config/tsm/report";
+
+/// Output of the TSM report request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TsmReportOutput {
+    /// Quote, the format is defined by the provider
+    pub outblob: Vec<u8>,
+    /// TSM provider which produced the quote, e.g. `tdx_guest`
+    pub provider: String,
+    /// Value of the generation counter the outblob corresponds to
+    pub generation: u64,
+}
+
+/// Linux configfs-tsm backend, produces quotes with the unified `/sys/kernel/config/tsm/report`
+/// interface: the report data is written to `inblob`, then the quote is read from `outblob`.
+///
+/// The `generation` counter is checked to detect a concurrent write to the same report entry, in
+/// that case [`SGXError::TsmGenerationMismatch`] is returned and the request may be retried.
+///
+/// No upstream kernel registers a configfs-tsm provider for SGX enclaves (the existing ones, e.g.
+/// `tdx_guest` and `sev_guest`, produce VM reports), so the name of the provider which produces
+/// SGX quotes, e.g. one of a LibOS, must be given. As a [`QuoteGenerator`], the outblobs of other
+/// providers fail with [`SGXError::UnsupportedTsmProvider`].
+///
+/// @see https://www.kernel.org/doc/Documentation/ABI/testing/configfs-tsm
+///
+/// # Example
+/// ```rust ignore
+/// set_quote_generator(TsmReport::new(TSM_REPORT_PATH, "my_sgx_provider")).unwrap();
+/// let quote = SGXQuote::from_report_data(&report_data)?;
+/// ```
+pub struct TsmReport {
+    root: PathBuf,
+    entry: Option<PathBuf>,
+    next_entry: AtomicUsize,
+    provider: String,
+}
+
+impl TsmReport {
+    /// Backend which creates a report entry in `root` (e.g. [`TSM_REPORT_PATH`]) for every request
+    /// and removes it afterwards, `provider` is the name of the SGX provider
+    pub fn new<P: AsRef<Path>, S: Into<String>>(root: P, provider: S) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            entry: None,
+            next_entry: AtomicUsize::new(0),
+            provider: provider.into(),
+        }
+    }
+
+    /// Backend which uses an existing report entry, e.g. `/sys/kernel/config/tsm/report/app`
+    pub fn with_entry<P: AsRef<Path>, S: Into<String>>(entry: P, provider: S) -> Self {
+        let entry = entry.as_ref().to_path_buf();
+        Self {
+            root: entry.parent().map(Path::to_path_buf).unwrap_or_default(),
+            entry: Some(entry),
+            next_entry: AtomicUsize::new(0),
+            provider: provider.into(),
+        }
+    }
+
+    /// Returns `true` if the kernel exposes configfs-tsm reports
+    pub fn is_available(&self) -> bool {
+        self.entry.as_ref().unwrap_or(&self.root).is_dir()
+    }
+
+    fn read_generation(entry: &Path) -> Result<u64, SGXError> {
+        let generation = fs::read_to_string(entry.join("generation"))?;
+        generation.trim().parse().map_err(|_| {
+            SGXError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("bad TSM generation {:?}", generation),
+            ))
+        })
+    }
+
+    fn report_in(entry: &Path, inblob: &[u8]) -> Result<TsmReportOutput, SGXError> {
+        let before = Self::read_generation(entry)?;
+        trace!("Write TSM inblob, generation {}", before);
+        fs::write(entry.join("inblob"), inblob)?;
+        let outblob = fs::read(entry.join("outblob"))?;
+        let provider = fs::read_to_string(entry.join("provider"))?
+            .trim()
+            .to_string();
+        let generation = Self::read_generation(entry)?;
+
+        // The only write since `before` must be our inblob
+        if generation != before + 1 {
+            warn!(
+                "TSM report {} was written concurrently, generation {} instead of {}",
+                entry.display(),
+                generation,
+                before + 1
+            );
+            return Err(SGXError::TsmGenerationMismatch {
+                expected: before + 1,
+                actual: generation,
+            });
+        }
+
+        Ok(TsmReportOutput {
+            outblob,
+            provider,
+            generation,
+        })
+    }
+
+    /// Request a report for `inblob` (up to 64 bytes)
+    pub fn report(&self, inblob: &[u8]) -> Result<TsmReportOutput, SGXError> {
+        if let Some(entry) = &self.entry {
+            return Self::report_in(entry, inblob);
+        }
+
+        let entry = self.root.join(format!(
+            "occlum-sgx-{}-{}",
+            std::process::id(),
+            self.next_entry.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir(&entry)?;
+        let result = Self::report_in(&entry, inblob);
+        if let Err(e) = fs::remove_dir(&entry) {
+            warn!("Failed to remove TSM report {}: {}", entry.display(), e);
+        }
+        result
+    }
+}
+
+impl QuoteGenerator for TsmReport {
+    fn generate_quote(&self, report_data: &ReportData) -> Result<Vec<u8>, SGXError> {
+        let output = self.report(report_data)?;
+        if output.provider != self.provider {
+            return Err(SGXError::UnsupportedTsmProvider(output.provider));
+        }
+        Ok(output.outblob)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+    use std::thread;
+
+    use super::*;
+
+    /// Fake report entry, `outblob` is a FIFO served by a thread acting as the kernel: it bumps
+    /// the generation by `writes` and returns the quote.
+    fn fake_entry(entry: &Path, writes: u64) -> thread::JoinHandle<Vec<u8>> {
+        fs::create_dir(entry).unwrap();
+        fs::write(entry.join("generation"), "3\n").unwrap();
+        fs::write(entry.join("provider"), "fake_guest\n").unwrap();
+        let outblob = entry.join("outblob");
+        let path = CString::new(outblob.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+
+        let entry = entry.to_path_buf();
+        thread::spawn(move || {
+            // Blocks until the backend opens outblob, i.e. after it wrote inblob
+            let mut outblob = fs::OpenOptions::new().write(true).open(outblob).unwrap();
+            let inblob = fs::read(entry.join("inblob")).unwrap();
+            fs::write(entry.join("generation"), format!("{}\n", 3 + writes)).unwrap();
+            outblob
+                .write_all(include_bytes!("../tests/fixtures/quote.raw"))
+                .unwrap();
+            inblob
+        })
+    }
+
+    #[test]
+    fn report() {
+        let root = tempfile::tempdir().unwrap();
+        let entry = root.path().join("app");
+        let kernel = fake_entry(&entry, 1);
+
+        let tsm = TsmReport::with_entry(&entry, "fake_guest");
+        assert!(tsm.is_available());
+        let output = tsm.report(&[5u8; 64]).unwrap();
+        assert_eq!(kernel.join().unwrap(), [5u8; 64]);
+        assert_eq!(output.provider, "fake_guest");
+        assert_eq!(output.generation, 4);
+        assert_eq!(
+            output.outblob,
+            include_bytes!("../tests/fixtures/quote.raw")
+        );
+    }
+
+    #[test]
+    fn sgx_provider_required() {
+        let root = tempfile::tempdir().unwrap();
+        let entry = root.path().join("app");
+        let kernel = fake_entry(&entry, 1);
+        let e = TsmReport::with_entry(&entry, "tdx_guest")
+            .generate_quote(&[5u8; 64])
+            .unwrap_err();
+        kernel.join().unwrap();
+        assert!(
+            matches!(e, SGXError::UnsupportedTsmProvider(ref provider) if provider == "fake_guest")
+        );
+
+        fs::remove_dir_all(&entry).unwrap();
+        let kernel = fake_entry(&entry, 1);
+        let quote = TsmReport::with_entry(&entry, "fake_guest")
+            .generate_quote(&[5u8; 64])
+            .unwrap();
+        kernel.join().unwrap();
+        assert_eq!(quote, include_bytes!("../tests/fixtures/quote.raw"));
+    }
+
+    #[test]
+    fn concurrent_write_detected() {
+        let root = tempfile::tempdir().unwrap();
+        let entry = root.path().join("app");
+        let kernel = fake_entry(&entry, 2);
+
+        let e = TsmReport::with_entry(&entry, "fake_guest")
+            .report(&[5u8; 64])
+            .unwrap_err();
+        kernel.join().unwrap();
+        assert!(matches!(
+            e,
+            SGXError::TsmGenerationMismatch {
+                expected: 4,
+                actual: 5
+            }
+        ));
+        assert!(e.is_retryable());
+    }
+}
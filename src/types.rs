@@ -7,7 +7,7 @@ use crate::constants::{
     SGX_REPORT_BODY_RESERVED2_BYTES, SGX_REPORT_BODY_RESERVED3_BYTES,
     SGX_REPORT_BODY_RESERVED4_BYTES, SGX_REPORT_DATA_SIZE,
 };
-use crate::error::SGXError;
+use crate::error::{message, SGXError};
 
 pub type ReportData = [u8; SGX_REPORT_DATA_SIZE];
 
@@ -46,22 +46,38 @@ impl Debug for SGXReportData {
 }
 
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SGXQuoteHeader {
     pub version: u16,
     pub att_key_type: u16,
     pub att_key_data_0: u32,
     pub qe_svn: u16,
     pub pce_svn: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
     pub vendor_id: [u8; 16],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
     pub user_data: [u8; 20],
 }
 
+impl SGXQuoteHeader {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { as_bytes(self) }
+    }
+}
+
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct SGXCpuSvn {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
     pub svn: [u8; SGX_CPUSVN_SIZE],
 }
 
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SGXAttributes {
     pub flags: u64,
     pub xfrm: u64,
@@ -82,13 +98,13 @@ impl SGXMeasurement {
 
     pub fn from_hex(s: &str) -> Result<Self, SGXError> {
         let measurement: SGXHash = hex::decode(s)
-            .map_err(|e| SGXError::SGXMeasurementParseError(e.to_string()))?
+            .map_err(|e| SGXError::SGXMeasurementParseError(e.into()))?
             .try_into()
             .map_err(|x: Vec<u8>| {
-                SGXError::SGXMeasurementParseError(format!(
+                SGXError::SGXMeasurementParseError(message(format!(
                     "Bad length, required 32 bytes, received: {}",
                     x.len()
-                ))
+                )))
             })?;
         Ok(Self { measurement })
     }
@@ -116,7 +132,7 @@ impl Debug for SGXMeasurement {
 
 #[repr(C)]
 #[derive(Clone, Copy)]
-pub struct SGXFamilyId(u64, u64);
+pub struct SGXFamilyId(pub(crate) u64, pub(crate) u64);
 
 impl Debug for SGXFamilyId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -129,7 +145,7 @@ impl Debug for SGXFamilyId {
 
 #[repr(C)]
 #[derive(Clone, Copy)]
-pub struct SGXExtProdId(u64, u64);
+pub struct SGXExtProdId(pub(crate) u64, pub(crate) u64);
 
 impl Debug for SGXExtProdId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -142,7 +158,7 @@ impl Debug for SGXExtProdId {
 
 #[repr(C)]
 #[derive(Clone, Copy)]
-pub struct SGXConfigId([u8; SGX_CONFIGID_SIZE]);
+pub struct SGXConfigId(pub(crate) [u8; SGX_CONFIGID_SIZE]);
 
 impl Debug for SGXConfigId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -151,26 +167,44 @@ impl Debug for SGXConfigId {
 }
 
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SGXReportBody {
     pub cpu_svn: SGXCpuSvn,
     pub misc_select: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
     pub reserved1: [u8; SGX_REPORT_BODY_RESERVED1_BYTES],
     pub isv_ext_prod_id: SGXExtProdId,
     pub attributes: SGXAttributes,
     pub mr_enclave: SGXMeasurement,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
     pub reserved2: [u8; SGX_REPORT_BODY_RESERVED2_BYTES],
     pub mr_signer: SGXMeasurement,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
     pub reserved3: [u8; SGX_REPORT_BODY_RESERVED3_BYTES],
     pub config_id: SGXConfigId,
     pub isv_prod_id: u16,
     pub isv_svn: u16,
     pub config_svn: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::hex_bytes"))]
     pub reserved4: [u8; SGX_REPORT_BODY_RESERVED4_BYTES],
     pub isv_family_id: SGXFamilyId,
     pub report_data: SGXReportData,
 }
 
-#[derive(Debug)]
+impl SGXReportBody {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { as_bytes(self) }
+    }
+}
+
+/// View of a `#[repr(C)]` structure without padding as raw bytes
+unsafe fn as_bytes<T>(value: &T) -> &[u8] {
+    std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SGXQuoteVerifyResult {
     Ok = 0x0000_0000,
     ///#  Not terminal
@@ -219,3 +253,76 @@ pub enum SGXQuoteVerifyResult {
     ConfigAndSwHardeningNeeded = 0x0000_A008,
     Max = 0x0000_A0FF,
 }
+
+impl SGXQuoteVerifyResult {
+    /// Returns `true` if the quote must be rejected regardless of the enclave identity policy.
+    ///
+    /// [`SGXQuoteVerifyResult::Ok`] and the results marked as *Not terminal* return `false`.
+    pub fn is_terminal(&self) -> bool {
+        !matches!(
+            self,
+            SGXQuoteVerifyResult::Ok
+                | SGXQuoteVerifyResult::ConfigNeeded
+                | SGXQuoteVerifyResult::OutOfDate
+                | SGXQuoteVerifyResult::OutOfDateConfigNeeded
+                | SGXQuoteVerifyResult::SwHardeningNeeded
+                | SGXQuoteVerifyResult::ConfigAndSwHardeningNeeded
+        )
+    }
+}
+
+/// TCB status names used by Intel PCS collateral and Intel Trust Authority
+const TCB_STATUS_NAMES: [(SGXQuoteVerifyResult, &str); 10] = [
+    (SGXQuoteVerifyResult::Ok, "UpToDate"),
+    (SGXQuoteVerifyResult::ConfigNeeded, "ConfigurationNeeded"),
+    (SGXQuoteVerifyResult::OutOfDate, "OutOfDate"),
+    (
+        SGXQuoteVerifyResult::OutOfDateConfigNeeded,
+        "OutOfDateConfigurationNeeded",
+    ),
+    (SGXQuoteVerifyResult::InvalidSignature, "InvalidSignature"),
+    (SGXQuoteVerifyResult::Revoked, "Revoked"),
+    (SGXQuoteVerifyResult::Unspecified, "Unspecified"),
+    (SGXQuoteVerifyResult::SwHardeningNeeded, "SWHardeningNeeded"),
+    (
+        SGXQuoteVerifyResult::ConfigAndSwHardeningNeeded,
+        "ConfigurationAndSWHardeningNeeded",
+    ),
+    (SGXQuoteVerifyResult::Max, "Max"),
+];
+
+impl SGXQuoteVerifyResult {
+    /// TCB status name, e.g. `UpToDate` for [`SGXQuoteVerifyResult::Ok`]
+    pub fn tcb_status(&self) -> &'static str {
+        TCB_STATUS_NAMES
+            .iter()
+            .find(|(result, _)| result == self)
+            .map(|(_, name)| *name)
+            .unwrap_or("Unspecified")
+    }
+
+    /// Result of the TCB status name, see [`SGXQuoteVerifyResult::tcb_status`]
+    pub fn from_tcb_status(name: &str) -> Option<Self> {
+        TCB_STATUS_NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(result, _)| *result)
+    }
+}
+
+impl From<u32> for SGXQuoteVerifyResult {
+    /// Unknown values are mapped to [`SGXQuoteVerifyResult::Unspecified`]
+    fn from(value: u32) -> Self {
+        match value {
+            0x0000_0000 => SGXQuoteVerifyResult::Ok,
+            0x0000_A001 => SGXQuoteVerifyResult::ConfigNeeded,
+            0x0000_A002 => SGXQuoteVerifyResult::OutOfDate,
+            0x0000_A003 => SGXQuoteVerifyResult::OutOfDateConfigNeeded,
+            0x0000_A004 => SGXQuoteVerifyResult::InvalidSignature,
+            0x0000_A005 => SGXQuoteVerifyResult::Revoked,
+            0x0000_A007 => SGXQuoteVerifyResult::SwHardeningNeeded,
+            0x0000_A008 => SGXQuoteVerifyResult::ConfigAndSwHardeningNeeded,
+            _ => SGXQuoteVerifyResult::Unspecified,
+        }
+    }
+}
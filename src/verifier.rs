@@ -0,0 +1,37 @@
+use crate::{CollateralVersions, SGXError, SGXQuote, SGXQuoteVerifyResult};
+
+/// Backend which checks the signature and TCB status of an [`SGXQuote`].
+///
+/// Used by the consumers that need to verify quotes without knowing where the
+/// verification actually happens (e.g. RA-TLS certificate verifiers).
+pub trait QuoteVerifier: Send + Sync {
+    fn verify_quote(&self, quote: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError>;
+
+    /// [`verify_quote`](Self::verify_quote) with the versions of the collateral used, which are
+    /// empty if the backend doesn't report them
+    fn verify_quote_with_versions(
+        &self,
+        quote: &SGXQuote,
+    ) -> Result<(SGXQuoteVerifyResult, CollateralVersions), SGXError> {
+        Ok((self.verify_quote(quote)?, CollateralVersions::default()))
+    }
+}
+
+/// Verifies quotes with the Occlum `/dev/sgx` ioctls, same as [`SGXQuote::verify_result`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoctlVerifier;
+
+impl QuoteVerifier for IoctlVerifier {
+    fn verify_quote(&self, quote: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> {
+        quote.verify_result()
+    }
+}
+
+impl<F> QuoteVerifier for F
+where
+    F: Fn(&SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> + Send + Sync,
+{
+    fn verify_quote(&self, quote: &SGXQuote) -> Result<SGXQuoteVerifyResult, SGXError> {
+        self(quote)
+    }
+}
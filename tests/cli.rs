@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const QUOTE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/quote.raw");
+
+fn occlum_sgx(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_occlum-sgx"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn write_collateral(path: &Path) {
+    let fields = [
+        "pck_crl_issuer_chain",
+        "root_ca_crl",
+        "pck_crl",
+        "tcb_info_issuer_chain",
+        "tcb_info",
+        "qe_identity_issuer_chain",
+        "qe_identity",
+    ];
+    let mut collateral = serde_json::json!({
+        "major_version": 3,
+        "minor_version": 0,
+        "tee_type": 0,
+    });
+    for field in fields {
+        collateral[field] = "".into();
+    }
+    fs::write(path, collateral.to_string()).unwrap();
+}
+
+#[test]
+fn inspect() {
+    let output = occlum_sgx(&["inspect", QUOTE]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output)
+        .contains("9c90fd81f6e9fe64b46b14f0623523a52d6a5678482988c408f6adffe6301e2c"));
+
+    let output = occlum_sgx(&["inspect", QUOTE, "--format", "json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json["header"]["version"], 3);
+}
+
+#[test]
+fn verify_dcap() {
+    let output = occlum_sgx(&["verify", QUOTE, "--backend", "dcap"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("requires --collateral or --pccs-url"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let collateral = dir.path().join("collateral.json");
+    write_collateral(&collateral);
+    let root = dir.path().join("root.pem");
+    let certified = rcgen::generate_simple_self_signed(vec!["root".to_string()]).unwrap();
+    fs::write(&root, certified.cert.pem()).unwrap();
+
+    // The PCK chain of the fixture does not chain to another root
+    let output = occlum_sgx(&[
+        "verify",
+        QUOTE,
+        "--backend",
+        "dcap",
+        "--collateral",
+        collateral.to_str().unwrap(),
+        "--trusted-root",
+        root.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stdout(&output).contains("InvalidSignature"));
+
+    let output = occlum_sgx(&[
+        "verify",
+        QUOTE,
+        "--backend",
+        "ioctl",
+        "--pccs-url",
+        "https://localhost:8081",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("not supported by the ioctl backend"));
+}
+
+#[test]
+fn extract_certs() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = occlum_sgx(&[
+        "extract-certs",
+        QUOTE,
+        "--output-dir",
+        dir.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    for name in ["pck.pem", "intermediate.pem", "root.pem"] {
+        let pem = fs::read_to_string(dir.path().join(name)).unwrap();
+        assert!(pem.starts_with("-----BEGIN CERTIFICATE-----"));
+    }
+}